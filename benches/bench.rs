@@ -62,7 +62,11 @@ fn benchmark_file(c: &mut Criterion) {
 fn benchmark_write(c: &mut Criterion) {
     c.bench_function("write kitchen sink file", |b| {
         let parser = Parser::build(black_box(KITCHEN_SINK_BYTES)).unwrap();
-        let mut writer = Writer::new(WriterOptions { verbose: false });
+        let mut writer = Writer::new(WriterOptions {
+            verbose: false,
+            faithful: false,
+            undocumented: false,
+        });
         let instructions = &parser.collect::<Vec<_>>();
         b.iter(move || {
             for instruction in instructions {
@@ -73,7 +77,11 @@ fn benchmark_write(c: &mut Criterion) {
 
     c.bench_function("write evil file", |b| {
         let parser = Parser::build(black_box(EVIL_FILE_BYTES)).unwrap();
-        let mut writer = Writer::new(WriterOptions { verbose: false });
+        let mut writer = Writer::new(WriterOptions {
+            verbose: false,
+            faithful: false,
+            undocumented: false,
+        });
         let instructions = &parser.collect::<Vec<_>>();
         b.iter(move || {
             for instruction in instructions {
@@ -84,7 +92,11 @@ fn benchmark_write(c: &mut Criterion) {
 
     c.bench_function("write mov file (1000 elements)", |b| {
         let parser = Parser::build(black_box(MOV_FILE_BYTES)).unwrap();
-        let mut writer = Writer::new(WriterOptions { verbose: false });
+        let mut writer = Writer::new(WriterOptions {
+            verbose: false,
+            faithful: false,
+            undocumented: false,
+        });
         let instructions = &parser.collect::<Vec<_>>();
         b.iter(move || {
             for instruction in instructions {
@@ -0,0 +1,8 @@
+pub mod cfg;
+pub mod constants;
+pub mod coverage;
+pub mod functions;
+pub mod interrupts;
+pub mod labels;
+pub mod overlap;
+pub mod strings;
@@ -0,0 +1,433 @@
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
+
+use crate::{
+    instructions::instruction::ControlFlow,
+    parser::Parser,
+    writer::{Writer, WriterOptions},
+    Instruction,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EdgeKind {
+    /// Execution runs past the end of the block into the next one.
+    Fallthrough,
+    /// A jump, branch or loop to the target block.
+    Taken,
+    /// A call to the entry block of a function.
+    Call,
+    /// A jump or call whose target can not be determined statically.
+    Indirect,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Edge {
+    pub from: usize,
+    /// Index of the target block, or `None` if it is not known.
+    pub to: Option<usize>,
+    pub kind: EdgeKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BasicBlock {
+    /// Byte offset of the first instruction.
+    pub start: usize,
+    /// Byte offset one past the last instruction.
+    pub end: usize,
+    /// Range of the block's instructions in `ControlFlowGraph::instructions`.
+    pub instructions: std::ops::Range<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub instructions: Vec<(usize, Instruction)>,
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+    names: HashMap<usize, String>,
+    origin: usize,
+}
+
+fn ends_block(instruction: &Instruction) -> bool {
+    !matches!(
+        instruction.control_flow(),
+        ControlFlow::Next | ControlFlow::Call(_) | ControlFlow::IndirectCall
+    )
+}
+
+impl ControlFlowGraph {
+    pub fn build(bytes: &[u8]) -> Self {
+        let instructions: Vec<(usize, Instruction)> = match Parser::build(bytes) {
+            Ok(parser) => parser.with_offsets().collect(),
+            Err(_) => Vec::new(),
+        };
+
+        Self::from_instructions(instructions)
+    }
+
+    pub fn from_instructions(instructions: Vec<(usize, Instruction)>) -> Self {
+        Self::with_leaders(instructions, &[])
+    }
+
+    /// Builds the graph, additionally starting a block at every offset in
+    /// `extra_leaders` that is an instruction boundary.
+    pub fn with_leaders(instructions: Vec<(usize, Instruction)>, extra_leaders: &[usize]) -> Self {
+        let boundaries: HashSet<usize> = instructions.iter().map(|(offset, _)| *offset).collect();
+        let mut leaders: BTreeSet<usize> = extra_leaders
+            .iter()
+            .copied()
+            .filter(|offset| boundaries.contains(offset))
+            .collect();
+
+        if let Some((offset, _)) = instructions.first() {
+            leaders.insert(*offset);
+        }
+
+        for (offset, instruction) in &instructions {
+            if let Some(target) = instruction.branch_target(*offset) {
+                if target >= 0 && boundaries.contains(&(target as usize)) {
+                    leaders.insert(target as usize);
+                }
+            }
+
+            if ends_block(instruction) {
+                let next = offset + instruction.length as usize;
+                if boundaries.contains(&next) {
+                    leaders.insert(next);
+                }
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = Vec::with_capacity(leaders.len());
+
+        for (index, (offset, instruction)) in instructions.iter().enumerate() {
+            let end = offset + instruction.length as usize;
+
+            match blocks.last_mut() {
+                Some(block) if !leaders.contains(offset) => {
+                    block.end = end;
+                    block.instructions.end = index + 1;
+                }
+                _ => blocks.push(BasicBlock {
+                    start: *offset,
+                    end,
+                    instructions: index..index + 1,
+                }),
+            }
+        }
+
+        let mut graph = Self {
+            instructions,
+            blocks,
+            edges: Vec::new(),
+            names: HashMap::new(),
+            origin: 0,
+        };
+
+        graph.edges = graph.collect_edges();
+        graph
+    }
+
+    fn collect_edges(&self) -> Vec<Edge> {
+        let mut edges = Vec::new();
+
+        for (block_index, block) in self.blocks.iter().enumerate() {
+            let next_block = self.block_at(block.end);
+
+            for (offset, instruction) in &self.instructions[block.instructions.clone()] {
+                let target = instruction
+                    .branch_target(*offset)
+                    .and_then(|target| usize::try_from(target).ok())
+                    .and_then(|target| self.block_at(target));
+                let mut push = |to, kind| {
+                    edges.push(Edge {
+                        from: block_index,
+                        to,
+                        kind,
+                    })
+                };
+
+                match instruction.control_flow() {
+                    ControlFlow::Next => {}
+                    ControlFlow::Call(_) => push(target, EdgeKind::Call),
+                    ControlFlow::IndirectCall | ControlFlow::IndirectJump => {
+                        push(None, EdgeKind::Indirect)
+                    }
+                    ControlFlow::Jump(_) => push(target, EdgeKind::Taken),
+                    ControlFlow::Branch(_) => {
+                        push(target, EdgeKind::Taken);
+                        push(next_block, EdgeKind::Fallthrough);
+                    }
+                    ControlFlow::Return => {}
+                }
+            }
+
+            let last = &self.instructions[block.instructions.end - 1].1;
+
+            if !ends_block(last) && next_block.is_some() {
+                edges.push(Edge {
+                    from: block_index,
+                    to: next_block,
+                    kind: EdgeKind::Fallthrough,
+                });
+            }
+        }
+
+        edges
+    }
+
+    /// Returns the index of the block starting at the given byte offset.
+    pub fn block_at(&self, offset: usize) -> Option<usize> {
+        self.blocks
+            .binary_search_by_key(&offset, |block| block.start)
+            .ok()
+    }
+
+    /// Returns the index of the block containing the given byte offset.
+    pub fn block_containing(&self, offset: usize) -> Option<usize> {
+        let index = self
+            .blocks
+            .partition_point(|block| block.start <= offset)
+            .checked_sub(1)?;
+
+        (offset < self.blocks[index].end).then_some(index)
+    }
+
+    pub fn successors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+        // Edges are collected block by block, so they are sorted by `from`
+        let start = self.edges.partition_point(|edge| edge.from < block);
+        let end = self.edges.partition_point(|edge| edge.from <= block);
+
+        self.edges[start..end].iter()
+    }
+
+    pub fn predecessors(&self, block: usize) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |edge| edge.to == Some(block))
+    }
+
+    /// Returns the blocks reachable from `entry` without following calls,
+    /// ordered by address. Blocks for which `is_boundary` returns true are
+    /// not entered unless they are the entry itself.
+    pub fn reachable_blocks(
+        &self,
+        entry: usize,
+        is_boundary: impl Fn(usize) -> bool,
+    ) -> Vec<usize> {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![entry];
+
+        while let Some(block) = stack.pop() {
+            if !visited.insert(block) {
+                continue;
+            }
+
+            for edge in self.successors(block) {
+                if let (Some(to), EdgeKind::Fallthrough | EdgeKind::Taken) = (edge.to, edge.kind) {
+                    if to == entry || !is_boundary(to) {
+                        stack.push(to);
+                    }
+                }
+            }
+        }
+
+        visited.into_iter().collect()
+    }
+
+    /// Returns the blocks that are the target of a call.
+    pub fn call_targets(&self) -> BTreeSet<usize> {
+        self.edges
+            .iter()
+            .filter(|edge| edge.kind == EdgeKind::Call)
+            .filter_map(|edge| edge.to)
+            .collect()
+    }
+
+    /// Sets the address the input is loaded at, which blocks without a name
+    /// are named after.
+    pub fn set_origin(&mut self, origin: usize) {
+        self.origin = origin;
+    }
+
+    pub fn set_block_name(&mut self, block: usize, name: &str) {
+        self.names.insert(block, name.to_string());
+    }
+
+    pub fn block_name(&self, block: usize) -> String {
+        match self.names.get(&block) {
+            Some(name) => name.clone(),
+            None => format!("loc_{:04x}", self.origin + self.blocks[block].start),
+        }
+    }
+
+    /// Renders the disassembly of a block, jump targets named by their block.
+    pub fn block_text(&self, block: usize) -> String {
+        let mut writer = Writer::new(WriterOptions {
+            verbose: false,
+            faithful: false,
+            undocumented: false,
+        });
+
+        for index in 0..self.blocks.len() {
+            writer.add_label(self.blocks[index].start, &self.block_name(index));
+        }
+
+        let block = &self.blocks[block];
+        writer.set_origin(self.origin);
+        writer.set_position(block.start);
+
+        for (_, instruction) in &self.instructions[block.instructions.clone()] {
+            instruction.write(&mut writer);
+        }
+
+        String::from_utf8_lossy(writer.as_slice()).into_owned()
+    }
+
+    /// Writes the given blocks and their outgoing edges as a Graphviz DOT
+    /// digraph.
+    pub fn write_dot(&self, name: &str, blocks: &[usize], out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "digraph {} {{", dot_id(name))?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        for &block in blocks {
+            writeln!(
+                out,
+                "    {} [label=\"{}\"];",
+                dot_id(&self.block_name(block)),
+                escape_dot_label(&self.block_text(block))
+            )?;
+        }
+
+        for &block in blocks {
+            for (index, edge) in self.successors(block).enumerate() {
+                let name = self.block_name(block);
+                let from = dot_id(&name);
+                let to = match edge.to {
+                    Some(to) => dot_id(&self.block_name(to)),
+                    None => {
+                        let node = dot_id(&format!("{}_indirect_{}", name, index));
+                        writeln!(out, "    {} [label=\"?\", shape=plaintext];", node)?;
+                        node
+                    }
+                };
+                let attributes = match edge.kind {
+                    EdgeKind::Fallthrough => "style=dashed",
+                    EdgeKind::Taken => "color=darkgreen",
+                    EdgeKind::Call => "color=blue, style=dotted, label=\"call\"",
+                    EdgeKind::Indirect => "color=red, style=dotted",
+                };
+
+                writeln!(out, "    {} -> {} [{}];", from, to, attributes)?;
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+/// Quotes a name as a DOT ID, which may contain characters like `.` and `@`
+/// that symbol names can have.
+pub fn dot_id(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\\\""))
+}
+
+fn escape_dot_label(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for char in text.chars() {
+        match char {
+            '"' | '\\' | '{' | '}' | '<' | '>' | '|' => {
+                escaped.push('\\');
+                escaped.push(char);
+            }
+            '\n' => escaped.push_str("\\l"),
+            _ => escaped.push(char),
+        }
+    }
+
+    escaped
+}
+
+#[test]
+fn test_cfg_splits_blocks_at_branches() {
+    // 0: mov ax, bx
+    // 2: je 6
+    // 4: add ax, bx
+    // 6: sub ax, bx
+    // 8: jmp 0
+    // 10: ret
+    let bytes = [
+        0b1000_1001,
+        0b1101_1000,
+        0b0111_0100,
+        0b0000_0010,
+        0b0000_0001,
+        0b1101_1000,
+        0b0010_1001,
+        0b1101_1000,
+        0b1110_1011,
+        0b1111_0110,
+        0b1100_0011,
+    ];
+    let graph = ControlFlowGraph::build(&bytes);
+    let starts: Vec<usize> = graph.blocks.iter().map(|block| block.start).collect();
+
+    assert_eq!(starts, vec![0, 4, 6, 10]);
+    assert_eq!(
+        graph.successors(0).copied().collect::<Vec<_>>(),
+        vec![
+            Edge {
+                from: 0,
+                to: Some(2),
+                kind: EdgeKind::Taken
+            },
+            Edge {
+                from: 0,
+                to: Some(1),
+                kind: EdgeKind::Fallthrough
+            }
+        ]
+    );
+    assert_eq!(graph.successors(2).count(), 1);
+    assert_eq!(graph.successors(3).count(), 0);
+    assert_eq!(graph.reachable_blocks(0, |_| false), vec![0, 1, 2]);
+    assert_eq!(graph.reachable_blocks(1, |block| block == 2), vec![1]);
+    assert_eq!(graph.block_text(2), "loc_0006:\nsub ax, bx\njmp loc_0000\n");
+}
+
+#[test]
+fn test_cfg_call_edges() {
+    // 0: call 4
+    // 3: ret
+    // 4: call [bx]
+    // 6: ret
+    let bytes = [
+        0b1110_1000,
+        0b0000_0001,
+        0b0000_0000,
+        0b1100_0011,
+        0b1111_1111,
+        0b0001_0111,
+        0b1100_0011,
+    ];
+    let graph = ControlFlowGraph::build(&bytes);
+
+    assert_eq!(
+        graph.call_targets().into_iter().collect::<Vec<_>>(),
+        vec![1]
+    );
+    assert_eq!(graph.reachable_blocks(0, |_| false), vec![0]);
+    assert_eq!(graph.successors(1).next().unwrap().kind, EdgeKind::Indirect);
+
+    let mut graph = graph;
+    graph.set_block_name(1, "sub_0004");
+
+    let mut dot = Vec::new();
+    graph.write_dot("start", &[0], &mut dot).unwrap();
+    let dot = String::from_utf8(dot).unwrap();
+
+    assert!(dot.starts_with("digraph \"start\" {"));
+    assert!(
+        dot.contains("\"loc_0000\" -> \"sub_0004\" [color=blue, style=dotted, label=\"call\"];")
+    );
+    assert_eq!(dot_id("print@str.1"), "\"print@str.1\"");
+    assert_eq!(dot_id("a\"b"), "\"a\\\"b\"");
+}
@@ -0,0 +1,254 @@
+use crate::{
+    instructions::{
+        common::{instruction_flags::has_word_flag, register, InstRegister, RM},
+        opcode::Opcode,
+    },
+    Instruction,
+};
+
+/// Values of the general purpose registers that are known to be constant,
+/// tracked byte by byte so that `mov ah, 9` is known without AL.
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub struct RegisterValues {
+    bytes: [Option<u8>; 16],
+}
+
+/// Returns the register written by the instruction's destination operand,
+/// if it is a general purpose register.
+fn destination_register(instruction: &Instruction) -> Option<u8> {
+    let destination = match instruction.input[0] {
+        0x04 | 0x05 | 0x0c | 0x0d | 0x14 | 0x15 | 0x1c | 0x1d | 0x24 | 0x25 | 0x2c | 0x2d
+        | 0x34 | 0x35 => return Some(register::AX),
+        0x40..=0x4f | 0x58..=0x5f | 0xb0..=0xbf => RM::Reg(instruction.register),
+        0x80..=0x83 | 0x8f | 0xc6 | 0xc7 | 0xd0..=0xd3 | 0xf6 | 0xf7 | 0xfe | 0xff => {
+            instruction.data_fields.rm
+        }
+        _ => instruction.get_destination(),
+    };
+
+    match destination {
+        RM::Reg(InstRegister::Reg(register)) => Some(register),
+        _ => None,
+    }
+}
+
+impl RegisterValues {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn byte_index(register: u8, word: bool) -> usize {
+        if word || register < 4 {
+            register as usize * 2
+        } else {
+            (register as usize - 4) * 2 + 1
+        }
+    }
+
+    /// Returns the value of a byte register, `register` numbered like the
+    /// byte register encoding (AL, CL, DL, BL, AH, CH, DH, BH).
+    pub fn byte(&self, register: u8) -> Option<u8> {
+        self.bytes[Self::byte_index(register, false)]
+    }
+
+    pub fn word(&self, register: u8) -> Option<u16> {
+        let index = Self::byte_index(register, true);
+        let low = self.bytes[index]?;
+        let high = self.bytes[index + 1]?;
+
+        Some((high as u16) << 8 | low as u16)
+    }
+
+    pub fn set(&mut self, register: u8, word: bool, value: Option<u16>) {
+        let index = Self::byte_index(register, word);
+
+        self.bytes[index] = value.map(|value| value as u8);
+
+        if word {
+            self.bytes[index + 1] = value.map(|value| (value >> 8) as u8);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+
+    /// Updates the known values with the effect of executing the instruction.
+    pub fn update(&mut self, instruction: &Instruction) {
+        let word = has_word_flag(instruction.flags);
+
+        match instruction.opcode {
+            Opcode::MOV if (0xb0..=0xbf).contains(&instruction.input[0]) => {
+                let register: u8 = instruction.register.into();
+                self.set(register, word, Some(instruction.data));
+                return;
+            }
+            Opcode::XOR | Opcode::SUB
+                if matches!(instruction.input[0], 0x28..=0x2b | 0x30..=0x33)
+                    && instruction.get_source() == instruction.get_destination() =>
+            {
+                if let Some(register) = destination_register(instruction) {
+                    self.set(register, word, Some(0));
+                    return;
+                }
+            }
+            _ => {}
+        }
+
+        match instruction.opcode {
+            Opcode::CMP
+            | Opcode::TEST
+            | Opcode::PUSH
+            | Opcode::PUSHF
+            | Opcode::OUT
+            | Opcode::NOP
+            | Opcode::SAHF
+            | Opcode::STOSB
+            | Opcode::STOSW
+            | Opcode::LOCK
+            | Opcode::REP
+            | Opcode::SEGMENT
+            | Opcode::CLC
+            | Opcode::CMC
+            | Opcode::STC
+            | Opcode::CLD
+            | Opcode::STD
+            | Opcode::CLI
+            | Opcode::STI
+            | Opcode::HLT
+            | Opcode::WAIT
+            | Opcode::JA
+            | Opcode::JAE
+            | Opcode::JB
+            | Opcode::JBE
+            | Opcode::JC
+            | Opcode::JCXZ
+            | Opcode::JE
+            | Opcode::JG
+            | Opcode::JGE
+            | Opcode::JL
+            | Opcode::JLE
+            | Opcode::JMP
+            | Opcode::JNC
+            | Opcode::JNE
+            | Opcode::JNO
+            | Opcode::JNP
+            | Opcode::JNS
+            | Opcode::JO
+            | Opcode::JP
+            | Opcode::JS => {}
+            Opcode::MOV
+            | Opcode::ADD
+            | Opcode::ADC
+            | Opcode::SUB
+            | Opcode::SBB
+            | Opcode::AND
+            | Opcode::OR
+            | Opcode::XOR
+            | Opcode::INC
+            | Opcode::DEC
+            | Opcode::NEG
+            | Opcode::NOT
+            | Opcode::ROL
+            | Opcode::ROR
+            | Opcode::RCL
+            | Opcode::RCR
+            | Opcode::SAL
+            | Opcode::SHR
+            | Opcode::SAR
+            | Opcode::POP
+            | Opcode::LEA => {
+                if let Some(register) = destination_register(instruction) {
+                    self.set(register, word, None);
+                }
+            }
+            Opcode::LDS | Opcode::LES => {
+                if let Some(register) = destination_register(instruction) {
+                    self.set(register, true, None);
+                }
+            }
+            Opcode::CBW | Opcode::LAHF => self.set(4, false, None),
+            Opcode::XLAT | Opcode::IN | Opcode::LODSB | Opcode::LODSW => {
+                self.set(register::AX, true, None)
+            }
+            Opcode::CWD => self.set(register::DX, true, None),
+            Opcode::MUL
+            | Opcode::IMUL
+            | Opcode::DIV
+            | Opcode::IDIV
+            | Opcode::AAA
+            | Opcode::AAS
+            | Opcode::AAM
+            | Opcode::AAD
+            | Opcode::DAA
+            | Opcode::DAS => {
+                self.set(register::AX, true, None);
+                self.set(register::DX, true, None);
+            }
+            Opcode::MOVSB
+            | Opcode::MOVSW
+            | Opcode::CMPSB
+            | Opcode::CMPSW
+            | Opcode::SCASB
+            | Opcode::SCASW => {}
+            // Calls, interrupts, loops, exchanges and anything unexpected
+            _ => self.clear(),
+        }
+
+        if matches!(
+            instruction.opcode,
+            Opcode::MOVSB
+                | Opcode::MOVSW
+                | Opcode::CMPSB
+                | Opcode::CMPSW
+                | Opcode::SCASB
+                | Opcode::SCASW
+                | Opcode::LODSB
+                | Opcode::LODSW
+                | Opcode::STOSB
+                | Opcode::STOSW
+        ) {
+            self.set(register::SI, true, None);
+            self.set(register::DI, true, None);
+        }
+
+        if instruction.opcode == Opcode::REP {
+            self.set(register::CX, true, None);
+        }
+    }
+}
+
+#[test]
+fn test_register_values() {
+    let mut values = RegisterValues::new();
+    let instructions = [
+        // mov ax, 0x4c00
+        &[0xb8, 0x00, 0x4c][..],
+        // mov dl, 0x41
+        &[0xb2, 0x41],
+        // mov dh, al
+        &[0x88, 0xc6],
+        // xor bx, bx
+        &[0x31, 0xdb],
+        // inc bl
+        &[0xfe, 0xc3],
+        // cmp ax, 3
+        &[0x3d, 0x03, 0x00],
+    ];
+
+    for bytes in instructions {
+        values.update(&Instruction::parse(bytes).unwrap());
+    }
+
+    assert_eq!(values.word(register::AX), Some(0x4c00));
+    assert_eq!(values.byte(4), Some(0x4c));
+    assert_eq!(values.byte(register::DX), Some(0x41));
+    assert_eq!(values.word(register::DX), None);
+    assert_eq!(values.byte(register::BX), None);
+    assert_eq!(values.byte(7), Some(0));
+
+    // int 0x21
+    values.update(&Instruction::parse(&[0xcd, 0x21]).unwrap());
+
+    assert_eq!(values, RegisterValues::new());
+}
@@ -0,0 +1,168 @@
+use std::ops::Range;
+
+use crate::{instructions::opcode::Opcode, parser::Item, writer::Writer};
+
+/// An instruction with its prefixes and how many times it was executed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub range: Range<usize>,
+    /// Offset of the instruction after its prefixes.
+    pub offset: usize,
+    pub hits: u64,
+}
+
+fn is_prefix(opcode: Opcode) -> bool {
+    matches!(opcode, Opcode::SEGMENT | Opcode::REP | Opcode::LOCK)
+}
+
+/// Returns the lines of the instructions with the hits of each byte offset,
+/// counting those of the prefixes for the instruction after them.
+pub fn lines(items: &[(usize, Item)], hits: &[u64]) -> Vec<Line> {
+    let hits_at = |offset: usize| hits.get(offset).copied().unwrap_or(0);
+    let mut lines = Vec::new();
+    let mut prefixes: Option<Line> = None;
+
+    for &(offset, item) in items {
+        let Item::Instruction(instruction) = item else {
+            prefixes = None;
+            continue;
+        };
+        let end = offset + instruction.length as usize;
+        let line = match prefixes.take() {
+            Some(line) if line.range.end == offset => Line {
+                range: line.range.start..end,
+                offset,
+                hits: line.hits + hits_at(offset),
+            },
+            _ => Line {
+                range: offset..end,
+                offset,
+                hits: hits_at(offset),
+            },
+        };
+
+        match is_prefix(instruction.opcode) {
+            true => prefixes = Some(line),
+            false => lines.push(line),
+        }
+    }
+
+    lines
+}
+
+/// Adds `hits: N` to the end of the comment of each instruction.
+pub fn annotate(lines: &[Line], writer: &mut Writer) {
+    for line in lines {
+        writer.append_comment(line.offset, &format!("hits: {}", line.hits));
+    }
+}
+
+/// Returns the ranges of instructions that were never executed, merging
+/// the ones next to each other.
+pub fn unexecuted(lines: &[Line]) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+
+    for line in lines.iter().filter(|line| line.hits == 0) {
+        match ranges.last_mut() {
+            Some(range) if range.end == line.range.start => range.end = line.range.end,
+            _ => ranges.push(line.range.clone()),
+        }
+    }
+
+    ranges
+}
+
+/// Returns comment lines with the number of instructions executed and the
+/// ranges that were not, as addresses from the origin.
+pub fn summary(lines: &[Line], origin: usize) -> String {
+    let executed = lines.iter().filter(|line| line.hits > 0).count();
+    let mut summary = format!(
+        "; coverage: {} of {} instructions executed\n",
+        executed,
+        lines.len()
+    );
+
+    for range in unexecuted(lines) {
+        let start = origin + range.start;
+
+        summary.push_str(&match range.len() {
+            1 => format!("; never executed: {:#06x} (1 byte)\n", start),
+            length => format!(
+                "; never executed: {:#06x}-{:#06x} ({} bytes)\n",
+                start,
+                start + length - 1,
+                length
+            ),
+        });
+    }
+
+    summary
+}
+
+/// Returns the hits of a line of the annotated disassembly, if it has them.
+fn line_hits(line: &str) -> Option<u64> {
+    let (_, comment) = line.split_once("; ")?;
+    let (_, hits) = comment.rsplit_once("hits: ")?;
+
+    hits.parse().ok()
+}
+
+/// Returns an lcov tracefile of the disassembly annotated with
+/// `annotate`, whose line numbers it counts, named `source`.
+pub fn lcov(source: &str, disassembly: &str) -> String {
+    let mut lcov = format!("TN:\nSF:{}\n", source);
+    let mut found = 0;
+    let mut hit = 0;
+
+    for (number, line) in disassembly.lines().enumerate() {
+        let Some(hits) = line_hits(line) else {
+            continue;
+        };
+
+        found += 1;
+
+        if hits > 0 {
+            hit += 1;
+        }
+
+        lcov.push_str(&format!("DA:{},{}\n", number + 1, hits));
+    }
+
+    lcov.push_str(&format!("LF:{}\nLH:{}\nend_of_record\n", found, hit));
+    lcov
+}
+
+#[test]
+fn test_coverage() {
+    use crate::parser::parse_with_data;
+
+    // mov cx, 2; rep movsb; jmp 0; nop; nop; db 0xd6
+    let bytes = [0xb9, 0x02, 0x00, 0xf3, 0xa4, 0xeb, 0xf9, 0x90, 0x90, 0xd6];
+    let items = parse_with_data(&bytes, &[], false);
+    let mut hits = vec![0; bytes.len()];
+
+    hits[0] = 1;
+    hits[3] = 2;
+
+    let lines = lines(&items, &hits);
+
+    assert_eq!(
+        lines.iter().map(|line| line.hits).collect::<Vec<_>>(),
+        [1, 2, 0, 0, 0]
+    );
+    assert_eq!(lines[1].range, 3..5);
+    assert_eq!(lines[1].offset, 4);
+    assert_eq!(unexecuted(&lines), vec![5..9]);
+    assert_eq!(
+        summary(&lines, 0x100),
+        "; coverage: 2 of 5 instructions executed\n\
+         ; never executed: 0x0105-0x0108 (4 bytes)\n"
+    );
+
+    let disassembly = "bits 16\nmov cx, 0x0002 ; hits: 1\nrep movsb ; hits: 2\nnop ; hits: 0\n";
+
+    assert_eq!(
+        lcov("a.asm", disassembly),
+        "TN:\nSF:a.asm\nDA:2,1\nDA:3,2\nDA:4,0\nLF:3\nLH:2\nend_of_record\n"
+    );
+}
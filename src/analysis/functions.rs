@@ -0,0 +1,311 @@
+use std::collections::BTreeSet;
+use std::io::{self, Write};
+
+use crate::{
+    instructions::{
+        common::{register, InstRegister, RM},
+        opcode::Opcode,
+    },
+    parser::Parser,
+    symbols::SymbolMap,
+    writer::Writer,
+    Instruction,
+};
+
+use super::cfg::{dot_id, ControlFlowGraph, EdgeKind};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// Byte offset of the first instruction.
+    pub offset: usize,
+    /// Index of the entry block in the control flow graph.
+    pub entry: usize,
+    /// Blocks reachable from the entry without calls, ordered by address.
+    pub blocks: Vec<usize>,
+    /// Indices of the functions called by this function.
+    pub callees: Vec<usize>,
+    /// Indices of the functions calling this function.
+    pub callers: Vec<usize>,
+    /// Whether the function starts with `push bp; mov bp, sp`.
+    pub has_prologue: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CallGraph {
+    pub cfg: ControlFlowGraph,
+    /// Functions ordered by address.
+    pub functions: Vec<Function>,
+}
+
+fn is_push_bp(instruction: &Instruction) -> bool {
+    instruction.opcode == Opcode::PUSH
+        && instruction.length == 1
+        && instruction.register == InstRegister::Reg(register::BP)
+}
+
+fn is_mov_bp_sp(instruction: &Instruction) -> bool {
+    instruction.opcode == Opcode::MOV
+        && instruction.get_destination() == RM::Reg(InstRegister::Reg(register::BP))
+        && instruction.get_source() == RM::Reg(InstRegister::Reg(register::SP))
+}
+
+/// Returns the offsets of every `push bp; mov bp, sp` sequence.
+pub fn find_prologues(instructions: &[(usize, Instruction)]) -> Vec<usize> {
+    instructions
+        .windows(2)
+        .filter(|pair| is_push_bp(&pair[0].1) && is_mov_bp_sp(&pair[1].1))
+        .map(|pair| pair[0].0)
+        .collect()
+}
+
+impl CallGraph {
+    pub fn build(bytes: &[u8]) -> Self {
+        let instructions: Vec<(usize, Instruction)> = match Parser::build(bytes) {
+            Ok(parser) => parser.with_offsets().collect(),
+            Err(_) => Vec::new(),
+        };
+
+        Self::from_instructions(instructions)
+    }
+
+    pub fn from_instructions(instructions: Vec<(usize, Instruction)>) -> Self {
+        Self::from_instructions_at(instructions, 0)
+    }
+
+    /// Builds the graph of instructions loaded at the given address, naming
+    /// the functions and blocks after their addresses.
+    pub fn from_instructions_at(instructions: Vec<(usize, Instruction)>, origin: usize) -> Self {
+        let prologues = find_prologues(&instructions);
+        let mut cfg = ControlFlowGraph::with_leaders(instructions, &prologues);
+
+        cfg.set_origin(origin);
+
+        let mut entries = cfg.call_targets();
+        entries.extend(prologues.iter().filter_map(|&offset| cfg.block_at(offset)));
+
+        if !cfg.blocks.is_empty() {
+            entries.insert(0);
+        }
+
+        let entries: Vec<usize> = entries.into_iter().collect();
+        let mut functions: Vec<Function> = entries
+            .iter()
+            .map(|&entry| {
+                let offset = cfg.blocks[entry].start;
+                let name = if entry == 0 {
+                    "start".to_string()
+                } else {
+                    format!("sub_{:04x}", origin + offset)
+                };
+
+                Function {
+                    name,
+                    offset,
+                    entry,
+                    blocks: cfg
+                        .reachable_blocks(entry, |block| entries.binary_search(&block).is_ok()),
+                    callees: Vec::new(),
+                    callers: Vec::new(),
+                    has_prologue: prologues.contains(&offset),
+                }
+            })
+            .collect();
+
+        for caller in 0..functions.len() {
+            let mut callees = BTreeSet::new();
+
+            for &block in &functions[caller].blocks {
+                for edge in cfg.successors(block) {
+                    let callee = edge.to.and_then(|to| entries.binary_search(&to).ok());
+
+                    match (edge.kind, callee) {
+                        (EdgeKind::Call, Some(callee)) => {
+                            callees.insert(callee);
+                        }
+                        // Jumps to the entry of another function are tail calls
+                        (EdgeKind::Taken, Some(callee)) if callee != caller => {
+                            callees.insert(callee);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            for &callee in &callees {
+                functions[callee].callers.push(caller);
+            }
+
+            functions[caller].callees = callees.into_iter().collect();
+        }
+
+        for function in &functions {
+            cfg.set_block_name(function.entry, &function.name);
+        }
+
+        Self { cfg, functions }
+    }
+
+    /// Renames the functions and blocks that have a name in the symbol map.
+    pub fn apply_symbols(&mut self, symbols: &SymbolMap) {
+        for block in 0..self.cfg.blocks.len() {
+            if let Some(name) = symbols.name(self.cfg.blocks[block].start) {
+                self.cfg.set_block_name(block, name);
+            }
+        }
+
+        for function in &mut self.functions {
+            if let Some(name) = symbols.name(function.offset) {
+                function.name = name.to_string();
+            }
+        }
+    }
+
+    /// Returns the function whose entry is at the given byte offset.
+    pub fn function_at(&self, offset: usize) -> Option<&Function> {
+        self.functions
+            .binary_search_by_key(&offset, |function| function.offset)
+            .ok()
+            .map(|index| &self.functions[index])
+    }
+
+    fn names(&self, indices: &[usize]) -> String {
+        if indices.is_empty() {
+            return "-".to_string();
+        }
+
+        indices
+            .iter()
+            .map(|&index| self.functions[index].name.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Names function entries `sub_XXXX` and writes a header with their
+    /// callers before each of them.
+    pub fn annotate(&self, writer: &mut Writer) {
+        for function in &self.functions {
+            writer.add_label(function.offset, &function.name);
+            writer.add_header_comment(function.offset, &format!("function {}", function.name));
+
+            if !function.callers.is_empty() {
+                writer.add_header_comment(
+                    function.offset,
+                    &format!("called by: {}", self.names(&function.callers)),
+                );
+            }
+        }
+    }
+
+    /// Writes the callers and callees of every function.
+    pub fn write_text(&self, out: &mut impl Write) -> io::Result<()> {
+        for function in &self.functions {
+            writeln!(out, "{}", function.name)?;
+            writeln!(out, "    calls: {}", self.names(&function.callees))?;
+            writeln!(out, "    called by: {}", self.names(&function.callers))?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the call graph as a Graphviz DOT digraph.
+    pub fn write_dot(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "digraph calls {{")?;
+        writeln!(out, "    node [shape=box, fontname=\"monospace\"];")?;
+
+        for function in &self.functions {
+            writeln!(out, "    {};", dot_id(&function.name))?;
+        }
+
+        for function in &self.functions {
+            for &callee in &function.callees {
+                writeln!(
+                    out,
+                    "    {} -> {};",
+                    dot_id(&function.name),
+                    dot_id(&self.functions[callee].name)
+                )?;
+            }
+        }
+
+        writeln!(out, "}}")
+    }
+
+    /// Writes the control flow graph of the given function as a Graphviz DOT
+    /// digraph.
+    pub fn write_function_dot(&self, function: &Function, out: &mut impl Write) -> io::Result<()> {
+        self.cfg.write_dot(&function.name, &function.blocks, out)
+    }
+}
+
+#[test]
+fn test_call_graph() {
+    // 0: call 6
+    // 3: call 10
+    // 6: push bp
+    // 7: mov bp, sp
+    // 9: ret
+    // 10: jmp 6
+    // 12: push bp
+    // 13: mov bp, sp
+    // 15: ret
+    let bytes = [
+        0b1110_1000,
+        0b0000_0011,
+        0b0000_0000,
+        0b1110_1000,
+        0b0000_0100,
+        0b0000_0000,
+        0b0101_0101,
+        0b1000_1001,
+        0b1110_0101,
+        0b1100_0011,
+        0b1110_1011,
+        0b1111_1010,
+        0b0101_0101,
+        0b1000_1011,
+        0b1110_1100,
+        0b1100_0011,
+    ];
+    let graph = CallGraph::build(&bytes);
+    let names: Vec<&str> = graph.functions.iter().map(|f| f.name.as_str()).collect();
+
+    assert_eq!(names, vec!["start", "sub_0006", "sub_000a", "sub_000c"]);
+    assert_eq!(graph.functions[0].callees, vec![1, 2]);
+    assert_eq!(graph.functions[1].callers, vec![0, 2]);
+    assert!(graph.functions[1].has_prologue);
+    assert!(!graph.functions[2].has_prologue);
+    assert!(graph.functions[3].has_prologue);
+    assert!(graph.functions[3].callers.is_empty());
+
+    let mut text = Vec::new();
+    graph.write_text(&mut text).unwrap();
+
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "start
+    calls: sub_0006, sub_000a
+    called by: -
+sub_0006
+    calls: -
+    called by: start, sub_000a
+sub_000a
+    calls: sub_0006
+    called by: start
+sub_000c
+    calls: -
+    called by: -
+"
+    );
+
+    // Names with dots are quoted
+    let mut graph = graph;
+    let mut dot = Vec::new();
+
+    graph.apply_symbols(&SymbolMap::parse("0x0006 print.str").unwrap());
+    graph.write_dot(&mut dot).unwrap();
+
+    let dot = String::from_utf8(dot).unwrap();
+
+    assert!(dot.contains("    \"start\" -> \"print.str\";\n"));
+}
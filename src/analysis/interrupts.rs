@@ -0,0 +1,263 @@
+use crate::{
+    instructions::{common::register, opcode::Opcode},
+    writer::Writer,
+};
+
+use super::{cfg::ControlFlowGraph, constants::RegisterValues};
+
+/// An `int` instruction with the values of AH, AL and DX that were loaded
+/// before it, if they are constant.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct InterruptCall {
+    pub offset: usize,
+    pub vector: u8,
+    pub ah: Option<u8>,
+    pub al: Option<u8>,
+    pub dx: Option<u16>,
+}
+
+struct Service {
+    vector: u8,
+    ah: u8,
+    /// Subfunction in AL, for services that need one.
+    al: Option<u8>,
+    description: &'static str,
+}
+
+const fn service(vector: u8, ah: u8, description: &'static str) -> Service {
+    Service {
+        vector,
+        ah,
+        al: None,
+        description,
+    }
+}
+
+const fn subservice(vector: u8, ax: u16, description: &'static str) -> Service {
+    Service {
+        vector,
+        ah: (ax >> 8) as u8,
+        al: Some(ax as u8),
+        description,
+    }
+}
+
+const VECTOR_NAMES: [(u8, &str); 8] = [
+    (0x10, "BIOS video"),
+    (0x13, "BIOS disk"),
+    (0x16, "BIOS keyboard"),
+    (0x1a, "BIOS time"),
+    (0x20, "DOS"),
+    (0x21, "DOS"),
+    (0x27, "DOS"),
+    (0x2f, "Multiplex"),
+];
+
+/// Interrupts that do the same regardless of AX.
+const VECTOR_SERVICES: [(u8, &str); 2] = [
+    (0x20, "terminate program"),
+    (0x27, "terminate and stay resident (DX)"),
+];
+
+const SERVICES: &[Service] = &[
+    service(0x21, 0x00, "terminate program"),
+    service(0x21, 0x01, "read character with echo (AL)"),
+    service(0x21, 0x02, "write character (DL)"),
+    service(0x21, 0x03, "read auxiliary input (AL)"),
+    service(0x21, 0x04, "write auxiliary output (DL)"),
+    service(0x21, 0x05, "write printer output (DL)"),
+    service(0x21, 0x06, "direct console I/O (DL)"),
+    service(0x21, 0x07, "direct character input (AL)"),
+    service(0x21, 0x08, "read character without echo (AL)"),
+    service(0x21, 0x09, "print string (DS:DX)"),
+    service(0x21, 0x0a, "buffered input (DS:DX)"),
+    service(0x21, 0x0b, "check input status (AL)"),
+    service(0x21, 0x0c, "flush buffer and read (AL)"),
+    service(0x21, 0x0d, "disk reset"),
+    service(0x21, 0x0e, "select drive (DL)"),
+    service(0x21, 0x19, "get current drive (AL)"),
+    service(0x21, 0x1a, "set disk transfer address (DS:DX)"),
+    service(0x21, 0x25, "set interrupt vector (AL, DS:DX)"),
+    service(0x21, 0x2a, "get date (CX, DH, DL)"),
+    service(0x21, 0x2b, "set date (CX, DH, DL)"),
+    service(0x21, 0x2c, "get time (CH, CL, DH, DL)"),
+    service(0x21, 0x2d, "set time (CH, CL, DH, DL)"),
+    service(0x21, 0x30, "get DOS version (AL, AH)"),
+    service(0x21, 0x31, "terminate and stay resident (AL, DX)"),
+    service(0x21, 0x35, "get interrupt vector (AL, ES:BX)"),
+    service(0x21, 0x36, "get free disk space (DL)"),
+    service(0x21, 0x39, "create directory (DS:DX)"),
+    service(0x21, 0x3a, "remove directory (DS:DX)"),
+    service(0x21, 0x3b, "change directory (DS:DX)"),
+    service(0x21, 0x3c, "create file (DS:DX, CX)"),
+    service(0x21, 0x3d, "open file (DS:DX, AL)"),
+    service(0x21, 0x3e, "close file (BX)"),
+    service(0x21, 0x3f, "read file (BX, CX, DS:DX)"),
+    service(0x21, 0x40, "write file (BX, CX, DS:DX)"),
+    service(0x21, 0x41, "delete file (DS:DX)"),
+    service(0x21, 0x42, "seek (BX, AL, CX:DX)"),
+    service(0x21, 0x43, "get or set file attributes (AL, DS:DX)"),
+    service(0x21, 0x44, "IOCTL (AL, BX)"),
+    service(0x21, 0x47, "get current directory (DL, DS:SI)"),
+    service(0x21, 0x48, "allocate memory (BX)"),
+    service(0x21, 0x49, "free memory (ES)"),
+    service(0x21, 0x4a, "resize memory block (ES, BX)"),
+    service(0x21, 0x4b, "execute program (AL, DS:DX, ES:BX)"),
+    service(0x21, 0x4c, "terminate with return code (AL)"),
+    service(0x21, 0x4d, "get return code (AX)"),
+    service(0x21, 0x4e, "find first file (DS:DX, CX)"),
+    service(0x21, 0x4f, "find next file"),
+    service(0x21, 0x56, "rename file (DS:DX, ES:DI)"),
+    service(0x21, 0x57, "get or set file date and time (AL, BX)"),
+    service(0x21, 0x62, "get PSP address (BX)"),
+    service(0x10, 0x00, "set video mode (AL)"),
+    service(0x10, 0x01, "set cursor shape (CX)"),
+    service(0x10, 0x02, "set cursor position (BH, DH, DL)"),
+    service(0x10, 0x03, "get cursor position (BH)"),
+    service(0x10, 0x05, "select active page (AL)"),
+    service(0x10, 0x06, "scroll window up (AL, BH, CX, DX)"),
+    service(0x10, 0x07, "scroll window down (AL, BH, CX, DX)"),
+    service(0x10, 0x08, "read character and attribute (BH)"),
+    service(0x10, 0x09, "write character and attribute (AL, BH, BL, CX)"),
+    service(0x10, 0x0a, "write character (AL, BH, CX)"),
+    service(0x10, 0x0b, "set color palette (BH, BL)"),
+    service(0x10, 0x0c, "write pixel (AL, CX, DX)"),
+    service(0x10, 0x0d, "read pixel (CX, DX)"),
+    service(0x10, 0x0e, "teletype output (AL)"),
+    service(0x10, 0x0f, "get video mode"),
+    service(0x10, 0x10, "palette registers (AL)"),
+    service(0x10, 0x13, "write string (AL, BH, BL, CX, DH, DL, ES:BP)"),
+    service(0x13, 0x00, "reset disk system (DL)"),
+    service(0x13, 0x01, "get status of last operation (DL)"),
+    service(0x13, 0x02, "read sectors (AL, CH, CL, DH, DL, ES:BX)"),
+    service(0x13, 0x03, "write sectors (AL, CH, CL, DH, DL, ES:BX)"),
+    service(0x13, 0x04, "verify sectors (AL, CH, CL, DH, DL)"),
+    service(0x13, 0x05, "format track (AL, CH, DH, DL, ES:BX)"),
+    service(0x13, 0x08, "get drive parameters (DL)"),
+    service(0x13, 0x15, "get disk type (DL)"),
+    service(0x16, 0x00, "read key (AX)"),
+    service(0x16, 0x01, "check for key (AX, ZF)"),
+    service(0x16, 0x02, "get shift flags (AL)"),
+    service(0x16, 0x10, "read extended key (AX)"),
+    service(0x16, 0x11, "check for extended key (AX, ZF)"),
+    service(0x1a, 0x00, "read tick count (CX:DX)"),
+    service(0x1a, 0x01, "set tick count (CX:DX)"),
+    service(0x1a, 0x02, "read real-time clock time (CH, CL, DH)"),
+    service(0x1a, 0x03, "set real-time clock time (CH, CL, DH)"),
+    service(0x1a, 0x04, "read real-time clock date (CH, CL, DH, DL)"),
+    service(0x1a, 0x05, "set real-time clock date (CH, CL, DH, DL)"),
+    subservice(0x2f, 0x1600, "Windows enhanced mode installation check"),
+    subservice(0x2f, 0x1680, "release time slice"),
+    subservice(0x2f, 0x1687, "DPMI installation check"),
+    subservice(0x2f, 0x4300, "XMS installation check"),
+    subservice(0x2f, 0x4310, "get XMS driver address (ES:BX)"),
+    subservice(0x2f, 0x1100, "network redirector installation check"),
+    subservice(0x2f, 0xb700, "APPEND installation check"),
+];
+
+impl InterruptCall {
+    /// Describes the called service, e.g. `DOS: print string (DS:DX)`.
+    /// Returns `None` for vectors that are not in the built-in table, and
+    /// for services selected by AH when its value is not known.
+    pub fn description(&self) -> Option<String> {
+        let (_, name) = VECTOR_NAMES
+            .iter()
+            .find(|(vector, _)| *vector == self.vector)?;
+
+        if let Some((_, service)) = VECTOR_SERVICES
+            .iter()
+            .find(|(vector, _)| *vector == self.vector)
+        {
+            return Some(format!("{}: {}", name, service));
+        }
+
+        let ah = self.ah?;
+
+        let service = SERVICES
+            .iter()
+            .filter(|service| service.vector == self.vector && service.ah == ah)
+            .find(|service| service.al.is_none() || service.al == self.al);
+
+        match service {
+            Some(service) => Some(format!("{}: {}", name, service.description)),
+            None => Some(format!("{}: function {:#04x}", name, ah)),
+        }
+    }
+}
+
+/// Finds every `int n` instruction, tracking the constants loaded into AH, AL
+/// and DX within its basic block.
+pub fn find_interrupt_calls(cfg: &ControlFlowGraph) -> Vec<InterruptCall> {
+    let mut calls = Vec::new();
+
+    for block in &cfg.blocks {
+        let mut values = RegisterValues::new();
+
+        for (offset, instruction) in &cfg.instructions[block.instructions.clone()] {
+            if instruction.opcode == Opcode::INT {
+                calls.push(InterruptCall {
+                    offset: *offset,
+                    vector: instruction.data as u8,
+                    ah: values.byte(4),
+                    al: values.byte(register::AX),
+                    dx: values.word(register::DX),
+                });
+            }
+
+            values.update(instruction);
+        }
+    }
+
+    calls
+}
+
+/// Comments every recognized interrupt call with the service it invokes.
+pub fn annotate(cfg: &ControlFlowGraph, writer: &mut Writer) {
+    for call in find_interrupt_calls(cfg) {
+        if let Some(description) = call.description() {
+            writer.add_comment(call.offset, &description);
+        }
+    }
+}
+
+#[test]
+fn test_interrupt_call_descriptions() {
+    // mov ah, 9; mov dx, 0x10a; int 0x21; mov ax, 0x4c00; int 0x21; int 0x10;
+    // int 0x20; int 0x2f
+    let bytes = [
+        0xb4, 0x09, 0xba, 0x0a, 0x01, 0xcd, 0x21, 0xb8, 0x00, 0x4c, 0xcd, 0x21, 0xcd, 0x10, 0xcd,
+        0x20, 0xcd, 0x2f,
+    ];
+    let cfg = ControlFlowGraph::build(&bytes);
+    let descriptions: Vec<Option<String>> = find_interrupt_calls(&cfg)
+        .iter()
+        .map(InterruptCall::description)
+        .collect();
+
+    assert_eq!(
+        descriptions,
+        vec![
+            Some("DOS: print string (DS:DX)".to_string()),
+            Some("DOS: terminate with return code (AL)".to_string()),
+            None,
+            Some("DOS: terminate program".to_string()),
+            None,
+        ]
+    );
+
+    let call = InterruptCall {
+        offset: 0,
+        vector: 0x2f,
+        ah: Some(0x43),
+        al: Some(0x10),
+        dx: None,
+    };
+    assert_eq!(
+        call.description().unwrap(),
+        "Multiplex: get XMS driver address (ES:BX)"
+    );
+    assert_eq!(
+        InterruptCall { al: None, ..call }.description().unwrap(),
+        "Multiplex: function 0x43"
+    );
+}
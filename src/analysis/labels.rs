@@ -0,0 +1,49 @@
+use crate::{writer::Writer, Instruction};
+
+/// Adds the targets of the relative jumps, branches and calls to the writer
+/// in the order they appear, so that every label is known before the
+/// instructions are written.
+pub fn annotate(instructions: impl IntoIterator<Item = (usize, Instruction)>, writer: &mut Writer) {
+    for (offset, instruction) in instructions {
+        let Some(target) = instruction.branch_target(offset) else {
+            continue;
+        };
+
+        if let Ok(target) = usize::try_from(target) {
+            writer.add_jump_target(target);
+        }
+    }
+}
+
+#[test]
+fn test_annotate() {
+    use crate::{parser::Parser, writer::WriterOptions};
+
+    // loc_0: add ax, bx; je loc_0; jmp into the middle of mov ax, 0x00eb
+    let bytes = [0x01, 0xd8, 0x74, 0xfc, 0xeb, 0x01, 0xb8, 0xeb, 0x00];
+    let mut writer = Writer::new(WriterOptions {
+        verbose: false,
+        faithful: false,
+        undocumented: false,
+    });
+    let parser = Parser::build(&bytes).unwrap();
+
+    annotate(parser.with_offsets(), &mut writer);
+
+    for instruction in parser {
+        instruction.write(&mut writer);
+    }
+
+    writer.define_unwritten_labels();
+
+    assert_eq!(
+        std::str::from_utf8(writer.as_slice()).unwrap(),
+        "loc_0:
+add ax, bx
+je loc_0
+jmp loc_1
+mov ax, 0x00eb
+loc_1 equ $-2
+"
+    );
+}
@@ -0,0 +1,260 @@
+use crate::{listing::Listing, parser::Item, writer::Writer, Instruction};
+
+/// Most instructions decoded from a target before giving up on the stream
+/// joining the main one.
+const MAX_LISTING_LENGTH: usize = 16;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum OverlapKind {
+    /// The target is inside the item starting at the offset.
+    Inside(usize),
+    /// The target is before the start or past the end of the input.
+    Outside,
+}
+
+/// A jump, branch or call to an offset that is not the start of an
+/// instruction or data.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Overlap {
+    /// Offset of the jumping instruction.
+    pub source: usize,
+    pub target: isize,
+    pub kind: OverlapKind,
+}
+
+/// Finds the branch targets that are not item boundaries.
+pub fn find_overlaps(bytes: &[u8], items: &[(usize, Item)]) -> Vec<Overlap> {
+    let mut overlaps = Vec::new();
+
+    for &(source, item) in items {
+        let Item::Instruction(instruction) = item else {
+            continue;
+        };
+        let Some(target) = instruction.branch_target(source) else {
+            continue;
+        };
+
+        let Some(offset) = usize::try_from(target)
+            .ok()
+            .filter(|&offset| offset < bytes.len())
+        else {
+            overlaps.push(Overlap {
+                source,
+                target,
+                kind: OverlapKind::Outside,
+            });
+            continue;
+        };
+
+        // Targets in the bytes that were not parsed are left alone
+        match items.binary_search_by_key(&offset, |&(start, _)| start) {
+            Ok(_) | Err(0) => {}
+            Err(index) if index == items.len() && !contains(items[index - 1], offset) => {}
+            Err(index) => overlaps.push(Overlap {
+                source,
+                target,
+                kind: OverlapKind::Inside(items[index - 1].0),
+            }),
+        }
+    }
+
+    overlaps
+}
+
+fn contains((start, item): (usize, Item), offset: usize) -> bool {
+    let length = match item {
+        Item::Instruction(instruction) => instruction.length as usize,
+        Item::Data(length) => length,
+    };
+
+    (start..start + length).contains(&offset)
+}
+
+/// Decodes the instructions from the offset until they reach the start of an
+/// item, returning them along with that offset if they do.
+pub fn alternate_stream(
+    bytes: &[u8],
+    items: &[(usize, Item)],
+    offset: usize,
+    undocumented: bool,
+) -> (Vec<(usize, Instruction)>, Option<usize>) {
+    let mut instructions = Vec::new();
+    let mut index = offset;
+
+    while instructions.len() < MAX_LISTING_LENGTH {
+        if items
+            .binary_search_by_key(&index, |&(start, _)| start)
+            .is_ok()
+        {
+            return (instructions, Some(index));
+        }
+
+        let instruction = match undocumented {
+            true => Instruction::parse_undocumented(&bytes[index..]),
+            false => Instruction::parse(&bytes[index..]),
+        };
+        let Some(instruction) = instruction else {
+            break;
+        };
+
+        instructions.push((index, instruction));
+        index += instruction.length as usize;
+    }
+
+    (instructions, None)
+}
+
+fn hex(target: isize) -> String {
+    match target {
+        0.. => format!("{:#06x}", target),
+        _ => format!("-{:#06x}", target.unsigned_abs()),
+    }
+}
+
+/// Lists the instructions decoded from the middle of an instruction in the
+/// header before it, and points out jumps outside the input, with the
+/// addresses of the input loaded at the origin.
+pub fn annotate(
+    bytes: &[u8],
+    items: &[(usize, Item)],
+    writer: &mut Writer,
+    undocumented: bool,
+    origin: usize,
+) {
+    let overlaps = find_overlaps(bytes, items);
+    let mut listed = Vec::new();
+
+    for overlap in overlaps {
+        let OverlapKind::Inside(start) = overlap.kind else {
+            writer.append_comment(
+                overlap.source,
+                &format!(
+                    "target {} is outside the file",
+                    hex(origin as isize + overlap.target)
+                ),
+            );
+            continue;
+        };
+        let target = overlap.target as usize;
+
+        writer.append_comment(
+            overlap.source,
+            &format!("target {:#06x} is inside an instruction", origin + target),
+        );
+
+        if listed.contains(&target) {
+            continue;
+        }
+
+        listed.push(target);
+
+        let (instructions, rejoin) = alternate_stream(bytes, items, target, undocumented);
+        let listing = match undocumented {
+            true => Listing::new(bytes).with_undocumented(),
+            false => Listing::new(bytes),
+        }
+        .with_origin(origin);
+
+        writer.add_header_comment(start, &format!("decoded from {:#06x}:", origin + target));
+
+        let end = instructions.last().map_or(target, |(offset, instruction)| {
+            offset + instruction.length as usize
+        });
+        let stopped = instructions.len() < MAX_LISTING_LENGTH;
+
+        for (offset, instruction) in instructions {
+            writer.add_header_comment(
+                start,
+                &format!(
+                    "  {:#06x}: {}",
+                    origin + offset,
+                    listing.instruction_text(offset, &instruction)
+                ),
+            );
+        }
+
+        match rejoin {
+            Some(rejoin) => {
+                writer
+                    .add_header_comment(start, &format!("  continues at {:#06x}", origin + rejoin));
+            }
+            None if stopped => {
+                writer.add_header_comment(
+                    start,
+                    &format!("  {:#06x}: can not be decoded", origin + end),
+                );
+            }
+            None => {}
+        }
+    }
+}
+
+#[test]
+fn test_find_overlaps() {
+    use crate::parser::parse_with_data;
+
+    // jmp into mov ax, 0x00eb; jmp before the start; jmp past the end
+    let bytes = [0xeb, 0x01, 0xb8, 0xeb, 0x00, 0xeb, 0xf0, 0xeb, 0x10];
+    let items = parse_with_data(&bytes, &[], false);
+
+    assert_eq!(
+        find_overlaps(&bytes, &items),
+        vec![
+            Overlap {
+                source: 0,
+                target: 3,
+                kind: OverlapKind::Inside(2)
+            },
+            Overlap {
+                source: 5,
+                target: -9,
+                kind: OverlapKind::Outside
+            },
+            Overlap {
+                source: 7,
+                target: 25,
+                kind: OverlapKind::Outside
+            },
+        ]
+    );
+
+    let (instructions, rejoin) = alternate_stream(&bytes, &items, 3, false);
+
+    assert_eq!(instructions.len(), 1);
+    assert_eq!(
+        Listing::new(&bytes).instruction_text(3, &instructions[0].1),
+        "jmp 0x0005"
+    );
+    assert_eq!(rejoin, Some(5));
+
+    // The jumps are written so that they reassemble to the same bytes
+    use crate::writer::WriterOptions;
+
+    let options = WriterOptions {
+        verbose: false,
+        faithful: false,
+        undocumented: false,
+    };
+    let output = crate::disassemble("", &bytes, options.clone()).unwrap();
+
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains("jmp $-14 ; target -0x0009 is outside the file"));
+    assert!(crate::verify::verify(&bytes, options.clone()).is_ok());
+
+    // The comments have the addresses the input is loaded at
+    let output = crate::disassemble_with_origin(
+        "",
+        &bytes,
+        options,
+        &crate::symbols::SymbolMap::new(),
+        0x100,
+    )
+    .unwrap();
+    let output = String::from_utf8(output).unwrap();
+
+    assert!(output.contains("; target 0x0103 is inside an instruction\n"));
+    assert!(output.contains("; decoded from 0x0103:\n;   0x0103: jmp 0x0105\n"));
+    assert!(output.contains(";   continues at 0x0105\n"));
+    assert!(output.contains("; target 0x00f7 is outside the file\n"));
+}
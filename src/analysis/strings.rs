@@ -0,0 +1,191 @@
+use std::ops::Range;
+
+use crate::parser::{self, Item};
+
+use super::{cfg::ControlFlowGraph, interrupts::find_interrupt_calls};
+
+/// Shortest string that is found without a hint, not counting line breaks
+/// and the terminator.
+const MIN_LENGTH: usize = 4;
+
+/// Address DOS loads `.com` programs at, which addresses loaded into
+/// registers are relative to when no other is given.
+pub const COM_ORIGIN: usize = 0x100;
+
+fn is_text(byte: u8) -> bool {
+    matches!(byte, 0x20..=0x7e | b'\t' | b'\r' | b'\n')
+}
+
+/// Whether the bytes look like a message rather than code that happens to
+/// be printable: mostly letters and spaces, with at least one lowercase
+/// letter or space.
+fn looks_like_text(bytes: &[u8]) -> bool {
+    let characters: Vec<u8> = bytes
+        .iter()
+        .copied()
+        .filter(|byte| !matches!(byte, b'$' | b'\r' | b'\n' | 0))
+        .collect();
+    let letters = characters
+        .iter()
+        .filter(|byte| byte.is_ascii_alphabetic() || **byte == b' ')
+        .count();
+
+    characters.len() >= MIN_LENGTH
+        && letters * 4 >= characters.len() * 3
+        && characters
+            .iter()
+            .any(|byte| byte.is_ascii_lowercase() || *byte == b' ')
+}
+
+/// Finds runs of printable ASCII that end in `$`, a zero byte or CR/LF.
+pub fn find_strings(bytes: &[u8]) -> Vec<Range<usize>> {
+    let mut strings = Vec::new();
+    let mut push = |range: Range<usize>| {
+        if looks_like_text(&bytes[range.clone()]) {
+            strings.push(range);
+        }
+    };
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if !is_text(bytes[index]) {
+            index += 1;
+            continue;
+        }
+
+        let start = index;
+
+        while index < bytes.len() && is_text(bytes[index]) {
+            index += 1;
+        }
+
+        let mut piece_start = start;
+
+        for dollar in (start..index).filter(|&i| bytes[i] == b'$') {
+            push(piece_start..dollar + 1);
+            piece_start = dollar + 1;
+        }
+
+        if bytes.get(index) == Some(&0) {
+            push(piece_start..index + 1);
+            index += 1;
+        } else if let Some(line_end) =
+            (piece_start + 1..index).rfind(|&i| bytes[i - 1] == b'\r' && bytes[i] == b'\n')
+        {
+            push(piece_start..line_end + 1);
+        }
+    }
+
+    strings
+}
+
+/// Finds the `$`-terminated strings printed with `int 21h` function 09h,
+/// whose address is loaded into DX before the call.
+pub fn find_hinted_strings(
+    bytes: &[u8],
+    cfg: &ControlFlowGraph,
+    origin: usize,
+) -> Vec<Range<usize>> {
+    let mut strings: Vec<Range<usize>> = find_interrupt_calls(cfg)
+        .iter()
+        .filter(|call| call.vector == 0x21 && call.ah == Some(0x09))
+        .filter_map(|call| {
+            let start = (call.dx? as usize).checked_sub(origin)?;
+            let length = bytes.get(start..)?.iter().position(|&byte| byte == b'$')?;
+
+            Some(start..start + length + 1)
+        })
+        .collect();
+
+    strings.sort_by_key(|range| range.start);
+    strings.dedup();
+    strings
+}
+
+fn overlaps(a: &Range<usize>, b: &Range<usize>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+/// Finds the strings in a program loaded at the given address outside of the
+/// given data ranges. Strings found by scanning are kept as data so that they
+/// do not desync the code after them, which is then searched for hinted
+/// strings until no new ones are found.
+pub fn find_string_regions(
+    bytes: &[u8],
+    data: &[Range<usize>],
+    undocumented: bool,
+    origin: usize,
+) -> Vec<Range<usize>> {
+    let mut scanned: Vec<Range<usize>> = find_strings(bytes)
+        .into_iter()
+        .filter(|string| !data.iter().any(|range| overlaps(string, range)))
+        .collect();
+    let mut hinted: Vec<Range<usize>> = Vec::new();
+
+    loop {
+        let mut ranges = data.to_vec();
+        ranges.extend(scanned.iter().cloned());
+        ranges.extend(hinted.iter().cloned());
+
+        let instructions = parser::parse_with_data(bytes, &ranges, undocumented)
+            .into_iter()
+            .filter_map(|(offset, item)| match item {
+                Item::Instruction(instruction) => Some((offset, instruction)),
+                Item::Data(_) => None,
+            })
+            .collect();
+        let cfg = ControlFlowGraph::from_instructions(instructions);
+        let mut found = false;
+
+        for hint in find_hinted_strings(bytes, &cfg, origin) {
+            if data
+                .iter()
+                .chain(&hinted)
+                .any(|range| overlaps(&hint, range))
+            {
+                continue;
+            }
+
+            // Hints are more reliable than the scan, so they replace the
+            // strings they overlap
+            scanned.retain(|string| !overlaps(string, &hint));
+            hinted.push(hint);
+            found = true;
+        }
+
+        if !found {
+            break;
+        }
+    }
+
+    scanned.extend(hinted);
+    scanned.sort_by_key(|range| range.start);
+    scanned
+}
+
+#[test]
+fn test_find_strings() {
+    let bytes = b"\xb4\x09PQRS$\x90Hello, world!\r\n$Bye now\x00\x00Line\r\nno end\xc3";
+
+    assert_eq!(find_strings(bytes), vec![8..24, 24..32, 33..39]);
+}
+
+#[test]
+fn test_find_string_regions() {
+    // mov ah, 9; mov dx, 0x10a; int 0x21; ret; db 'Hi!$'
+    let bytes = [
+        0xb4, 0x09, 0xba, 0x0a, 0x01, 0xcd, 0x21, 0xc3, 0x00, 0x00, b'H', b'i', b'!', b'$',
+    ];
+
+    assert_eq!(find_strings(&bytes), vec![]);
+    assert_eq!(
+        find_string_regions(&bytes, &[], false, COM_ORIGIN),
+        vec![10..14]
+    );
+    assert_eq!(
+        find_string_regions(&bytes, &[8..12, 14..16], false, COM_ORIGIN),
+        vec![]
+    );
+    // Loaded at 0x102, the string starts at the zeros before the text
+    assert_eq!(find_string_regions(&bytes, &[], false, 0x102), vec![8..14]);
+}
@@ -0,0 +1,495 @@
+pub mod syntax;
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::{
+    encoder::{encode, encode_near_jump},
+    instructions::{
+        opcode::Opcode,
+        operand::{self, Memory, Operation, Size},
+    },
+};
+
+use syntax::{parse_line, DataItem, Expression, Operand, Statement, Term};
+
+/// Passes over the source before giving up on the addresses settling.
+const MAX_PASSES: usize = 32;
+
+/// How deep `equ` definitions may refer to each other.
+const MAX_DEPTH: usize = 32;
+
+#[derive(Debug)]
+pub struct AssembleError {
+    /// Line of the source the error is on, starting from 1.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error for AssembleError {}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Assembly error on line {}: {}", self.line, self.message)
+    }
+}
+
+enum Symbol {
+    /// Index of the statement the label is on.
+    Label(usize),
+    /// Value and the index of the statement, for `$`.
+    Equ(Expression, usize),
+}
+
+struct Line {
+    number: usize,
+    statement: Statement,
+    /// Last label not starting with a dot, which local labels belong to.
+    scope: String,
+}
+
+struct Assembler {
+    lines: Vec<Line>,
+    symbols: HashMap<String, Symbol>,
+    addresses: Vec<usize>,
+    /// Jumps that have been made near once, which stay near so that the
+    /// passes settle.
+    near: Vec<bool>,
+    /// Whether to guess lengths, assuming jumps are short, in the first
+    /// pass or for a statement that has an error, so that the statements
+    /// after it can be placed.
+    guessing: bool,
+}
+
+fn qualify(name: &str, scope: &str) -> String {
+    match name.starts_with('.') {
+        true => format!("{}{}", scope, name),
+        false => name.to_string(),
+    }
+}
+
+fn is_branch(opcode: Opcode) -> bool {
+    matches!(
+        opcode,
+        Opcode::JMP
+            | Opcode::CALL
+            | Opcode::JA
+            | Opcode::JAE
+            | Opcode::JB
+            | Opcode::JBE
+            | Opcode::JC
+            | Opcode::JCXZ
+            | Opcode::JE
+            | Opcode::JG
+            | Opcode::JGE
+            | Opcode::JL
+            | Opcode::JLE
+            | Opcode::JNC
+            | Opcode::JNE
+            | Opcode::JNO
+            | Opcode::JNP
+            | Opcode::JNS
+            | Opcode::JO
+            | Opcode::JP
+            | Opcode::JS
+            | Opcode::LOOP
+            | Opcode::LOOPE
+            | Opcode::LOOPNE
+    )
+}
+
+fn prefix_byte(prefix: Opcode) -> u8 {
+    match prefix {
+        Opcode::LOCK => 0xf0,
+        Opcode::REPNE => 0xf2,
+        _ => 0xf3,
+    }
+}
+
+impl Assembler {
+    fn new(source: &str) -> Result<Self, AssembleError> {
+        let mut lines = Vec::new();
+        let mut symbols = HashMap::new();
+        let mut scope = String::new();
+
+        for (index, text) in source.lines().enumerate() {
+            let number = index + 1;
+            let statements = parse_line(text).map_err(|message| AssembleError {
+                line: number,
+                message,
+            })?;
+
+            for statement in statements {
+                let definition = match &statement {
+                    Statement::Label(name) => {
+                        if !name.starts_with('.') {
+                            scope = name.clone();
+                        }
+                        Some((qualify(name, &scope), Symbol::Label(lines.len())))
+                    }
+                    Statement::Equ(name, value) => Some((
+                        qualify(name, &scope),
+                        Symbol::Equ(value.clone(), lines.len()),
+                    )),
+                    _ => None,
+                };
+
+                if let Some((name, symbol)) = definition {
+                    if symbols.insert(name.clone(), symbol).is_some() {
+                        return Err(AssembleError {
+                            line: number,
+                            message: format!("symbol '{}' is already defined", name),
+                        });
+                    }
+                }
+
+                lines.push(Line {
+                    number,
+                    statement,
+                    scope: scope.clone(),
+                });
+            }
+        }
+
+        Ok(Self {
+            addresses: vec![0; lines.len()],
+            near: vec![false; lines.len()],
+            lines,
+            symbols,
+            guessing: true,
+        })
+    }
+
+    fn evaluate(&self, expression: &Expression, index: usize, depth: usize) -> Result<i32, String> {
+        if depth > MAX_DEPTH {
+            return Err("symbol is defined in terms of itself".to_string());
+        }
+
+        let mut sum: i32 = 0;
+
+        for (negative, term) in &expression.terms {
+            let value = match term {
+                Term::Number(value) => *value,
+                Term::Here => self.addresses[index] as i32,
+                Term::Symbol(name) => {
+                    match self.symbols.get(&qualify(name, &self.lines[index].scope)) {
+                        Some(Symbol::Label(label)) => self.addresses[*label] as i32,
+                        Some(Symbol::Equ(value, at)) => self.evaluate(value, *at, depth + 1)?,
+                        None if self.guessing => 0,
+                        None => return Err(format!("undefined symbol '{}'", name)),
+                    }
+                }
+            };
+
+            sum = match negative {
+                true => sum.wrapping_sub(value),
+                false => sum.wrapping_add(value),
+            };
+        }
+
+        Ok(sum)
+    }
+
+    fn size(opcode: Opcode, operands: &[Operand]) -> Result<Size, String> {
+        let sizes = operands.iter().take(match opcode {
+            // The shift count does not tell the size of the shifted operand
+            Opcode::ROL
+            | Opcode::ROR
+            | Opcode::RCL
+            | Opcode::RCR
+            | Opcode::SAL
+            | Opcode::SHR
+            | Opcode::SAR => 1,
+            _ => operands.len(),
+        });
+
+        for operand in sizes {
+            match operand {
+                Operand::Memory {
+                    size: Some(size), ..
+                }
+                | Operand::Immediate {
+                    size: Some(size), ..
+                } => return Ok(*size),
+                Operand::Byte(_) => return Ok(Size::Byte),
+                Operand::Word(_) | Operand::Segment(_) => return Ok(Size::Word),
+                _ => {}
+            }
+        }
+
+        match opcode {
+            Opcode::PUSH
+            | Opcode::POP
+            | Opcode::JMP
+            | Opcode::CALL
+            | Opcode::LEA
+            | Opcode::LDS
+            | Opcode::LES => Ok(Size::Word),
+            _ => Err("operation size not specified".to_string()),
+        }
+    }
+
+    fn operation(
+        &self,
+        index: usize,
+        opcode: Opcode,
+        operands: &[Operand],
+        address: usize,
+    ) -> Result<Operation, String> {
+        let mut converted = Vec::new();
+
+        for operand in operands {
+            converted.push(match operand {
+                Operand::Byte(register) => operand::Operand::Byte(*register),
+                Operand::Word(register) => operand::Operand::Word(*register),
+                Operand::Segment(register) => operand::Operand::Segment(*register),
+                Operand::Memory {
+                    base,
+                    displacement,
+                    size,
+                    ..
+                } => operand::Operand::Memory(Memory {
+                    base: *base,
+                    displacement: self.evaluate(displacement, index, 0)? as i16,
+                    size: match size {
+                        Some(size) => *size,
+                        None => Self::size(opcode, operands)?,
+                    },
+                }),
+                Operand::Immediate { value, .. } if is_branch(opcode) => {
+                    let target = self.evaluate(value, index, 0)?;
+                    operand::Operand::Relative(target.wrapping_sub(address as i32) as i16)
+                }
+                Operand::Immediate { value, .. } => {
+                    operand::Operand::Immediate(self.evaluate(value, index, 0)? as u16)
+                }
+                Operand::Far { segment, offset } => operand::Operand::Far {
+                    segment: self.evaluate(segment, index, 0)? as u16,
+                    offset: self.evaluate(offset, index, 0)? as u16,
+                },
+            });
+        }
+
+        // `xchg ax, r` has the same short form as `xchg r, ax`
+        if let (Opcode::XCHG, [accumulator @ operand::Operand::Word(0), register]) =
+            (opcode, converted.as_slice())
+        {
+            converted = vec![*register, *accumulator];
+        }
+
+        Ok(Operation::new(opcode, &converted))
+    }
+
+    fn assemble_data(
+        &self,
+        index: usize,
+        word: bool,
+        items: &[DataItem],
+    ) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+
+        for item in items {
+            match item {
+                DataItem::String(string) => {
+                    bytes.extend(string);
+
+                    if word && string.len() % 2 == 1 {
+                        bytes.push(0);
+                    }
+                }
+                DataItem::Value(value) => {
+                    let value = self.evaluate(value, index, 0)?;
+
+                    if word {
+                        bytes.extend((value as u16).to_le_bytes());
+                    } else if (-0x80..=0xff).contains(&value) || self.guessing {
+                        bytes.push(value as u8);
+                    } else {
+                        return Err(format!("value {} does not fit in a byte", value));
+                    }
+                }
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn assemble_statement(&mut self, index: usize) -> Result<Vec<u8>, String> {
+        let Statement::Instruction {
+            prefixes,
+            opcode,
+            operands,
+            near,
+        } = &self.lines[index].statement
+        else {
+            return match &self.lines[index].statement {
+                Statement::Data { word, items } => self.assemble_data(index, *word, items),
+                _ => Ok(Vec::new()),
+            };
+        };
+
+        let mut bytes: Vec<u8> = prefixes.iter().map(|&prefix| prefix_byte(prefix)).collect();
+
+        for operand in operands {
+            if let Operand::Memory {
+                segment: Some(segment),
+                ..
+            } = operand
+            {
+                bytes.push(0b00100110 | segment << 3);
+            }
+        }
+
+        let Some(opcode) = *opcode else {
+            return Ok(bytes);
+        };
+
+        let address = self.addresses[index] + bytes.len();
+        let operation = self.operation(index, opcode, operands, address)?;
+        let near = *near || self.near[index];
+
+        let encoded = match operation.operands.as_slice() {
+            [operand::Operand::Relative(relative)] if opcode == Opcode::JMP && near => {
+                encode_near_jump(*relative)
+            }
+            [operand::Operand::Relative(_)] if self.guessing => {
+                let mut operation = operation.clone();
+                operation.operands = vec![operand::Operand::Relative(0)];
+                encode(&operation).map_err(|error| error.to_string())?
+            }
+            _ => encode(&operation).map_err(|error| error.to_string())?,
+        };
+
+        if opcode == Opcode::JMP && encoded[0] == 0xe9 {
+            self.near[index] = true;
+        }
+
+        bytes.extend(encoded);
+        Ok(bytes)
+    }
+
+    fn origin(&self) -> Result<usize, AssembleError> {
+        let mut origin = 0;
+
+        for (index, line) in self.lines.iter().enumerate() {
+            if let Statement::Org(value) = &line.statement {
+                origin = self
+                    .evaluate(value, index, 0)
+                    .map_err(|message| AssembleError {
+                        line: line.number,
+                        message,
+                    })? as u16 as usize;
+            }
+        }
+
+        Ok(origin)
+    }
+
+    /// Lays out the statements until their addresses stop changing, then
+    /// returns the output of the last pass. Errors are only reported once
+    /// the addresses have settled, as they may come from a label that has
+    /// not been placed yet.
+    fn assemble(&mut self) -> Result<Vec<u8>, AssembleError> {
+        let origin = self.origin()?;
+
+        for pass in 0..MAX_PASSES {
+            let mut output = Vec::new();
+            let mut error = None;
+            let mut changed = pass == 0;
+
+            self.guessing = pass == 0;
+
+            for index in 0..self.lines.len() {
+                let address = origin + output.len();
+
+                changed |= self.addresses[index] != address;
+                self.addresses[index] = address;
+
+                match self.assemble_statement(index) {
+                    Ok(bytes) => output.extend(bytes),
+                    Err(message) => {
+                        error.get_or_insert(AssembleError {
+                            line: self.lines[index].number,
+                            message,
+                        });
+
+                        let guessing = std::mem::replace(&mut self.guessing, true);
+                        output.extend(self.assemble_statement(index).unwrap_or_default());
+                        self.guessing = guessing;
+                    }
+                }
+            }
+
+            if !changed {
+                return match error {
+                    Some(error) => Err(error),
+                    None => Ok(output),
+                };
+            }
+        }
+
+        Err(AssembleError {
+            line: 0,
+            message: "addresses did not settle".to_string(),
+        })
+    }
+}
+
+/// Assembles 8086 code written in the subset of NASM syntax the disassembler
+/// writes into a flat binary.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssembleError> {
+    Assembler::new(source)?.assemble()
+}
+
+#[test]
+fn test_assemble() {
+    let source = "bits 16
+org 0x100
+message equ text
+start:
+    mov ah, 9
+    mov dx, message
+    int 0x21
+.wait:
+    in al, 0x60
+    cmp al, 1
+    jne .wait
+    jmp far_away
+    xchg ax, bx
+    mov [es:bx+si+2], byte 7
+    lock rep movsb
+    jmp 0x1234:0x10
+text:
+    db 'Hi', 0x0d, 0x0a, '$'
+    times_two dw 2, 'a'
+far_away:
+    ret
+";
+
+    assert_eq!(
+        assemble(source).unwrap(),
+        [
+            0xb4, 0x09, 0xba, 0x1d, 0x01, 0xcd, 0x21, 0xe4, 0x60, 0x3c, 0x01, 0x75, 0xfa, 0xeb,
+            0x17, 0x93, 0x26, 0xc6, 0x40, 0x02, 0x07, 0xf0, 0xf3, 0xa4, 0xea, 0x10, 0x00, 0x34,
+            0x12, b'H', b'i', 0x0d, 0x0a, b'$', 0x02, 0x00, b'a', 0x00, 0xc3,
+        ]
+    );
+}
+
+#[test]
+fn test_assemble_errors() {
+    let error = |source: &str| assemble(source).unwrap_err().to_string();
+
+    assert_eq!(
+        error("nop\njmp nowhere"),
+        "Assembly error on line 2: undefined symbol 'nowhere'"
+    );
+    assert_eq!(
+        error("mov [bx], 1"),
+        "Assembly error on line 1: operation size not specified"
+    );
+    assert_eq!(
+        error("a: nop\na: nop"),
+        "Assembly error on line 2: symbol 'a' is already defined"
+    );
+    assert!(error("jne target\ntarget equ 0x1000").starts_with("Assembly error on line 1: "));
+}
@@ -0,0 +1,725 @@
+use crate::instructions::{common::effective, opcode::Opcode, operand::Size};
+
+/// A term of an expression: a number, a symbol or `$`, the address of the
+/// current line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Number(i32),
+    Symbol(String),
+    Here,
+}
+
+/// Sum of signed terms, e.g. `loc_0+2` or `-30`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    pub terms: Vec<(bool, Term)>,
+}
+
+impl Expression {
+    pub fn number(value: i32) -> Self {
+        Self {
+            terms: vec![(false, Term::Number(value))],
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Byte(u8),
+    Word(u8),
+    Segment(u8),
+    Memory {
+        segment: Option<u8>,
+        base: Option<u8>,
+        displacement: Expression,
+        size: Option<Size>,
+    },
+    Immediate {
+        value: Expression,
+        size: Option<Size>,
+    },
+    Far {
+        segment: Expression,
+        offset: Expression,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataItem {
+    Value(Expression),
+    String(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Statement {
+    Label(String),
+    Equ(String, Expression),
+    Org(Expression),
+    Data {
+        word: bool,
+        items: Vec<DataItem>,
+    },
+    Instruction {
+        /// LOCK and REP prefixes in the order they were written in.
+        prefixes: Vec<Opcode>,
+        opcode: Option<Opcode>,
+        operands: Vec<Operand>,
+        /// Whether a jump was marked `near`.
+        near: bool,
+    },
+}
+
+const MNEMONICS: &[(&str, Opcode)] = &[
+    ("aaa", Opcode::AAA),
+    ("aad", Opcode::AAD),
+    ("aam", Opcode::AAM),
+    ("aas", Opcode::AAS),
+    ("adc", Opcode::ADC),
+    ("add", Opcode::ADD),
+    ("and", Opcode::AND),
+    ("call", Opcode::CALL),
+    ("cbw", Opcode::CBW),
+    ("clc", Opcode::CLC),
+    ("cld", Opcode::CLD),
+    ("cli", Opcode::CLI),
+    ("cmc", Opcode::CMC),
+    ("cmp", Opcode::CMP),
+    ("cmpsb", Opcode::CMPSB),
+    ("cmpsw", Opcode::CMPSW),
+    ("cwd", Opcode::CWD),
+    ("daa", Opcode::DAA),
+    ("das", Opcode::DAS),
+    ("dec", Opcode::DEC),
+    ("div", Opcode::DIV),
+    ("hlt", Opcode::HLT),
+    ("idiv", Opcode::IDIV),
+    ("imul", Opcode::IMUL),
+    ("in", Opcode::IN),
+    ("inc", Opcode::INC),
+    ("int", Opcode::INT),
+    ("int3", Opcode::INT3),
+    ("into", Opcode::INTO),
+    ("iret", Opcode::IRET),
+    ("ja", Opcode::JA),
+    ("jnbe", Opcode::JA),
+    ("jae", Opcode::JAE),
+    ("jnb", Opcode::JAE),
+    ("jb", Opcode::JB),
+    ("jnae", Opcode::JB),
+    ("jbe", Opcode::JBE),
+    ("jna", Opcode::JBE),
+    ("jc", Opcode::JC),
+    ("jcxz", Opcode::JCXZ),
+    ("je", Opcode::JE),
+    ("jz", Opcode::JE),
+    ("jg", Opcode::JG),
+    ("jnle", Opcode::JG),
+    ("jge", Opcode::JGE),
+    ("jnl", Opcode::JGE),
+    ("jl", Opcode::JL),
+    ("jnge", Opcode::JL),
+    ("jle", Opcode::JLE),
+    ("jng", Opcode::JLE),
+    ("jmp", Opcode::JMP),
+    ("jnc", Opcode::JNC),
+    ("jne", Opcode::JNE),
+    ("jnz", Opcode::JNE),
+    ("jno", Opcode::JNO),
+    ("jnp", Opcode::JNP),
+    ("jpo", Opcode::JNP),
+    ("jns", Opcode::JNS),
+    ("jo", Opcode::JO),
+    ("jp", Opcode::JP),
+    ("jpe", Opcode::JP),
+    ("js", Opcode::JS),
+    ("lahf", Opcode::LAHF),
+    ("lds", Opcode::LDS),
+    ("lea", Opcode::LEA),
+    ("les", Opcode::LES),
+    ("lodsb", Opcode::LODSB),
+    ("lodsw", Opcode::LODSW),
+    ("loop", Opcode::LOOP),
+    ("loope", Opcode::LOOPE),
+    ("loopz", Opcode::LOOPE),
+    ("loopne", Opcode::LOOPNE),
+    ("loopnz", Opcode::LOOPNE),
+    ("mov", Opcode::MOV),
+    ("movsb", Opcode::MOVSB),
+    ("movsw", Opcode::MOVSW),
+    ("mul", Opcode::MUL),
+    ("neg", Opcode::NEG),
+    ("nop", Opcode::NOP),
+    ("not", Opcode::NOT),
+    ("or", Opcode::OR),
+    ("out", Opcode::OUT),
+    ("pop", Opcode::POP),
+    ("popf", Opcode::POPF),
+    ("push", Opcode::PUSH),
+    ("pushf", Opcode::PUSHF),
+    ("rcl", Opcode::RCL),
+    ("rcr", Opcode::RCR),
+    ("ret", Opcode::RET),
+    ("retn", Opcode::RET),
+    ("retf", Opcode::RETF),
+    ("rol", Opcode::ROL),
+    ("ror", Opcode::ROR),
+    ("sahf", Opcode::SAHF),
+    ("sal", Opcode::SAL),
+    ("salc", Opcode::SALC),
+    ("shl", Opcode::SAL),
+    ("sar", Opcode::SAR),
+    ("sbb", Opcode::SBB),
+    ("scasb", Opcode::SCASB),
+    ("scasw", Opcode::SCASW),
+    ("setmo", Opcode::SETMO),
+    ("shr", Opcode::SHR),
+    ("stc", Opcode::STC),
+    ("std", Opcode::STD),
+    ("sti", Opcode::STI),
+    ("stosb", Opcode::STOSB),
+    ("stosw", Opcode::STOSW),
+    ("sub", Opcode::SUB),
+    ("test", Opcode::TEST),
+    ("wait", Opcode::WAIT),
+    ("fwait", Opcode::WAIT),
+    ("xchg", Opcode::XCHG),
+    ("xlat", Opcode::XLAT),
+    ("xlatb", Opcode::XLAT),
+    ("xor", Opcode::XOR),
+];
+
+const PREFIXES: &[(&str, Opcode)] = &[
+    ("lock", Opcode::LOCK),
+    ("rep", Opcode::REP),
+    ("repe", Opcode::REPE),
+    ("repz", Opcode::REPE),
+    ("repne", Opcode::REPNE),
+    ("repnz", Opcode::REPNE),
+];
+
+const BYTE_REGISTERS: [&str; 8] = ["al", "cl", "dl", "bl", "ah", "ch", "dh", "bh"];
+const WORD_REGISTERS: [&str; 8] = ["ax", "cx", "dx", "bx", "sp", "bp", "si", "di"];
+const SEGMENT_REGISTERS: [&str; 4] = ["es", "cs", "ss", "ds"];
+
+fn lookup(table: &[(&str, Opcode)], name: &str) -> Option<Opcode> {
+    table
+        .iter()
+        .find(|(mnemonic, _)| *mnemonic == name)
+        .map(|&(_, opcode)| opcode)
+}
+
+fn segment_register(name: &str) -> Option<u8> {
+    SEGMENT_REGISTERS
+        .iter()
+        .position(|&segment| segment == name)
+        .map(|segment| segment as u8)
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || matches!(c, '_' | '.' | '?' | '@'))
+        && chars.all(|c| {
+            c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '?' | '@' | '$' | '#' | '~')
+        })
+}
+
+/// Removes a `;` comment that is not inside a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+
+    for (index, c) in line.char_indices() {
+        match (quote, c) {
+            (None, ';') => return &line[..index],
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+
+    line
+}
+
+/// Splits at commas that are not inside a string or brackets.
+fn split_operands(text: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, ',') if depth == 0 => {
+                parts.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    parts.push(text[start..].trim());
+    parts
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let text = text.to_ascii_lowercase();
+    let (digits, radix) = if let Some(digits) = text.strip_prefix("0x") {
+        (digits.to_string(), 16)
+    } else if let Some(digits) = text.strip_suffix('h') {
+        (digits.to_string(), 16)
+    } else if let Some(digits) = text.strip_prefix("0b") {
+        (digits.to_string(), 2)
+    } else {
+        (text.clone(), 10)
+    };
+    let digits = digits.replace('_', "");
+
+    if digits.is_empty() {
+        return None;
+    }
+
+    i64::from_str_radix(&digits, radix)
+        .ok()
+        .filter(|value| *value <= u32::MAX as i64)
+        .map(|value| value as i32)
+}
+
+fn parse_string(text: &str) -> Option<Vec<u8>> {
+    let quote = text.chars().next()?;
+
+    if !matches!(quote, '\'' | '"' | '`') || text.len() < 2 || !text.ends_with(quote) {
+        return None;
+    }
+
+    let inner = &text[1..text.len() - 1];
+
+    if inner.contains(quote) {
+        return None;
+    }
+
+    Some(inner.as_bytes().to_vec())
+}
+
+fn parse_term(text: &str) -> Result<Term, String> {
+    if text == "$" {
+        return Ok(Term::Here);
+    }
+
+    if let Some(value) =
+        parse_number(text).filter(|_| text.starts_with(|c: char| c.is_ascii_digit()))
+    {
+        return Ok(Term::Number(value));
+    }
+
+    if let Some(bytes) = parse_string(text).filter(|bytes| (1..=2).contains(&bytes.len())) {
+        let value = bytes
+            .iter()
+            .rev()
+            .fold(0, |value, &byte| value << 8 | byte as i32);
+        return Ok(Term::Number(value));
+    }
+
+    if is_identifier(text) {
+        return Ok(Term::Symbol(text.to_string()));
+    }
+
+    Err(format!("invalid expression '{}'", text))
+}
+
+/// Splits a sum into signed parts, e.g. `bx+si-5` into `+bx`, `+si` and `-5`.
+fn split_terms(text: &str) -> Result<Vec<(bool, &str)>, String> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    let mut quote = None;
+
+    for (index, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '\'' | '"' | '`') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '+' | '-') => {
+                let term = text[start..index].trim();
+
+                if !term.is_empty() {
+                    terms.push((negative, term));
+                } else if !terms.is_empty() || index > 0 && !text[..index].trim().is_empty() {
+                    return Err(format!("invalid expression '{}'", text));
+                }
+
+                negative = c == '-';
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    let term = text[start..].trim();
+
+    if term.is_empty() {
+        return Err(format!("invalid expression '{}'", text));
+    }
+
+    terms.push((negative, term));
+    Ok(terms)
+}
+
+pub fn parse_expression(text: &str) -> Result<Expression, String> {
+    let terms = split_terms(text)?
+        .into_iter()
+        .map(|(negative, term)| Ok((negative, parse_term(term)?)))
+        .collect::<Result<_, String>>()?;
+
+    Ok(Expression { terms })
+}
+
+/// Maps the registers of an effective address to the R/M field value.
+fn effective_address(registers: &[&str]) -> Option<u8> {
+    let mut sorted = registers.to_vec();
+    sorted.sort_unstable();
+
+    Some(match sorted.as_slice() {
+        ["bx", "si"] => effective::BX_PLUS_SI,
+        ["bx", "di"] => effective::BX_PLUS_DI,
+        ["bp", "si"] => effective::BP_PLUS_SI,
+        ["bp", "di"] => effective::BP_PLUS_DI,
+        ["si"] => effective::SI,
+        ["di"] => effective::DI,
+        ["bp"] => effective::BP_OR_DIRECT_ADDRESS,
+        ["bx"] => effective::BX,
+        _ => return None,
+    })
+}
+
+fn parse_memory(text: &str, segment: Option<u8>, size: Option<Size>) -> Result<Operand, String> {
+    let inner = text
+        .strip_prefix('[')
+        .and_then(|text| text.strip_suffix(']'))
+        .ok_or_else(|| format!("invalid memory operand '{}'", text))?
+        .trim();
+    let (segment, inner) = match inner.split_once(':') {
+        Some((name, rest)) => match segment_register(name.trim()) {
+            Some(segment) => (Some(segment), rest.trim()),
+            None => return Err(format!("invalid segment '{}'", name)),
+        },
+        None => (segment, inner),
+    };
+
+    let mut registers = Vec::new();
+    let mut terms = Vec::new();
+
+    for (negative, term) in split_terms(inner)? {
+        if matches!(term, "bx" | "bp" | "si" | "di") {
+            if negative {
+                return Err(format!("can not subtract register '{}'", term));
+            }
+            registers.push(term);
+        } else {
+            terms.push((negative, parse_term(term)?));
+        }
+    }
+
+    let base = match registers.as_slice() {
+        [] => None,
+        registers => Some(
+            effective_address(registers)
+                .ok_or_else(|| format!("invalid effective address '{}'", inner))?,
+        ),
+    };
+
+    Ok(Operand::Memory {
+        segment,
+        base,
+        displacement: Expression { terms },
+        size,
+    })
+}
+
+/// Parses an operand, returning it together with whether it was marked
+/// `near`.
+fn parse_operand(text: &str) -> Result<(Operand, bool), String> {
+    let mut text = text.trim();
+    let mut size = None;
+    let mut near = false;
+
+    loop {
+        let (word, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+
+        match word.to_ascii_lowercase().as_str() {
+            "byte" => size = Some(Size::Byte),
+            "word" => size = Some(Size::Word),
+            "far" | "dword" => size = Some(Size::Far),
+            "near" => near = true,
+            "short" | "strict" => {}
+            _ => break,
+        }
+
+        text = rest.trim();
+    }
+
+    let lower = text.to_ascii_lowercase();
+
+    if let Some(register) = BYTE_REGISTERS.iter().position(|&name| name == lower) {
+        return Ok((Operand::Byte(register as u8), near));
+    }
+
+    if let Some(register) = WORD_REGISTERS.iter().position(|&name| name == lower) {
+        return Ok((Operand::Word(register as u8), near));
+    }
+
+    if let Some(segment) = segment_register(&lower) {
+        return Ok((Operand::Segment(segment), near));
+    }
+
+    if lower.ends_with(']') {
+        let (segment, memory) = match lower.split_once('[') {
+            Some((prefix, _)) if !prefix.trim().is_empty() => {
+                let name = prefix.trim().trim_end_matches(':').trim();
+                let segment =
+                    segment_register(name).ok_or_else(|| format!("invalid segment '{}'", name))?;
+                (Some(segment), &text[text.find('[').unwrap()..])
+            }
+            _ => (None, text),
+        };
+
+        return Ok((parse_memory(memory, segment, size)?, near));
+    }
+
+    if let Some((segment, offset)) = text.split_once(':') {
+        return Ok((
+            Operand::Far {
+                segment: parse_expression(segment.trim())?,
+                offset: parse_expression(offset.trim())?,
+            },
+            near,
+        ));
+    }
+
+    Ok((
+        Operand::Immediate {
+            value: parse_expression(text)?,
+            size,
+        },
+        near,
+    ))
+}
+
+fn parse_data(text: &str) -> Result<Vec<DataItem>, String> {
+    split_operands(text)
+        .into_iter()
+        .map(|item| match parse_string(item) {
+            Some(bytes) => Ok(DataItem::String(bytes)),
+            None => Ok(DataItem::Value(parse_expression(item)?)),
+        })
+        .collect()
+}
+
+/// Parses a line of source into statements, e.g. a label followed by an
+/// instruction.
+pub fn parse_line(line: &str) -> Result<Vec<Statement>, String> {
+    let mut text = strip_comment(line).trim();
+    let mut statements = Vec::new();
+
+    // A label followed by a colon, unless the colon belongs to an operand
+    if let Some((name, rest)) = text.split_once(':') {
+        let name = name.trim();
+
+        if is_identifier(name) && segment_register(name).is_none() {
+            statements.push(Statement::Label(name.to_string()));
+            text = rest.trim();
+        }
+    }
+
+    if text.is_empty() {
+        return Ok(statements);
+    }
+
+    let (first, rest) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let rest = rest.trim();
+
+    // `name equ value` and labels without a colon followed by data
+    if let Some((directive, value)) = rest.split_once(char::is_whitespace) {
+        match directive.to_ascii_lowercase().as_str() {
+            "equ" if is_identifier(first) => {
+                statements.push(Statement::Equ(
+                    first.to_string(),
+                    parse_expression(value.trim())?,
+                ));
+                return Ok(statements);
+            }
+            "db" | "dw" if is_identifier(first) => {
+                statements.push(Statement::Label(first.to_string()));
+                return parse_line(rest).map(|rest| [statements, rest].concat());
+            }
+            _ => {}
+        }
+    }
+
+    let mut words = text;
+    let mut prefixes = Vec::new();
+
+    loop {
+        let (word, rest) = words.split_once(char::is_whitespace).unwrap_or((words, ""));
+
+        match lookup(PREFIXES, &word.to_ascii_lowercase()) {
+            Some(prefix) => {
+                prefixes.push(prefix);
+                words = rest.trim();
+            }
+            None => break,
+        }
+    }
+
+    if words.is_empty() {
+        statements.push(Statement::Instruction {
+            prefixes,
+            opcode: None,
+            operands: Vec::new(),
+            near: false,
+        });
+        return Ok(statements);
+    }
+
+    let (mnemonic, rest) = words.split_once(char::is_whitespace).unwrap_or((words, ""));
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    let rest = rest.trim();
+
+    match mnemonic.as_str() {
+        "bits" => match rest {
+            "16" => {}
+            _ => return Err(format!("unsupported mode 'bits {}'", rest)),
+        },
+        "org" => statements.push(Statement::Org(parse_expression(rest)?)),
+        "db" | "dw" => statements.push(Statement::Data {
+            word: mnemonic == "dw",
+            items: parse_data(rest)?,
+        }),
+        mnemonic => {
+            let opcode = lookup(MNEMONICS, mnemonic)
+                .ok_or_else(|| format!("unknown instruction '{}'", mnemonic))?;
+            let mut operands = Vec::new();
+            let mut near = false;
+
+            if !rest.is_empty() {
+                for operand in split_operands(rest) {
+                    let (operand, is_near) = parse_operand(operand)?;
+                    operands.push(operand);
+                    near |= is_near;
+                }
+            }
+
+            statements.push(Statement::Instruction {
+                prefixes,
+                opcode: Some(opcode),
+                operands,
+                near,
+            });
+        }
+    }
+
+    Ok(statements)
+}
+
+#[test]
+fn test_parse_line() {
+    use crate::instructions::common::{register, segment_register};
+
+    let symbol = |name: &str| Expression {
+        terms: vec![(false, Term::Symbol(name.to_string()))],
+    };
+
+    assert_eq!(
+        parse_line("loc_0: mov [bp+di-2], byte 0x07 ; comment").unwrap(),
+        vec![
+            Statement::Label("loc_0".to_string()),
+            Statement::Instruction {
+                prefixes: vec![],
+                opcode: Some(Opcode::MOV),
+                operands: vec![
+                    Operand::Memory {
+                        segment: None,
+                        base: Some(effective::BP_PLUS_DI),
+                        displacement: Expression {
+                            terms: vec![(true, Term::Number(2))]
+                        },
+                        size: None,
+                    },
+                    Operand::Immediate {
+                        value: Expression::number(7),
+                        size: Some(Size::Byte),
+                    },
+                ],
+                near: false,
+            }
+        ]
+    );
+    assert_eq!(
+        parse_line("lock rep sub es:[bx+si], al").unwrap(),
+        vec![Statement::Instruction {
+            prefixes: vec![Opcode::LOCK, Opcode::REP],
+            opcode: Some(Opcode::SUB),
+            operands: vec![
+                Operand::Memory {
+                    segment: Some(segment_register::ES),
+                    base: Some(effective::BX_PLUS_SI),
+                    displacement: Expression { terms: vec![] },
+                    size: None,
+                },
+                Operand::Byte(register::AX),
+            ],
+            near: false,
+        }]
+    );
+    assert_eq!(
+        parse_line("jmp 789:34").unwrap(),
+        vec![Statement::Instruction {
+            prefixes: vec![],
+            opcode: Some(Opcode::JMP),
+            operands: vec![Operand::Far {
+                segment: Expression::number(789),
+                offset: Expression::number(34),
+            }],
+            near: false,
+        }]
+    );
+    assert_eq!(
+        parse_line("message equ 0x010c").unwrap(),
+        vec![Statement::Equ(
+            "message".to_string(),
+            Expression::number(0x10c)
+        )]
+    );
+    assert_eq!(
+        parse_line("db 'It', 0x27, \"s\"").unwrap(),
+        vec![Statement::Data {
+            word: false,
+            items: vec![
+                DataItem::String(b"It".to_vec()),
+                DataItem::Value(Expression::number(0x27)),
+                DataItem::String(b"s".to_vec()),
+            ],
+        }]
+    );
+    assert_eq!(
+        parse_line("mov ax, [counter]").unwrap()[0],
+        Statement::Instruction {
+            prefixes: vec![],
+            opcode: Some(Opcode::MOV),
+            operands: vec![
+                Operand::Word(register::AX),
+                Operand::Memory {
+                    segment: None,
+                    base: None,
+                    displacement: symbol("counter"),
+                    size: None,
+                },
+            ],
+            near: false,
+        }
+    );
+    assert!(parse_line("mov ax, [bx+bp]").is_err());
+    assert!(parse_line("bits 32").is_err());
+}
@@ -0,0 +1,1121 @@
+use std::{
+    error::Error,
+    fmt, fs,
+    io::{self, stdin, stdout, BufRead, BufReader, IsTerminal, Read, Write},
+    net::TcpListener,
+    path::Path,
+};
+
+use de8086::{
+    analysis::{coverage, strings::COM_ORIGIN},
+    call_graph,
+    debugger::{gdb::GdbStub, Debugger},
+    decoded_length, disassemble_with_coverage, disassemble_with_origin,
+    emulator::{
+        coverage::Coverage,
+        dos::{Dos, Exit, COM_OFFSET},
+        io::Ports,
+        memory::Memory,
+        mz::is_mz,
+        trace,
+        video::Adapter,
+        ExecError, ExecErrorKind, Machine, LOAD_SEGMENT,
+    },
+    info::Info,
+    listing::Listing,
+    run_streaming,
+    shell::Shell,
+    symbols::SymbolMap,
+    verify::verify_with_origin,
+    writer::WriterOptions,
+};
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_DECODE: i32 = 3;
+
+/// Instructions `exec` runs without `--max-steps`.
+const DEFAULT_MAX_STEPS: u64 = 1_000_000;
+
+pub const USAGE: &str = "\
+Usage: de8086 [command] [options] <file>
+
+Commands:
+  disasm    disassemble into NASM source or a listing (the default)
+  info      summarize what the file decodes to
+  exec      run the file and print the registers
+  verify    check that the disassembly reassembles to the same bytes
+  hexdump   dump the bytes in hex and ASCII
+  shell     explore the file with commands read from stdin
+  debug     run the file under a debugger with commands read from stdin
+  help      show this text
+
+A file name of - reads stdin.
+
+Options:
+  -o, --output <file>   write to the file instead of stdout
+  --start <n>           skip the bytes before offset n
+  --end <n>             stop before offset n
+  --length <n>          stop after n bytes
+  --org <n>             address the file is loaded at
+  --cpu <8086|8088>     processor, accepted for compatibility as both
+                        decode and run the same instructions
+  --syntax <nasm>       assembler syntax, accepted for compatibility as
+                        nasm is the only one
+  --format <format>     asm or listing for disasm, text or json for info
+  --symbols <file>      names, comments and data ranges
+  --undocumented        decode undocumented opcodes
+  -v, --verbose         write the bits of each instruction
+  --faithful            keep non-canonical encodings
+  --stream              disassemble while reading
+  --cfg <directory>     write the control flow graph of each function
+  --call-graph [dot]    print the call graph
+  --max-steps <n>       instructions exec runs at most, 1000000 by default
+  --trace               write each instruction exec runs with the registers
+                        and flags it changed
+  --dos                 run a .com or MZ program with the console on stdin
+                        and stdout, exiting with its return code
+  --args <text>         command tail of the DOS program
+  --sandbox <directory> directory the DOS program can open files in
+  --dump <file>         write the memory to the file after exec
+  --a20                 let addresses past 1 MiB reach the high memory area
+  --screen <text|ansi>  print the text mode screen after exec
+  --mda                 use the monochrome screen at B000 instead of B800
+  --coverage <file>     write the disassembly with the hits of each
+                        instruction exec runs to the file
+  --lcov <file>         write the coverage of that disassembly for lcov
+  --script <file>       read the debugger commands from the file
+  --gdb <port>          serve the program to gdb on the local port instead
+
+Numbers are decimal, or hexadecimal with 0x. Usage errors exit with 2 and
+bytes that can not be decoded with 3.";
+
+#[derive(Debug, PartialEq)]
+pub struct CliError {
+    pub message: String,
+    /// Exit code of the process.
+    pub code: i32,
+}
+
+impl Error for CliError {}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error: {}", self.message)?;
+
+        if self.code == EXIT_USAGE {
+            write!(f, "\nRun `de8086 help` for usage.")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl From<io::Error> for CliError {
+    fn from(error: io::Error) -> Self {
+        failure(error.to_string())
+    }
+}
+
+impl From<Box<dyn Error>> for CliError {
+    fn from(error: Box<dyn Error>) -> Self {
+        failure(error.to_string())
+    }
+}
+
+fn failure(message: impl Into<String>) -> CliError {
+    CliError {
+        message: message.into(),
+        code: EXIT_FAILURE,
+    }
+}
+
+fn usage<T>(message: impl Into<String>) -> Result<T, CliError> {
+    Err(CliError {
+        message: message.into(),
+        code: EXIT_USAGE,
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Command {
+    Disasm,
+    Info,
+    Exec,
+    Verify,
+    Hexdump,
+    Shell,
+    Debug,
+    Help,
+}
+
+/// How `--screen` prints the screen.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Screen {
+    Text,
+    Ansi,
+}
+
+/// The processor of `--cpu`. The 8088 only differs from the 8086 in its
+/// bus, which is not emulated.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Cpu {
+    I8086,
+    I8088,
+}
+
+/// The assembler syntax of `--syntax`.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Syntax {
+    Nasm,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Format {
+    Asm,
+    Listing,
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Options {
+    command: Command,
+    /// File name, or `-` for stdin.
+    input: String,
+    output: Option<String>,
+    start: usize,
+    end: Option<usize>,
+    length: Option<usize>,
+    origin: usize,
+    cpu: Cpu,
+    syntax: Syntax,
+    format: Option<Format>,
+    symbols: Option<String>,
+    verbose: bool,
+    faithful: bool,
+    undocumented: bool,
+    stream: bool,
+    cfg: Option<String>,
+    call_graph: Option<bool>,
+    max_steps: u64,
+    trace: bool,
+    dos: bool,
+    command_tail: String,
+    sandbox: Option<String>,
+    dump: Option<String>,
+    a20: bool,
+    screen: Option<Screen>,
+    adapter: Adapter,
+    coverage: Option<String>,
+    lcov: Option<String>,
+    script: Option<String>,
+    gdb: Option<u16>,
+}
+
+impl Options {
+    fn writer_options(&self) -> WriterOptions {
+        WriterOptions {
+            verbose: self.verbose,
+            faithful: self.faithful,
+            undocumented: self.undocumented,
+        }
+    }
+
+    /// Address of the first selected byte. Without `--org`, a selection
+    /// starting past the first byte is addressed as in a `.com` program,
+    /// like the whole file is when finding strings.
+    fn origin(&self) -> usize {
+        match (self.origin, self.start) {
+            (0, 0) => 0,
+            (0, start) => COM_ORIGIN + start,
+            (origin, start) => origin + start,
+        }
+    }
+
+    /// Offset after the last selected byte, if the selection ends early.
+    fn end(&self) -> Option<usize> {
+        self.end
+            .or_else(|| self.length.map(|length| self.start.saturating_add(length)))
+    }
+}
+
+fn number(option: &str, value: &str) -> Result<usize, CliError> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.or_else(|_| usage(format!("invalid number {} for {}", value, option)))
+}
+
+fn option_value<'a>(
+    args: &mut impl Iterator<Item = &'a String>,
+    option: &str,
+) -> Result<String, CliError> {
+    match args.next() {
+        Some(value) => Ok(value.clone()),
+        None => usage(format!("{} needs a value", option)),
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, CliError> {
+    let mut args = args.iter().peekable();
+    let command = match args.peek().map(|arg| arg.as_str()) {
+        Some("disasm") => Some(Command::Disasm),
+        Some("info") => Some(Command::Info),
+        Some("exec") => Some(Command::Exec),
+        Some("verify") => Some(Command::Verify),
+        Some("hexdump") => Some(Command::Hexdump),
+        Some("shell") => Some(Command::Shell),
+        Some("debug") => Some(Command::Debug),
+        Some("help" | "-h" | "--help") => Some(Command::Help),
+        _ => None,
+    };
+
+    if command.is_some() {
+        args.next();
+    }
+
+    // Without a command, the file comes first like in `de8086 <file> -v`
+    let mut options = Options {
+        command: command.unwrap_or(Command::Disasm),
+        input: String::new(),
+        output: None,
+        start: 0,
+        end: None,
+        length: None,
+        origin: 0,
+        cpu: Cpu::I8086,
+        syntax: Syntax::Nasm,
+        format: None,
+        symbols: None,
+        verbose: false,
+        faithful: false,
+        undocumented: false,
+        stream: false,
+        cfg: None,
+        call_graph: None,
+        max_steps: DEFAULT_MAX_STEPS,
+        trace: false,
+        dos: false,
+        command_tail: String::new(),
+        sandbox: None,
+        dump: None,
+        a20: false,
+        screen: None,
+        adapter: Adapter::Cga,
+        coverage: None,
+        lcov: None,
+        script: None,
+        gdb: None,
+    };
+    let mut input = None;
+
+    if options.command == Command::Help {
+        return Ok(options);
+    }
+
+    while let Some(arg) = args.next() {
+        let option = arg.as_str();
+        let mut value = || option_value(&mut args, option);
+
+        match option {
+            "-o" | "--output" => options.output = Some(value()?),
+            "--start" => options.start = number(option, &value()?)?,
+            "--end" => options.end = Some(number(option, &value()?)?),
+            "--length" => options.length = Some(number(option, &value()?)?),
+            "--org" => options.origin = number(option, &value()?)?,
+            "--cpu" => {
+                options.cpu = match value()?.as_str() {
+                    "8086" => Cpu::I8086,
+                    "8088" => Cpu::I8088,
+                    cpu => return usage(format!("unsupported cpu {}, use 8086 or 8088", cpu)),
+                }
+            }
+            "--syntax" => {
+                options.syntax = match value()?.as_str() {
+                    "nasm" => Syntax::Nasm,
+                    syntax => return usage(format!("unsupported syntax {}, use nasm", syntax)),
+                }
+            }
+            "--format" => {
+                options.format = Some(match value()?.as_str() {
+                    "asm" => Format::Asm,
+                    "listing" => Format::Listing,
+                    "text" => Format::Text,
+                    "json" => Format::Json,
+                    format => return usage(format!("unknown format {}", format)),
+                })
+            }
+            "--symbols" => options.symbols = Some(value()?),
+            "--max-steps" => options.max_steps = number(option, &value()?)? as u64,
+            "-v" | "--verbose" => options.verbose = true,
+            "--faithful" => options.faithful = true,
+            "--undocumented" => options.undocumented = true,
+            "--stream" => options.stream = true,
+            "--trace" => options.trace = true,
+            "--dos" => options.dos = true,
+            "--args" => options.command_tail = value()?,
+            "--sandbox" => options.sandbox = Some(value()?),
+            "--dump" => options.dump = Some(value()?),
+            "--a20" => options.a20 = true,
+            "--screen" => {
+                options.screen = Some(match value()?.as_str() {
+                    "text" => Screen::Text,
+                    "ansi" => Screen::Ansi,
+                    screen => return usage(format!("unknown screen format {}", screen)),
+                })
+            }
+            "--mda" => options.adapter = Adapter::Mda,
+            "--coverage" => options.coverage = Some(value()?),
+            "--lcov" => options.lcov = Some(value()?),
+            "--script" => options.script = Some(value()?),
+            "--gdb" => match u16::try_from(number(option, &value()?)?) {
+                Ok(port) => options.gdb = Some(port),
+                Err(_) => return usage("--gdb needs a port below 65536"),
+            },
+            "--cfg" => options.cfg = Some(value()?),
+            "--call-graph" => {
+                let dot = args.peek().is_some_and(|arg| *arg == "dot");
+
+                if dot {
+                    args.next();
+                }
+
+                options.call_graph = Some(dot);
+            }
+            "--verify" if command.is_none() => options.command = Command::Verify,
+            _ if option.starts_with('-') && option != "-" => {
+                return usage(format!("unknown option {}", option))
+            }
+            _ => {
+                if let Some(first) = input.replace(option.to_string()) {
+                    return usage(format!("expected one file, got {} and {}", first, option));
+                }
+            }
+        }
+    }
+
+    let Some(input) = input else {
+        return usage("missing file");
+    };
+
+    options.input = input;
+    validate(&options)?;
+
+    Ok(options)
+}
+
+fn validate(options: &Options) -> Result<(), CliError> {
+    if options.end.is_some() && options.length.is_some() {
+        return usage("--end and --length can not be used together");
+    }
+
+    if options.end.is_some_and(|end| end < options.start) {
+        return usage("--end is before --start");
+    }
+
+    let formats: &[Format] = match options.command {
+        Command::Disasm => &[Format::Asm, Format::Listing],
+        Command::Info => &[Format::Text, Format::Json],
+        _ => &[],
+    };
+
+    if let Some(format) = options.format.filter(|format| !formats.contains(format)) {
+        return usage(
+            format!(
+                "--format {:?} can not be used with {:?}",
+                format, options.command
+            )
+            .to_lowercase(),
+        );
+    }
+
+    if options.stream && (options.origin != 0 || options.format == Some(Format::Listing)) {
+        return usage("--stream only writes assembly without --org");
+    }
+
+    if options.trace && options.command != Command::Exec {
+        return usage("--trace can only be used with exec");
+    }
+
+    if (options.dump.is_some() || options.a20) && options.command != Command::Exec {
+        return usage("--dump and --a20 can only be used with exec");
+    }
+
+    if (options.screen.is_some() || options.adapter == Adapter::Mda)
+        && options.command != Command::Exec
+    {
+        return usage("--screen and --mda can only be used with exec");
+    }
+
+    if options.coverage.is_some() && options.command != Command::Exec {
+        return usage("--coverage can only be used with exec");
+    }
+
+    if options.lcov.is_some() && options.coverage.is_none() {
+        return usage("--lcov counts the lines of the --coverage disassembly, so it needs one");
+    }
+
+    if options.dos && !matches!(options.command, Command::Exec | Command::Debug) {
+        return usage("--dos can only be used with exec and debug");
+    }
+
+    if options.dos && options.origin != 0 {
+        return usage("--dos loads the program at its own address, so it takes no --org");
+    }
+
+    if options.dos && options.trace {
+        return usage("--dos programs can not be traced");
+    }
+
+    if (options.script.is_some() || options.gdb.is_some()) && options.command != Command::Debug {
+        return usage("--script and --gdb can only be used with debug");
+    }
+
+    if options.script.is_some() && options.gdb.is_some() {
+        return usage("--script and --gdb can not be used together");
+    }
+
+    if !options.dos && (options.sandbox.is_some() || !options.command_tail.is_empty()) {
+        return usage("--args and --sandbox can only be used with --dos");
+    }
+
+    if options.command == Command::Shell && options.input == "-" {
+        return usage("the shell reads its commands from stdin, so it can not read the file");
+    }
+
+    if options.command == Command::Debug
+        && options.script.is_none()
+        && options.gdb.is_none()
+        && options.input == "-"
+    {
+        return usage("the debugger reads its commands from stdin without --script");
+    }
+
+    Ok(())
+}
+
+fn file_name(input: &str) -> &str {
+    match input {
+        "-" => "stdin",
+        _ => Path::new(input)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(""),
+    }
+}
+
+/// Returns the name of the `--cfg` file of a function, with the characters
+/// that could leave the directory replaced.
+fn dot_file_name(function: &str) -> String {
+    let name: String = function
+        .chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || matches!(c, '_' | '@' | '$') {
+                true => c,
+                false => '_',
+            },
+        )
+        .collect();
+
+    format!("{}.dot", name)
+}
+
+fn open(input: &str) -> Result<Box<dyn Read>, CliError> {
+    match input {
+        "-" => Ok(Box::new(stdin())),
+        _ => match fs::File::open(input) {
+            Ok(file) => Ok(Box::new(BufReader::new(file))),
+            Err(error) => Err(failure(format!("can not read {}: {}", input, error))),
+        },
+    }
+}
+
+/// Reads the selected bytes of the input.
+fn read_input(options: &Options) -> Result<Vec<u8>, CliError> {
+    let mut bytes = Vec::new();
+
+    open(&options.input)?.read_to_end(&mut bytes)?;
+
+    if options.start > bytes.len() {
+        return usage(format!(
+            "--start {:#06x} is past the end of the input, which is {} bytes",
+            options.start,
+            bytes.len()
+        ));
+    }
+
+    let end = options
+        .end()
+        .map_or(bytes.len(), |end| end.min(bytes.len()));
+
+    bytes.truncate(end);
+    bytes.drain(..options.start);
+
+    Ok(bytes)
+}
+
+fn create_output(options: &Options) -> Result<Box<dyn Write>, CliError> {
+    match &options.output {
+        Some(output) => match fs::File::create(output) {
+            Ok(file) => Ok(Box::new(io::BufWriter::new(file))),
+            Err(error) => Err(failure(format!("can not write {}: {}", output, error))),
+        },
+        None => Ok(Box::new(stdout())),
+    }
+}
+
+fn load_symbols(options: &Options) -> Result<SymbolMap, CliError> {
+    match &options.symbols {
+        Some(file_name) => Ok(SymbolMap::load(file_name)?.with_origin(options.origin())),
+        None => Ok(SymbolMap::new()),
+    }
+}
+
+fn undecoded(options: &Options, decoded: usize, length: usize) -> Result<(), CliError> {
+    match length - decoded {
+        0 => Ok(()),
+        count => Err(CliError {
+            message: format!(
+                "{} {} from {:#06x} can not be decoded",
+                count,
+                match count {
+                    1 => "byte",
+                    _ => "bytes",
+                },
+                options.origin() + decoded
+            ),
+            code: EXIT_DECODE,
+        }),
+    }
+}
+
+/// Runs the command line arguments after the program name.
+pub fn run(args: &[String]) -> Result<(), CliError> {
+    let options = parse_args(args)?;
+
+    match options.command {
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Disasm if options.stream => stream(&options),
+        Command::Disasm => disasm(&options),
+        Command::Info => info(&options),
+        Command::Exec => exec(&options),
+        Command::Verify => verify(&options),
+        Command::Hexdump => hexdump(&options),
+        Command::Shell => shell(&options),
+        Command::Debug => debug(&options),
+    }
+}
+
+fn stream(options: &Options) -> Result<(), CliError> {
+    let mut reader = open(&options.input)?;
+
+    io::copy(
+        &mut reader.by_ref().take(options.start as u64),
+        &mut io::sink(),
+    )?;
+
+    let reader: Box<dyn Read> = match options.end() {
+        Some(end) => Box::new(reader.take((end - options.start) as u64)),
+        None => reader,
+    };
+    let remaining = run_streaming(
+        file_name(&options.input),
+        reader,
+        create_output(options)?,
+        options.writer_options(),
+    )?;
+
+    undecoded(options, 0, remaining)
+}
+
+fn disasm(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+    let symbols = load_symbols(options)?;
+
+    if let Some(directory) = &options.cfg {
+        let graph = call_graph(&bytes, &symbols, options.undocumented, options.origin());
+
+        fs::create_dir_all(directory)?;
+
+        for function in &graph.functions {
+            let path = Path::new(directory).join(dot_file_name(&function.name));
+            let mut file = io::BufWriter::new(fs::File::create(path)?);
+
+            graph.write_function_dot(function, &mut file)?;
+            file.flush()?;
+        }
+
+        return Ok(());
+    }
+
+    let mut out = create_output(options)?;
+
+    if let Some(dot) = options.call_graph {
+        let graph = call_graph(&bytes, &symbols, options.undocumented, options.origin());
+
+        match dot {
+            true => graph.write_dot(&mut out)?,
+            false => graph.write_text(&mut out)?,
+        }
+
+        return Ok(out.flush()?);
+    }
+
+    if options.format == Some(Format::Listing) {
+        let mut listing = Listing::new(&bytes)
+            .with_symbols(&symbols)
+            .with_origin(options.origin());
+
+        if options.undocumented {
+            listing = listing.with_undocumented();
+        }
+
+        listing.write_instructions(&mut out, 0, usize::MAX)?;
+    } else {
+        let output = disassemble_with_origin(
+            file_name(&options.input),
+            &bytes,
+            options.writer_options(),
+            &symbols,
+            options.origin(),
+        )?;
+
+        out.write_all(&output)?;
+    }
+
+    out.flush()?;
+
+    // Faithful mode writes the bytes that can not be decoded as data
+    if options.faithful && options.format != Some(Format::Listing) {
+        return Ok(());
+    }
+
+    let decoded = decoded_length(&bytes, &symbols, options.undocumented, options.origin());
+
+    undecoded(options, decoded, bytes.len())
+}
+
+fn info(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+    let symbols = load_symbols(options)?;
+    let info = Info::collect(&bytes, &symbols, options.undocumented, options.origin());
+    let mut out = create_output(options)?;
+    let file_name = file_name(&options.input);
+
+    match options.format {
+        Some(Format::Json) => info.write_json(&mut out, file_name)?,
+        _ => info.write_text(&mut out, file_name)?,
+    }
+
+    Ok(out.flush()?)
+}
+
+fn exec_error(error: Box<dyn Error>) -> CliError {
+    let code = match error.downcast_ref::<ExecError>().map(|error| error.kind) {
+        Some(ExecErrorKind::Undecodable) => EXIT_DECODE,
+        _ => EXIT_FAILURE,
+    };
+
+    CliError {
+        message: error.to_string(),
+        code,
+    }
+}
+
+/// Loads a `.com` program, or an MZ one if it starts with the signature.
+fn load_dos<R: BufRead, W: Write>(
+    options: &Options,
+    dos: Dos<R, W>,
+    bytes: &[u8],
+) -> Result<Dos<R, W>, CliError> {
+    let mut dos = match &options.sandbox {
+        Some(sandbox) => dos.with_sandbox(sandbox),
+        None => dos,
+    };
+
+    match is_mz(bytes) {
+        true => dos.load_exe(bytes, &options.command_tail)?,
+        false => dos.load_com(bytes, &options.command_tail)?,
+    }
+
+    Ok(dos)
+}
+
+/// Writes the memory to the `--dump` file, if there is one.
+fn dump_memory(options: &Options, memory: &Memory) -> Result<(), CliError> {
+    if let Some(path) = &options.dump {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+
+        memory.dump(&mut out)?;
+        out.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Returns the screen in the `--screen` format, or nothing without one.
+fn screen(options: &Options, memory: &Memory) -> String {
+    match options.screen {
+        Some(Screen::Text) => options.adapter.text(memory),
+        Some(Screen::Ansi) => options.adapter.ansi(memory),
+        None => String::new(),
+    }
+}
+
+/// Writes the disassembly of the program loaded at `base` with the hits of
+/// its instructions to the `--coverage` file, and its lcov tracefile to the
+/// `--lcov` one.
+fn write_coverage(
+    options: &Options,
+    bytes: &[u8],
+    coverage: Option<&Coverage>,
+    base: u32,
+    origin: usize,
+) -> Result<(), CliError> {
+    let (Some(path), Some(coverage)) = (&options.coverage, coverage) else {
+        return Ok(());
+    };
+    let symbols = load_symbols(options)?;
+    let output = disassemble_with_coverage(
+        file_name(&options.input),
+        bytes,
+        options.writer_options(),
+        &symbols,
+        origin,
+        &coverage.offsets(base, bytes.len()),
+    )?;
+
+    fs::write(path, &output)?;
+
+    if let Some(lcov) = &options.lcov {
+        fs::write(
+            lcov,
+            coverage::lcov(path, &String::from_utf8_lossy(&output)),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Runs a DOS program, writing only its output and exiting with its return
+/// code.
+fn exec_dos(options: &Options, bytes: &[u8]) -> Result<(), CliError> {
+    if options.coverage.is_some() && is_mz(bytes) {
+        return usage("--coverage can not map an MZ program back to its file");
+    }
+
+    let mut dos = Dos::new(stdin().lock(), create_output(options)?);
+
+    dos.machine = dos.machine.with_io(Ports::pc(stdout()));
+
+    if options.coverage.is_some() {
+        dos.machine = dos.machine.with_coverage();
+    }
+    dos.machine.memory.set_a20(options.a20);
+    options.adapter.hook(&mut dos.machine);
+
+    let mut dos = load_dos(options, dos, bytes)?;
+    let base = dos
+        .machine
+        .memory
+        .address(dos.machine.registers.cs, COM_OFFSET);
+    let exit = dos.run(options.max_steps);
+
+    dump_memory(options, &dos.machine.memory)?;
+    write_coverage(
+        options,
+        bytes,
+        dos.machine.coverage(),
+        base,
+        COM_OFFSET as usize,
+    )?;
+
+    let screen = screen(options, &dos.machine.memory);
+
+    dos.output_mut().write_all(screen.as_bytes())?;
+    dos.output_mut().flush()?;
+
+    match exit.map_err(exec_error)? {
+        Exit::Code(0) => Ok(()),
+        Exit::Code(code) => Err(CliError {
+            message: String::new(),
+            code: code as i32,
+        }),
+        Exit::Stopped(stop) => Err(failure(format!(
+            "the program {} after {} instructions without exiting",
+            stop, dos.machine.steps
+        ))),
+    }
+}
+
+fn exec(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+
+    if options.dos {
+        return exec_dos(options, &bytes);
+    }
+
+    if options.origin() + bytes.len() > 0x10000 {
+        return usage("the program does not fit in a segment after --org");
+    }
+
+    let mut machine = Machine::new().with_io(Ports::pc(stdout()));
+    let mut out = create_output(options)?;
+
+    if options.undocumented {
+        machine = machine.with_undocumented();
+    }
+
+    if options.coverage.is_some() {
+        machine = machine.with_coverage();
+    }
+
+    machine.memory.set_a20(options.a20);
+    options.adapter.hook(&mut machine);
+    machine.load(&bytes, LOAD_SEGMENT, options.origin() as u16);
+
+    let result = match options.trace {
+        true => trace::run(&mut machine, options.max_steps, &mut out),
+        false => machine.run(options.max_steps).map_err(Into::into),
+    };
+
+    if let Ok(stop) = &result {
+        writeln!(out, "{} after {} instructions", stop, machine.steps)?;
+    }
+
+    writeln!(out, "{}", machine.registers)?;
+    write!(out, "{}", screen(options, &machine.memory))?;
+    out.flush()?;
+    dump_memory(options, &machine.memory)?;
+    write_coverage(
+        options,
+        &bytes,
+        machine.coverage(),
+        machine
+            .memory
+            .address(LOAD_SEGMENT, options.origin() as u16),
+        options.origin(),
+    )?;
+
+    result.map(|_| ()).map_err(exec_error)
+}
+
+fn verify(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+    let symbols = load_symbols(options)?;
+    let mut out = create_output(options)?;
+
+    verify_with_origin(&bytes, options.writer_options(), &symbols, options.origin())?;
+
+    writeln!(
+        out,
+        "{} reassembles to the same bytes",
+        file_name(&options.input)
+    )?;
+
+    Ok(out.flush()?)
+}
+
+fn hexdump(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+    let mut out = create_output(options)?;
+
+    Listing::new(&bytes)
+        .with_origin(options.origin())
+        .write_dump(&mut out, 0, bytes.len())?;
+
+    Ok(out.flush()?)
+}
+
+fn shell(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+    let mut shell = Shell::new(bytes, load_symbols(options)?);
+    let mut out = create_output(options)?;
+
+    if options.undocumented {
+        shell = shell.with_undocumented();
+    }
+
+    if stdin().is_terminal() && options.output.is_none() {
+        shell = shell.with_prompt();
+    }
+
+    shell.run(stdin().lock(), &mut out)?;
+
+    Ok(out.flush()?)
+}
+
+/// Loads the program to debug like `exec` or `exec --dos` would.
+fn load_debuggee<R: BufRead, W: Write>(
+    options: &Options,
+    mut dos: Dos<R, W>,
+    bytes: &[u8],
+) -> Result<Dos<R, W>, CliError> {
+    if options.undocumented {
+        dos.machine = dos.machine.with_undocumented();
+    }
+
+    if options.dos {
+        return load_dos(options, dos, bytes);
+    }
+
+    if options.origin() + bytes.len() > 0x10000 {
+        return usage("the program does not fit in a segment after --org");
+    }
+
+    dos.machine
+        .load(bytes, LOAD_SEGMENT, options.origin() as u16);
+
+    Ok(dos)
+}
+
+/// Serves the program to one gdb connection, with the console of the
+/// program on stdin and stdout.
+fn debug_gdb(options: &Options, bytes: &[u8], port: u16) -> Result<(), CliError> {
+    let dos = load_debuggee(options, Dos::new(stdin().lock(), stdout()), bytes)?;
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+
+    eprintln!("Waiting for gdb on {}", listener.local_addr()?);
+
+    let (stream, _) = listener.accept()?;
+
+    Ok(GdbStub::new(dos).serve(stream)?)
+}
+
+fn debug(options: &Options) -> Result<(), CliError> {
+    let bytes = read_input(options)?;
+
+    if let Some(port) = options.gdb {
+        return debug_gdb(options, &bytes, port);
+    }
+
+    let dos = load_debuggee(options, Dos::new(io::empty(), Vec::new()), &bytes)?;
+    let mut debugger = Debugger::new(dos).with_step_limit(options.max_steps);
+    let mut out = create_output(options)?;
+
+    if options.undocumented {
+        debugger = debugger.with_undocumented();
+    }
+
+    match &options.script {
+        Some(script) => debugger.run(BufReader::new(open(script)?), &mut out)?,
+        None => {
+            if stdin().is_terminal() && options.output.is_none() {
+                debugger = debugger.with_prompt();
+            }
+
+            debugger.run(stdin().lock(), &mut out)?;
+        }
+    }
+
+    Ok(out.flush()?)
+}
+
+#[test]
+fn test_parse_args() {
+    let parse = |line: &str| {
+        let args: Vec<String> = line.split_whitespace().map(String::from).collect();
+
+        parse_args(&args)
+    };
+
+    let options = parse("test/kitchen_sink -v --verify").unwrap();
+
+    assert_eq!(options.command, Command::Verify);
+    assert_eq!(options.input, "test/kitchen_sink");
+    assert!(options.verbose);
+
+    let options = parse("disasm - --start 0x10 --length 32 --org 256 --format listing").unwrap();
+
+    assert_eq!(options.input, "-");
+    assert_eq!((options.start, options.end()), (0x10, Some(0x30)));
+    assert_eq!(options.origin(), 0x110);
+    assert_eq!(parse("a --start 0x10").unwrap().origin(), 0x110);
+    assert_eq!(parse("a").unwrap().origin(), 0);
+    assert_eq!(dot_file_name("sub_0104"), "sub_0104.dot");
+    assert_eq!(dot_file_name("../x/y"), "___x_y.dot");
+    assert_eq!(options.format, Some(Format::Listing));
+
+    let options = parse("disasm a --call-graph dot").unwrap();
+
+    assert_eq!(options.call_graph, Some(true));
+    assert_eq!(parse("help").unwrap().command, Command::Help);
+
+    let options = parse("a --cpu 8088 --syntax nasm").unwrap();
+
+    assert_eq!((options.cpu, options.syntax), (Cpu::I8088, Syntax::Nasm));
+
+    let message = |line: &str| parse(line).unwrap_err().message;
+
+    assert_eq!(message("info"), "missing file");
+    assert_eq!(message("info a b"), "expected one file, got a and b");
+    assert_eq!(message("a --start"), "--start needs a value");
+    assert_eq!(message("a --start ten"), "invalid number ten for --start");
+    assert_eq!(
+        message("a --end 4 --length 2"),
+        "--end and --length can not be used together"
+    );
+    assert_eq!(
+        message("a --cpu 286"),
+        "unsupported cpu 286, use 8086 or 8088"
+    );
+    assert_eq!(
+        message("exec a --format json"),
+        "--format json can not be used with exec"
+    );
+    assert_eq!(message("verify a --verify"), "unknown option --verify");
+    assert_eq!(
+        message("shell a --script b"),
+        "--script and --gdb can only be used with debug"
+    );
+    assert_eq!(
+        message("debug -"),
+        "the debugger reads its commands from stdin without --script"
+    );
+
+    assert_eq!(
+        message("exec a.com --dos --org 0x100"),
+        "--dos loads the program at its own address, so it takes no --org"
+    );
+    assert_eq!(
+        message("exec a.com --dos --trace"),
+        "--dos programs can not be traced"
+    );
+
+    let options = parse("debug a.exe --dos --script b").unwrap();
+
+    assert_eq!(options.command, Command::Debug);
+    assert_eq!(options.script.as_deref(), Some("b"));
+    assert_eq!(parse("debug - --gdb 1234").unwrap().gdb, Some(1234));
+    assert_eq!(
+        message("debug a --gdb 1234 --script b"),
+        "--script and --gdb can not be used together"
+    );
+    assert_eq!(
+        parse("exec a --dump b --a20").unwrap().dump.as_deref(),
+        Some("b")
+    );
+    assert_eq!(
+        message("debug a --a20 --script b"),
+        "--dump and --a20 can only be used with exec"
+    );
+    assert_eq!(
+        parse("exec a --screen ansi --mda").unwrap().adapter,
+        Adapter::Mda
+    );
+    assert_eq!(message("exec a --screen vga"), "unknown screen format vga");
+    assert_eq!(
+        message("exec a --lcov b"),
+        "--lcov counts the lines of the --coverage disassembly, so it needs one"
+    );
+    assert_eq!(parse("a --frobnicate").unwrap_err().code, EXIT_USAGE);
+}
@@ -0,0 +1,622 @@
+pub mod gdb;
+
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    emulator::{
+        dos::{Dos, Exit},
+        memory::physical,
+    },
+    instructions::opcode::Opcode,
+    listing::Listing,
+    symbols::parse_address,
+};
+
+/// Instructions `continue` runs before giving up, unless changed with
+/// `with_step_limit`.
+const DEFAULT_STEP_LIMIT: u64 = 1_000_000;
+
+/// Instructions written by `disasm` without a count.
+const DEFAULT_DISASM_COUNT: usize = 8;
+
+/// Units written by `x` without a count.
+const DEFAULT_EXAMINE_COUNT: usize = 16;
+
+/// Longest instruction with prefixes read for `disasm`.
+const MAX_LINE_LENGTH: u16 = 21;
+
+const HELP: &str = "\
+Addresses are seg:off or an offset, where both parts are hexadecimal or a
+register, like ds:si or 1000:0100. Counts are decimal.
+  break [addr]        stop before the instruction at addr, or list breakpoints
+  delete <addr>       remove the breakpoint at addr
+  watch <addr>        stop when the word at addr changes, ds by default
+  step [n], s [n]     execute n instructions
+  next, n             execute an instruction, running calls to their return
+  continue, c         run until a breakpoint, a watch or the program ends
+  regs, r             write the registers
+  x/<n><b|w> <addr>   examine n bytes or words of memory, ds by default
+  disasm [addr] [n]   disassemble n instructions, cs:ip by default
+  help                show this text
+  quit                leave the debugger";
+
+#[derive(Debug)]
+pub struct DebuggerError {
+    pub message: String,
+}
+
+impl Error for DebuggerError {}
+
+impl fmt::Display for DebuggerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error: {}", self.message)
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, DebuggerError> {
+    Err(DebuggerError {
+        message: message.into(),
+    })
+}
+
+/// A word of memory that stops the program when it changes.
+struct Watch {
+    segment: u16,
+    offset: u16,
+    value: u16,
+}
+
+/// Where running the program stops besides breakpoints and watches.
+enum Until {
+    Steps(u64),
+    /// The return address of a call, reached with the stack popped.
+    Return {
+        cs: u16,
+        ip: u16,
+        sp: u16,
+    },
+    Stopped,
+}
+
+/// Commands for running a loaded program one instruction at a time. The
+/// program writes to the same output and reads an empty console.
+pub struct Debugger {
+    dos: Dos<io::Empty, Vec<u8>>,
+    breakpoints: Vec<(u16, u16)>,
+    watches: Vec<Watch>,
+    /// How the program ended, after which it can not run again.
+    exit: Option<Exit>,
+    step_limit: u64,
+    undocumented: bool,
+    prompt: bool,
+}
+
+impl Debugger {
+    /// Debugs the program already loaded into the machine.
+    pub fn new(dos: Dos<io::Empty, Vec<u8>>) -> Self {
+        Self {
+            dos,
+            breakpoints: Vec::new(),
+            watches: Vec::new(),
+            exit: None,
+            step_limit: DEFAULT_STEP_LIMIT,
+            undocumented: false,
+            prompt: false,
+        }
+    }
+
+    /// Sets how many instructions `continue` and `next` run at most.
+    pub fn with_step_limit(mut self, step_limit: u64) -> Self {
+        self.step_limit = step_limit;
+        self
+    }
+
+    /// Also decodes the opcodes the 8086 executes without them being
+    /// documented, see `Instruction::parse_undocumented`.
+    pub fn with_undocumented(mut self) -> Self {
+        self.dos.machine = self.dos.machine.with_undocumented();
+        self.undocumented = true;
+        self
+    }
+
+    /// Writes a prompt before reading each command, for interactive use.
+    pub fn with_prompt(mut self) -> Self {
+        self.prompt = true;
+        self
+    }
+
+    /// Runs the commands read from the input until it ends or a `quit`.
+    /// Errors in commands are written to the output.
+    pub fn run(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        let mut lines = input.lines();
+
+        loop {
+            if self.prompt {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+
+            match self.execute(&line?, out) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(error) => writeln!(out, "{}", error)?,
+            }
+        }
+    }
+
+    /// Runs a command, returning whether to read another one.
+    pub fn execute(&mut self, line: &str, out: &mut impl Write) -> Result<bool, DebuggerError> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        let result = match command {
+            "break" | "b" => self.add_breakpoint(&arguments, out),
+            "delete" => self.delete(&arguments),
+            "watch" => self.watch(&arguments, out),
+            "step" | "s" => {
+                let count = Self::count(arguments.first(), 1)?;
+
+                self.resume(Until::Steps(count as u64), out)
+            }
+            "next" | "n" => self.next(out),
+            "continue" | "c" => self.resume(Until::Stopped, out),
+            "regs" | "r" => writeln!(out, "{}", self.dos.machine.registers).map_err(Into::into),
+            "disasm" => self.disasm(&arguments, out),
+            "help" => writeln!(out, "{}", HELP).map_err(Into::into),
+            "quit" | "q" => return Ok(false),
+            _ if command == "x" || command.starts_with("x/") => {
+                self.examine(&command[1..], &arguments, out)
+            }
+            _ => error(format!("unknown command {}", command)),
+        };
+
+        result.map(|_| true)
+    }
+
+    fn count(argument: Option<&&str>, default: usize) -> Result<usize, DebuggerError> {
+        match argument {
+            Some(argument) => argument
+                .parse()
+                .or_else(|_| error(format!("invalid count {}", argument))),
+            None => Ok(default),
+        }
+    }
+
+    /// Parses a part of an address, which is a register or a number.
+    fn value(&self, part: &str, segment: bool) -> Result<u16, DebuggerError> {
+        let registers = &self.dos.machine.registers;
+        let register = match segment {
+            true => match part {
+                "cs" => Some(registers.cs),
+                "ds" => Some(registers.ds),
+                "es" => Some(registers.es),
+                "ss" => Some(registers.ss),
+                _ => None,
+            },
+            false => match part {
+                "ax" => Some(registers.ax),
+                "bx" => Some(registers.bx),
+                "cx" => Some(registers.cx),
+                "dx" => Some(registers.dx),
+                "sp" => Some(registers.sp),
+                "bp" => Some(registers.bp),
+                "si" => Some(registers.si),
+                "di" => Some(registers.di),
+                "ip" => Some(registers.ip),
+                _ => None,
+            },
+        };
+
+        match register.or_else(|| parse_address(part).and_then(|value| value.try_into().ok())) {
+            Some(value) => Ok(value),
+            None => error(format!("invalid address {}", part)),
+        }
+    }
+
+    /// Parses `seg:off` or an offset in the default segment, optionally in
+    /// brackets.
+    fn address(&self, argument: Option<&&str>, segment: u16) -> Result<(u16, u16), DebuggerError> {
+        let Some(argument) = argument else {
+            return error("missing address");
+        };
+        let argument = argument
+            .strip_prefix('[')
+            .and_then(|argument| argument.strip_suffix(']'))
+            .unwrap_or(argument);
+
+        match argument.split_once(':') {
+            Some((segment, offset)) => Ok((self.value(segment, true)?, self.value(offset, false)?)),
+            None => Ok((segment, self.value(argument, false)?)),
+        }
+    }
+
+    fn add_breakpoint(
+        &mut self,
+        arguments: &[&str],
+        out: &mut impl Write,
+    ) -> Result<(), DebuggerError> {
+        if arguments.is_empty() {
+            for (segment, offset) in &self.breakpoints {
+                writeln!(out, "Breakpoint at {:04x}:{:04x}", segment, offset)?;
+            }
+
+            return Ok(());
+        }
+
+        let address = self.address(arguments.first(), self.dos.machine.registers.cs)?;
+
+        if !self.breakpoints.contains(&address) {
+            self.breakpoints.push(address);
+        }
+
+        writeln!(out, "Breakpoint at {:04x}:{:04x}", address.0, address.1)?;
+
+        Ok(())
+    }
+
+    fn delete(&mut self, arguments: &[&str]) -> Result<(), DebuggerError> {
+        let address = self.address(arguments.first(), self.dos.machine.registers.cs)?;
+        let Some(index) = self.breakpoints.iter().position(|&other| other == address) else {
+            return error(format!(
+                "no breakpoint at {:04x}:{:04x}",
+                address.0, address.1
+            ));
+        };
+
+        self.breakpoints.remove(index);
+
+        Ok(())
+    }
+
+    fn read_byte(&self, segment: u16, offset: u16) -> u8 {
+        self.dos.machine.memory.read_byte(physical(segment, offset))
+    }
+
+    fn read_word(&self, segment: u16, offset: u16) -> u16 {
+        u16::from_le_bytes([
+            self.read_byte(segment, offset),
+            self.read_byte(segment, offset.wrapping_add(1)),
+        ])
+    }
+
+    fn watch(&mut self, arguments: &[&str], out: &mut impl Write) -> Result<(), DebuggerError> {
+        let (segment, offset) = self.address(arguments.first(), self.dos.machine.registers.ds)?;
+        let value = self.read_word(segment, offset);
+
+        self.watches.push(Watch {
+            segment,
+            offset,
+            value,
+        });
+        writeln!(out, "Watch {:04x}:{:04x} = {:#06x}", segment, offset, value)?;
+
+        Ok(())
+    }
+
+    /// Writes the watched words that changed and remembers their values,
+    /// returning whether any did.
+    fn check_watches(&mut self, out: &mut impl Write) -> io::Result<bool> {
+        let mut changed = false;
+
+        for index in 0..self.watches.len() {
+            let watch = &self.watches[index];
+            let value = self.read_word(watch.segment, watch.offset);
+
+            if value != watch.value {
+                writeln!(
+                    out,
+                    "Watch {:04x}:{:04x}: {:#06x} -> {:#06x}",
+                    watch.segment, watch.offset, watch.value, value
+                )?;
+                self.watches[index].value = value;
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    /// Returns the length and text of the instruction with its prefixes at
+    /// the address, or a `db` of its first byte if it can not be decoded.
+    fn line(&self, segment: u16, offset: u16) -> (u16, String) {
+        let bytes: Vec<u8> = (0..MAX_LINE_LENGTH)
+            .map(|index| self.read_byte(segment, offset.wrapping_add(index)))
+            .collect();
+        let listing = Listing::new(&bytes).with_origin(offset as usize);
+        let listing = match self.undocumented {
+            true => listing.with_undocumented(),
+            false => listing,
+        };
+
+        match listing.line_at(0) {
+            Some((length, text)) => (length as u16, text),
+            None => (1, format!("db {:#04x}", bytes[0])),
+        }
+    }
+
+    /// Writes the instruction at the address, marking the one at CS:IP.
+    fn write_line(&self, out: &mut impl Write, segment: u16, offset: u16) -> io::Result<u16> {
+        let registers = &self.dos.machine.registers;
+        let (length, text) = self.line(segment, offset);
+        let hex: Vec<String> = (0..length)
+            .map(|index| {
+                format!(
+                    "{:02x}",
+                    self.read_byte(segment, offset.wrapping_add(index))
+                )
+            })
+            .collect();
+        let marker = match (segment, offset) == (registers.cs, registers.ip) {
+            true => "=>",
+            false => "  ",
+        };
+
+        writeln!(
+            out,
+            "{} {:04x}:{:04x}  {:<18}{}",
+            marker,
+            segment,
+            offset,
+            hex.join(" "),
+            text
+        )?;
+
+        Ok(length)
+    }
+
+    fn disasm(&self, arguments: &[&str], out: &mut impl Write) -> Result<(), DebuggerError> {
+        let registers = &self.dos.machine.registers;
+        let (segment, mut offset) = match arguments.first() {
+            Some(_) => self.address(arguments.first(), registers.cs)?,
+            None => (registers.cs, registers.ip),
+        };
+        let count = Self::count(arguments.get(1), DEFAULT_DISASM_COUNT)?;
+
+        for _ in 0..count {
+            let length = self.write_line(out, segment, offset)?;
+
+            offset = offset.wrapping_add(length);
+        }
+
+        Ok(())
+    }
+
+    fn examine(
+        &self,
+        format: &str,
+        arguments: &[&str],
+        out: &mut impl Write,
+    ) -> Result<(), DebuggerError> {
+        let format = format.strip_prefix('/').unwrap_or(format);
+        let (count, unit) = match format.strip_suffix(['b', 'w']) {
+            Some(count) => (count, &format[count.len()..]),
+            None => (format, "b"),
+        };
+        let count = match count {
+            "" => DEFAULT_EXAMINE_COUNT,
+            count => Self::count(Some(&count), 0)?,
+        };
+        let (segment, offset) = self.address(arguments.first(), self.dos.machine.registers.ds)?;
+        let (size, per_line) = match unit {
+            "w" => (2, 8),
+            _ => (1, 16),
+        };
+
+        for line in 0..count.div_ceil(per_line) {
+            let start = offset.wrapping_add((line * per_line * size) as u16);
+            let units = per_line.min(count - line * per_line);
+            let values: Vec<String> = (0..units)
+                .map(|index| {
+                    let offset = start.wrapping_add((index * size) as u16);
+
+                    match size {
+                        2 => format!("{:04x}", self.read_word(segment, offset)),
+                        _ => format!("{:02x}", self.read_byte(segment, offset)),
+                    }
+                })
+                .collect();
+
+            writeln!(out, "{:04x}:{:04x}  {}", segment, start, values.join(" "))?;
+        }
+
+        Ok(())
+    }
+
+    /// Steps over calls by running until they return.
+    fn next(&mut self, out: &mut impl Write) -> Result<(), DebuggerError> {
+        let registers = self.dos.machine.registers;
+        let mut ip = registers.ip;
+
+        // Prefixes are decoded as instructions of their own
+        let is_call = loop {
+            match self.dos.machine.decode(ip) {
+                Ok(instruction) => match instruction.opcode {
+                    Opcode::SEGMENT | Opcode::REP | Opcode::LOCK => ip = ip.wrapping_add(1),
+                    opcode => break opcode == Opcode::CALL,
+                },
+                Err(_) => break false,
+            }
+        };
+        let until = match is_call {
+            true => Until::Return {
+                cs: registers.cs,
+                ip: registers
+                    .ip
+                    .wrapping_add(self.line(registers.cs, registers.ip).0),
+                sp: registers.sp,
+            },
+            false => Until::Steps(1),
+        };
+
+        self.resume(until, out)
+    }
+
+    /// Writes what the program wrote, ending it with a new line.
+    fn write_program_output(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let output = std::mem::take(self.dos.output_mut());
+
+        if !output.is_empty() {
+            out.write_all(&output)?;
+
+            if !output.ends_with(b"\n") {
+                writeln!(out)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the program until it stops for the reason, at a breakpoint or a
+    /// watch, then writes the next instruction.
+    fn resume(&mut self, until: Until, out: &mut impl Write) -> Result<(), DebuggerError> {
+        if let Some(exit) = self.exit {
+            return error(format!("the program has ended: {}", describe(exit)));
+        }
+
+        let mut steps = 0;
+
+        loop {
+            let result = self.dos.step();
+
+            self.write_program_output(out)?;
+
+            let exit = result.or_else(|error| self::error(error.to_string()))?;
+
+            if let Some(exit) = exit {
+                self.exit = Some(exit);
+                writeln!(out, "Program {}", describe(exit))?;
+                return Ok(());
+            }
+
+            steps += 1;
+
+            let registers = &self.dos.machine.registers;
+            let here = (registers.cs, registers.ip);
+            let done = match until {
+                Until::Steps(count) => steps >= count,
+                Until::Return { cs, ip, sp } => here == (cs, ip) && registers.sp >= sp,
+                Until::Stopped => false,
+            };
+
+            if self.check_watches(out)? || done {
+                break;
+            }
+
+            if self.breakpoints.contains(&here) {
+                writeln!(out, "Breakpoint at {:04x}:{:04x}", here.0, here.1)?;
+                break;
+            }
+
+            if steps >= self.step_limit {
+                writeln!(out, "Stopped after {} instructions", steps)?;
+                break;
+            }
+        }
+
+        let registers = &self.dos.machine.registers;
+
+        self.write_line(out, registers.cs, registers.ip)?;
+
+        Ok(())
+    }
+}
+
+fn describe(exit: Exit) -> String {
+    match exit {
+        Exit::Code(code) => format!("exited with code {}", code),
+        Exit::Stopped(stop) => stop.to_string(),
+    }
+}
+
+impl From<io::Error> for DebuggerError {
+    fn from(error: io::Error) -> Self {
+        Self {
+            message: error.to_string(),
+        }
+    }
+}
+
+#[test]
+fn test_debugger() {
+    use crate::{assembler::assemble, emulator::LOAD_SEGMENT};
+
+    let program = assemble(
+        "\
+bits 16
+org 0x100
+mov si, text
+call print
+mov word [counter], 2
+mov ax, 0x4c05
+int 0x21
+print:
+mov ah, 0x09
+mov dx, si
+int 0x21
+ret
+text:
+db 'Hi$'
+counter:
+dw 0
+",
+    )
+    .unwrap();
+    let mut dos = Dos::new(io::empty(), Vec::new());
+
+    dos.load_com(&program, "").unwrap();
+    assert_eq!(dos.machine.registers.cs, LOAD_SEGMENT);
+
+    let mut debugger = Debugger::new(dos);
+    let mut output = Vec::new();
+    let input = "\
+step
+next
+watch 0x11b
+x/4b ds:si
+x/1w [0x11b]
+break 0x10f
+c
+c
+disasm 0x111 2
+c
+regs
+step
+bogus
+quit
+step";
+
+    debugger.run(input.as_bytes(), &mut output).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\
+=> 1000:0103  e8 0b 00          call 0x0111
+Hi
+=> 1000:0106  c7 06 1b 01 02 00 mov [283], word 0x0002
+Watch 1000:011b = 0x0000
+1000:0118  48 69 24 00
+1000:011b  0000
+Breakpoint at 1000:010f
+Watch 1000:011b: 0x0000 -> 0x0002
+=> 1000:010c  b8 05 4c          mov ax, 0x4c05
+Breakpoint at 1000:010f
+=> 1000:010f  cd 21             int 33
+   1000:0111  b4 09             mov ah, 0x09
+   1000:0113  89 f2             mov dx, si
+Program exited with code 5
+ax=4c05 bx=0000 cx=0000 dx=0118 sp=fffe bp=0000 si=0118 di=0000
+cs=1000 ds=1000 es=1000 ss=1000 ip=0111 flags=f002
+Error: the program has ended: exited with code 5
+Error: unknown command bogus
+"
+    );
+}
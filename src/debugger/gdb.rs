@@ -0,0 +1,563 @@
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::TcpStream;
+
+use crate::emulator::{
+    dos::{Dos, Exit},
+    memory::{physical, MEMORY_SIZE},
+};
+
+/// Describes the registers as the i386 core, which gdb requires for its
+/// i8086 architecture. The 16-bit registers are zero extended to 32 bits and
+/// the x87 registers always read as zero.
+pub const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <architecture>i8086</architecture>
+  <feature name="org.gnu.gdb.i386.core">
+    <reg name="eax" bitsize="32" type="int32" regnum="0"/>
+    <reg name="ecx" bitsize="32" type="int32"/>
+    <reg name="edx" bitsize="32" type="int32"/>
+    <reg name="ebx" bitsize="32" type="int32"/>
+    <reg name="esp" bitsize="32" type="data_ptr"/>
+    <reg name="ebp" bitsize="32" type="data_ptr"/>
+    <reg name="esi" bitsize="32" type="int32"/>
+    <reg name="edi" bitsize="32" type="int32"/>
+    <reg name="eip" bitsize="32" type="code_ptr"/>
+    <reg name="eflags" bitsize="32" type="int32"/>
+    <reg name="cs" bitsize="32" type="int32"/>
+    <reg name="ss" bitsize="32" type="int32"/>
+    <reg name="ds" bitsize="32" type="int32"/>
+    <reg name="es" bitsize="32" type="int32"/>
+    <reg name="fs" bitsize="32" type="int32"/>
+    <reg name="gs" bitsize="32" type="int32"/>
+    <reg name="st0" bitsize="80" type="i387_ext"/>
+    <reg name="st1" bitsize="80" type="i387_ext"/>
+    <reg name="st2" bitsize="80" type="i387_ext"/>
+    <reg name="st3" bitsize="80" type="i387_ext"/>
+    <reg name="st4" bitsize="80" type="i387_ext"/>
+    <reg name="st5" bitsize="80" type="i387_ext"/>
+    <reg name="st6" bitsize="80" type="i387_ext"/>
+    <reg name="st7" bitsize="80" type="i387_ext"/>
+    <reg name="fctrl" bitsize="32" type="int" group="float"/>
+    <reg name="fstat" bitsize="32" type="int" group="float"/>
+    <reg name="ftag" bitsize="32" type="int" group="float"/>
+    <reg name="fiseg" bitsize="32" type="int" group="float"/>
+    <reg name="fioff" bitsize="32" type="int" group="float"/>
+    <reg name="foseg" bitsize="32" type="int" group="float"/>
+    <reg name="fooff" bitsize="32" type="int" group="float"/>
+    <reg name="fop" bitsize="32" type="int" group="float"/>
+  </feature>
+</target>
+"#;
+
+/// Registers in `TARGET_XML`.
+const REGISTER_COUNT: usize = 32;
+
+/// Largest packet gdb is told to send.
+const PACKET_SIZE: usize = 0x4000;
+
+/// Instructions `c` runs between checks for a ^C from gdb.
+const INTERRUPT_CHECK_INTERVAL: u64 = 4096;
+
+/// Signals reported in stop replies.
+mod signal {
+    pub const INT: u8 = 2;
+    pub const ILL: u8 = 4;
+    pub const TRAP: u8 = 5;
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(text.get(index..index + 2)?, 16).ok())
+        .collect()
+}
+
+fn parse_number(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// Parses `addr,length` as sent with `m` and `M`.
+fn parse_range(text: &str) -> Option<(u32, u32)> {
+    let (address, length) = text.split_once(',')?;
+
+    Some((parse_number(address)?, parse_number(length)?))
+}
+
+/// Returns the addresses of an `m` or `M` packet range, which wrap around
+/// the 1 MiB address space, if the length fits in a packet.
+fn memory_range(text: &str) -> Option<impl ExactSizeIterator<Item = u32>> {
+    let (address, length) = parse_range(text)?;
+
+    (length as usize <= PACKET_SIZE / 2).then(|| {
+        (0..length).map(move |index| address.wrapping_add(index) & (MEMORY_SIZE as u32 - 1))
+    })
+}
+
+/// What gdb sent.
+enum Received {
+    Packet(String),
+    /// A ^C asking the running program to stop.
+    Interrupt,
+}
+
+/// Serves the machine to gdb over the remote serial protocol. Memory and
+/// breakpoint addresses are physical, so the instruction at CS:IP is at
+/// `$cs * 16 + $eip`.
+pub struct GdbStub<R: BufRead, W: Write> {
+    dos: Dos<R, W>,
+    /// Physical addresses of the breakpoints.
+    breakpoints: Vec<u32>,
+}
+
+impl<R: BufRead, W: Write> GdbStub<R, W> {
+    /// Serves the program already loaded into the machine.
+    pub fn new(dos: Dos<R, W>) -> Self {
+        Self {
+            dos,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn into_dos(self) -> Dos<R, W> {
+        self.dos
+    }
+
+    /// Answers the packets read from the connection until gdb detaches,
+    /// kills the program or disconnects.
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        // Packets are small and answered one at a time
+        stream.set_nodelay(true)?;
+
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        while let Some(received) = Self::receive(&mut reader, &mut writer)? {
+            let Received::Packet(packet) = received else {
+                // The program is not running, so there is nothing to stop
+                continue;
+            };
+
+            let reply = match packet.as_bytes().first() {
+                Some(b'c') => self.resume(&mut reader, &mut writer)?,
+                Some(b'D') => {
+                    Self::send(&mut writer, "OK")?;
+                    return Ok(());
+                }
+                Some(b'k') => return Ok(()),
+                _ => self.reply(&packet),
+            };
+
+            Self::send(&mut writer, &reply)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads a packet and acknowledges it, or returns `None` when the
+    /// connection is closed.
+    fn receive(reader: &mut impl BufRead, writer: &mut impl Write) -> io::Result<Option<Received>> {
+        loop {
+            let mut byte = [0];
+
+            if reader.read(&mut byte)? == 0 {
+                return Ok(None);
+            }
+
+            match byte[0] {
+                0x03 => return Ok(Some(Received::Interrupt)),
+                b'$' => {}
+                // Acknowledgements and anything between packets
+                _ => continue,
+            }
+
+            let mut data = Vec::new();
+            let mut checksum = [0; 2];
+
+            reader.read_until(b'#', &mut data)?;
+            reader.read_exact(&mut checksum)?;
+            data.pop();
+
+            let sum = data.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte));
+            let expected = std::str::from_utf8(&checksum)
+                .ok()
+                .and_then(|checksum| u8::from_str_radix(checksum, 16).ok());
+
+            if expected != Some(sum) {
+                writer.write_all(b"-")?;
+                writer.flush()?;
+                continue;
+            }
+
+            writer.write_all(b"+")?;
+
+            return Ok(Some(Received::Packet(
+                String::from_utf8_lossy(&data).to_string(),
+            )));
+        }
+    }
+
+    fn send(writer: &mut impl Write, data: &str) -> io::Result<()> {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+
+        write!(writer, "${}#{:02x}", data, sum)?;
+        writer.flush()
+    }
+
+    /// Answers a packet that does not run the program.
+    fn reply(&mut self, packet: &str) -> String {
+        let Some(command) = packet.get(..1) else {
+            return String::new();
+        };
+        let arguments = &packet[1..];
+
+        match command {
+            "?" => format!("S{:02x}", signal::TRAP),
+            "g" => hex(&(0..REGISTER_COUNT)
+                .flat_map(|number| self.register(number))
+                .collect::<Vec<u8>>()),
+            "G" => self.write_registers(arguments),
+            "p" => match parse_number(arguments) {
+                Some(number) if (number as usize) < REGISTER_COUNT => {
+                    hex(&self.register(number as usize))
+                }
+                _ => "E01".to_string(),
+            },
+            "P" => self.write_register(arguments),
+            "m" => match memory_range(arguments) {
+                Some(addresses) => hex(&addresses
+                    .map(|address| self.dos.machine.memory.read_byte(address))
+                    .collect::<Vec<u8>>()),
+                None => "E01".to_string(),
+            },
+            "M" => self.write_memory(arguments),
+            "s" => self.step(),
+            "Z" | "z" => self.breakpoint(command == "Z", arguments),
+            "H" => "OK".to_string(),
+            "q" => self.query(arguments),
+            // Empty replies tell gdb the packet is not supported
+            _ => String::new(),
+        }
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            return format!("PacketSize={:x};qXfer:features:read+", PACKET_SIZE);
+        }
+
+        if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, length)) = parse_range(range) else {
+                return "E01".to_string();
+            };
+            let start = (offset as usize).min(TARGET_XML.len());
+            let end = start.saturating_add(length as usize).min(TARGET_XML.len());
+            let more = match end < TARGET_XML.len() {
+                true => 'm',
+                false => 'l',
+            };
+
+            return format!("{}{}", more, &TARGET_XML[start..end]);
+        }
+
+        match query {
+            "Attached" => "1".to_string(),
+            "C" => "QC1".to_string(),
+            "fThreadInfo" => "m1".to_string(),
+            "sThreadInfo" => "l".to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Returns the register numbered like in `TARGET_XML` in target order.
+    fn register(&self, number: usize) -> Vec<u8> {
+        let registers = &self.dos.machine.registers;
+        let value = match number {
+            0..=7 => registers.word(number as u8),
+            8 => registers.ip,
+            9 => registers.flags,
+            10 => registers.cs,
+            11 => registers.ss,
+            12 => registers.ds,
+            13 => registers.es,
+            16..=23 => return vec![0; 10],
+            _ => 0,
+        };
+
+        (value as u32).to_le_bytes().to_vec()
+    }
+
+    /// Sets the register from its bytes, ignoring the ones the 8086 lacks.
+    fn set_register(&mut self, number: usize, bytes: &[u8]) {
+        let registers = &mut self.dos.machine.registers;
+        let value = u16::from_le_bytes([bytes[0], bytes[1]]);
+
+        match number {
+            0..=7 => registers.set_word(number as u8, value),
+            8 => registers.ip = value,
+            9 => registers.set_flags(value),
+            10 => registers.cs = value,
+            11 => registers.ss = value,
+            12 => registers.ds = value,
+            13 => registers.es = value,
+            _ => {}
+        }
+    }
+
+    fn register_size(number: usize) -> usize {
+        match number {
+            16..=23 => 10,
+            _ => 4,
+        }
+    }
+
+    fn write_registers(&mut self, arguments: &str) -> String {
+        let Some(bytes) = parse_hex(arguments) else {
+            return "E01".to_string();
+        };
+        let mut offset = 0;
+
+        for number in 0..REGISTER_COUNT {
+            let size = Self::register_size(number);
+
+            if offset + size > bytes.len() {
+                break;
+            }
+
+            self.set_register(number, &bytes[offset..offset + size]);
+            offset += size;
+        }
+
+        "OK".to_string()
+    }
+
+    fn write_register(&mut self, arguments: &str) -> String {
+        let parsed = arguments
+            .split_once('=')
+            .and_then(|(number, value)| Some((parse_number(number)? as usize, parse_hex(value)?)));
+
+        match parsed {
+            Some((number, bytes)) if number < REGISTER_COUNT && bytes.len() >= 2 => {
+                self.set_register(number, &bytes);
+                "OK".to_string()
+            }
+            _ => "E01".to_string(),
+        }
+    }
+
+    fn write_memory(&mut self, arguments: &str) -> String {
+        let parsed = arguments.split_once(':').and_then(|(range, data)| {
+            let addresses = memory_range(range)?;
+            let bytes = parse_hex(data)?;
+
+            (bytes.len() == addresses.len()).then_some((addresses, bytes))
+        });
+        let Some((addresses, bytes)) = parsed else {
+            return "E01".to_string();
+        };
+
+        for (address, byte) in addresses.zip(bytes) {
+            self.dos.machine.memory.load(address, &[byte]);
+        }
+
+        "OK".to_string()
+    }
+
+    fn breakpoint(&mut self, insert: bool, arguments: &str) -> String {
+        let mut parts = arguments.split(',');
+        let kind = parts.next();
+        let address = parts.next().and_then(parse_number);
+
+        // Only software and hardware execution breakpoints
+        let (Some("0" | "1"), Some(address)) = (kind, address) else {
+            return String::new();
+        };
+
+        self.breakpoints.retain(|&other| other != address);
+
+        if insert {
+            self.breakpoints.push(address);
+        }
+
+        "OK".to_string()
+    }
+
+    /// Executes an instruction, returning the stop reply if it stopped the
+    /// program.
+    fn execute(&mut self) -> Option<String> {
+        match self.dos.step() {
+            Ok(None) => None,
+            Ok(Some(Exit::Code(code))) => Some(format!("W{:02x}", code)),
+            // The program can not continue after halting or its last byte
+            Ok(Some(Exit::Stopped(_))) => Some("W00".to_string()),
+            Err(_) => Some(format!("S{:02x}", signal::ILL)),
+        }
+    }
+
+    fn step(&mut self) -> String {
+        self.execute()
+            .unwrap_or_else(|| format!("S{:02x}", signal::TRAP))
+    }
+
+    /// Runs until a breakpoint, the end of the program or a ^C from gdb.
+    fn resume(
+        &mut self,
+        reader: &mut BufReader<TcpStream>,
+        writer: &mut TcpStream,
+    ) -> io::Result<String> {
+        let mut steps = 0u64;
+
+        loop {
+            if let Some(reply) = self.execute() {
+                return Ok(reply);
+            }
+
+            let registers = &self.dos.machine.registers;
+
+            if self
+                .breakpoints
+                .contains(&physical(registers.cs, registers.ip))
+            {
+                return Ok(format!("S{:02x}", signal::TRAP));
+            }
+
+            steps += 1;
+
+            if steps.is_multiple_of(INTERRUPT_CHECK_INTERVAL) && Self::interrupted(reader, writer)?
+            {
+                return Ok(format!("S{:02x}", signal::INT));
+            }
+        }
+    }
+
+    /// Returns whether gdb sent a ^C, without waiting for one.
+    fn interrupted(reader: &mut BufReader<TcpStream>, writer: &mut TcpStream) -> io::Result<bool> {
+        if reader.buffer().is_empty() {
+            writer.set_nonblocking(true)?;
+
+            let filled = reader.fill_buf().map(|buffer| buffer.len());
+
+            writer.set_nonblocking(false)?;
+
+            match filled {
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(error) => return Err(error),
+                Ok(_) => {}
+            }
+        }
+
+        match reader.buffer().first() {
+            Some(0x03) => {
+                reader.consume(1);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
+
+#[test]
+fn test_gdb_stub() {
+    use crate::{assembler::assemble, emulator::LOAD_SEGMENT};
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    // mov ax, 0x1234; inc word [value]; mov ax, 0x4c02; int 0x21
+    let program = assemble(
+        "\
+bits 16
+org 0x100
+mov ax, 0x1234
+inc word [value]
+mov ax, 0x4c02
+int 0x21
+value:
+dw 0x00ff
+",
+    )
+    .unwrap();
+    let mut dos = Dos::new(io::empty(), Vec::new());
+
+    dos.load_com(&program, "").unwrap();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = std::thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut stub = GdbStub::new(dos);
+
+        stub.serve(stream).unwrap();
+        stub.into_dos()
+    });
+
+    let stream = TcpStream::connect(address).unwrap();
+
+    stream.set_nodelay(true).unwrap();
+
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+    let mut request = |packet: &str| {
+        let sum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+
+        write!(writer, "${}#{:02x}", packet, sum).unwrap();
+
+        let mut reply = Vec::new();
+
+        reader.read_until(b'#', &mut reply).unwrap();
+        reader.read_exact(&mut [0; 2]).unwrap();
+        writer.write_all(b"+").unwrap();
+
+        let reply = String::from_utf8(reply).unwrap();
+
+        // The acknowledgement, then the packet
+        reply
+            .strip_prefix("+$")
+            .and_then(|reply| reply.strip_suffix('#'))
+            .unwrap()
+            .to_string()
+    };
+    let code = physical(LOAD_SEGMENT, 0x100);
+
+    assert_eq!(
+        request("qSupported:xmlRegisters=i386"),
+        "PacketSize=4000;qXfer:features:read+"
+    );
+    assert!(request("qXfer:features:read:target.xml:0,40").starts_with("m<?xml"));
+    assert_eq!(request("?"), "S05");
+
+    let registers = request("g");
+
+    assert_eq!(registers.len(), 176 * 2);
+    // eip and eflags
+    assert_eq!(&registers[64..80], "0001000002f00000");
+    assert_eq!(request(&format!("m{:x},3", code)), "b83412");
+    assert_eq!(request("s"), "S05");
+    assert_eq!(request("p0"), "34120000");
+    assert_eq!(request(&format!("Z0,{:x},1", code + 7)), "OK");
+    assert_eq!(request("c"), "S05");
+    assert_eq!(request("p8"), "07010000");
+    assert_eq!(request(&format!("m{:x},2", code + 12)), "0001");
+    assert_eq!(request(&format!("M{:x},2:0300", code + 12)), "OK");
+    // Ranges wrap around at 1 MiB
+    assert_eq!(request("mfffff,2"), request("mfffff,1") + &request("m0,1"));
+    assert_eq!(request("mffffffff,2"), request("mfffff,2"));
+    assert_eq!(request("m0,2001"), "E01");
+    assert_eq!(request("Mffffffff,1:00"), "OK");
+    assert_eq!(request("M0,2:00"), "E01");
+    assert_eq!(request("P0=ffff0000"), "OK");
+    assert_eq!(request("p0"), "ffff0000");
+    assert_eq!(request("Z2,0,2"), "");
+    assert_eq!(request("c"), "W02");
+    assert_eq!(request("D"), "OK");
+
+    let dos = server.join().unwrap();
+
+    assert_eq!(dos.machine.memory.read_word(code + 12), 3);
+}
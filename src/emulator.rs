@@ -0,0 +1,1330 @@
+pub mod alu;
+pub mod coverage;
+pub mod dos;
+pub mod io;
+pub mod memory;
+pub mod mz;
+pub mod registers;
+pub mod trace;
+pub mod video;
+
+use std::collections::{HashMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+
+use crate::{
+    instructions::{
+        common::{effective, register, segment_register},
+        opcode::Opcode,
+        operand::{Memory as MemoryOperand, Operand, Operation, Size},
+    },
+    Instruction,
+};
+use coverage::Coverage;
+use io::IoBus;
+use memory::Memory;
+use registers::{flags, Registers};
+
+/// Segment flat binaries are loaded into, above the interrupt vectors and
+/// the BIOS data area.
+pub const LOAD_SEGMENT: u16 = 0x1000;
+
+/// Most prefixes read before an instruction.
+const MAX_PREFIXES: u16 = 15;
+
+/// Interrupt raised by `div`, `idiv` and `aam` when the result does not fit.
+pub const DIVIDE_ERROR: u8 = 0;
+
+/// Interrupt raised after each instruction while TF is set.
+pub const SINGLE_STEP: u8 = 1;
+
+pub const BREAKPOINT: u8 = 3;
+
+/// Interrupt raised by `into` when OF is set.
+pub const OVERFLOW: u8 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExecErrorKind {
+    /// The bytes at CS:IP are not an instruction.
+    Undecodable,
+    /// The instruction is decoded, but can not be executed yet.
+    Unsupported,
+}
+
+#[derive(Debug)]
+pub struct ExecError {
+    pub cs: u16,
+    pub ip: u16,
+    pub kind: ExecErrorKind,
+    pub message: String,
+}
+
+impl Error for ExecError {}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Execution error at {:04x}:{:04x}: {}",
+            self.cs, self.ip, self.message
+        )
+    }
+}
+
+/// Why execution stopped without an error.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stop {
+    /// A `hlt` was executed.
+    Halted,
+    /// Execution reached the end of the loaded program.
+    EndOfProgram,
+    /// The given number of instructions were executed.
+    StepLimit,
+    /// An interrupt was raised whose vector is not set and that no handler
+    /// hooks. CS:IP points after the instruction that raised it, so that
+    /// execution can continue once the interrupt is serviced.
+    Interrupt(u8),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Halted => write!(f, "halted"),
+            Stop::EndOfProgram => write!(f, "reached the end of the program"),
+            Stop::StepLimit => write!(f, "reached the step limit"),
+            Stop::Interrupt(number) => write!(f, "stopped at interrupt {:#04x}", number),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Repeat {
+    /// `rep` or `repe`, encoded as 0xf3.
+    WhileEqual,
+    /// `repne`, encoded as 0xf2.
+    WhileNotEqual,
+}
+
+/// The prefixes and location of the instruction being executed.
+#[derive(Debug, Copy, Clone)]
+struct Context {
+    segment: Option<u8>,
+    repeat: Option<Repeat>,
+    /// Offset of the instruction after its prefixes.
+    ip: u16,
+}
+
+/// Services an interrupt in Rust instead of through its vector, like a BIOS
+/// routine that returns with `iret`. Returns why to stop, if anything.
+pub type InterruptHandler = Box<dyn FnMut(&mut Machine) -> Option<Stop> + Send>;
+
+/// An 8086 that executes the instructions decoded by `Instruction::parse`.
+pub struct Machine {
+    pub registers: Registers,
+    pub memory: Memory,
+    /// Physical address after the loaded program.
+    end: Option<u32>,
+    undocumented: bool,
+    halted: bool,
+    /// Interrupt raised by the last instruction.
+    interrupt: Option<u8>,
+    /// External interrupts waiting for IF to be set.
+    pending: VecDeque<u8>,
+    /// Whether external interrupts wait for another instruction, after
+    /// `sti` and loads of SS.
+    inhibit: bool,
+    handlers: HashMap<u8, InterruptHandler>,
+    /// Devices on the I/O ports, without which `in` and `out` are not
+    /// supported.
+    io: Option<Box<dyn IoBus>>,
+    coverage: Option<Coverage>,
+    /// Instructions executed so far.
+    pub steps: u64,
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Machine {
+    pub fn new() -> Self {
+        Self {
+            registers: Registers::default(),
+            memory: Memory::new(),
+            end: None,
+            undocumented: false,
+            halted: false,
+            interrupt: None,
+            pending: VecDeque::new(),
+            inhibit: false,
+            handlers: HashMap::new(),
+            io: None,
+            coverage: None,
+            steps: 0,
+        }
+    }
+
+    /// Services the interrupt with the handler instead of its vector.
+    pub fn hook(
+        &mut self,
+        number: u8,
+        handler: impl FnMut(&mut Machine) -> Option<Stop> + Send + 'static,
+    ) {
+        self.handlers.insert(number, Box::new(handler));
+    }
+
+    /// Removes the handler of the interrupt, so that it goes through its
+    /// vector again.
+    pub fn unhook(&mut self, number: u8) {
+        self.handlers.remove(&number);
+    }
+
+    /// Raises an external interrupt, which is taken before the next
+    /// instruction once IF is set.
+    pub fn request_interrupt(&mut self, number: u8) {
+        self.pending.push_back(number);
+    }
+
+    /// Takes an interrupt: calls its handler, or pushes FLAGS, CS and IP,
+    /// clears IF and TF and jumps through the vector at `number * 4`. An
+    /// unset vector stops the machine instead.
+    fn dispatch(&mut self, number: u8) -> Option<Stop> {
+        if let Some(mut handler) = self.handlers.remove(&number) {
+            let stop = handler(self);
+
+            // Unless the handler replaced itself
+            self.handlers.entry(number).or_insert(handler);
+
+            return stop;
+        }
+
+        let vector = number as u32 * 4;
+        let offset = self.memory.read_word(vector);
+        let segment = self.memory.read_word(vector + 2);
+
+        if (segment, offset) == (0, 0) {
+            return Some(Stop::Interrupt(number));
+        }
+
+        self.push(self.registers.flags);
+        self.push(self.registers.cs);
+        self.push(self.registers.ip);
+        self.registers.set_flag(flags::IF, false);
+        self.registers.set_flag(flags::TF, false);
+        self.registers.cs = segment;
+        self.registers.ip = offset;
+
+        None
+    }
+
+    /// Also executes the opcodes the 8086 decodes without them being
+    /// documented, see `Instruction::parse_undocumented`.
+    pub fn with_undocumented(mut self) -> Self {
+        self.undocumented = true;
+        self
+    }
+
+    /// Connects the I/O ports to the bus, which is advanced after each
+    /// instruction and whose interrupts are taken like external ones.
+    pub fn with_io(mut self, bus: impl IoBus + 'static) -> Self {
+        self.io = Some(Box::new(bus));
+        self
+    }
+
+    /// Counts how many times each instruction is executed.
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(Coverage::new());
+        self
+    }
+
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+
+    /// Advances the devices on the bus, raising the lines they request.
+    fn tick(&mut self) {
+        if let Some(bus) = self.io.as_mut() {
+            let lines = bus.tick();
+
+            if lines != 0 {
+                bus.raise(lines);
+            }
+        }
+    }
+
+    /// Loads a flat binary at the given segment and offset and points all
+    /// segment registers to the segment, with the stack at its top.
+    pub fn load(&mut self, bytes: &[u8], segment: u16, offset: u16) {
+        let address = self.memory.address(segment, offset);
+
+        self.memory.load(address, bytes);
+        self.end = Some(address + bytes.len() as u32);
+        self.registers.cs = segment;
+        self.registers.ds = segment;
+        self.registers.es = segment;
+        self.registers.ss = segment;
+        self.registers.ip = offset;
+        self.registers.sp = 0xfffe;
+        self.halted = false;
+    }
+
+    fn error(&self, ip: u16, kind: ExecErrorKind, message: String) -> ExecError {
+        ExecError {
+            cs: self.registers.cs,
+            ip,
+            kind,
+            message,
+        }
+    }
+
+    /// Decodes the instruction at the offset in the code segment.
+    pub fn decode(&self, ip: u16) -> Result<Instruction, ExecError> {
+        let bytes: Vec<u8> = (0..6)
+            .map(|index| {
+                self.memory.read_byte(
+                    self.memory
+                        .address(self.registers.cs, ip.wrapping_add(index)),
+                )
+            })
+            .collect();
+        let instruction = match self.undocumented {
+            true => Instruction::parse_undocumented(&bytes),
+            false => Instruction::parse(&bytes),
+        };
+
+        instruction.ok_or_else(|| {
+            let hex: Vec<String> = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+
+            self.error(
+                ip,
+                ExecErrorKind::Undecodable,
+                format!("can not decode {}", hex.join(" ")),
+            )
+        })
+    }
+
+    /// Runs until the machine stops or has executed the given number of
+    /// instructions.
+    pub fn run(&mut self, max_steps: u64) -> Result<Stop, ExecError> {
+        for _ in 0..max_steps {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+
+        Ok(Stop::StepLimit)
+    }
+
+    /// Executes the instruction at CS:IP along with its prefixes, returning
+    /// why the machine stopped if it did. While halted, each call advances
+    /// the devices on the bus, so that calling it again waits for them to
+    /// interrupt.
+    pub fn step(&mut self) -> Result<Option<Stop>, ExecError> {
+        // External interrupts are taken between instructions and end a `hlt`
+        if self.registers.flag(flags::IF) && !self.inhibit {
+            let number = match self.pending.pop_front() {
+                Some(number) => Some(number),
+                None => self.io.as_mut().and_then(|bus| bus.acknowledge()),
+            };
+
+            if let Some(number) = number {
+                self.halted = false;
+
+                if let Some(stop) = self.dispatch(number) {
+                    return Ok(Some(stop));
+                }
+            }
+        }
+
+        if self.halted {
+            self.tick();
+            return Ok(Some(Stop::Halted));
+        }
+
+        if self.end == Some(self.memory.address(self.registers.cs, self.registers.ip)) {
+            return Ok(Some(Stop::EndOfProgram));
+        }
+
+        let start = self.registers.ip;
+        let mut context = Context {
+            segment: None,
+            repeat: None,
+            ip: start,
+        };
+
+        let instruction = loop {
+            let instruction = self.decode(context.ip)?;
+
+            match instruction.opcode {
+                Opcode::SEGMENT => context.segment = Some(instruction.input[0] >> 3 & 0b11),
+                Opcode::REP if instruction.input[0] == 0xf2 => {
+                    context.repeat = Some(Repeat::WhileNotEqual)
+                }
+                Opcode::REP => context.repeat = Some(Repeat::WhileEqual),
+                Opcode::LOCK => {}
+                _ => break instruction,
+            }
+
+            context.ip = context.ip.wrapping_add(1);
+
+            if context.ip.wrapping_sub(start) > MAX_PREFIXES {
+                return Err(self.error(
+                    start,
+                    ExecErrorKind::Undecodable,
+                    "too many prefixes".to_string(),
+                ));
+            }
+        };
+
+        let operation = Operation::from_instruction(&instruction).ok_or_else(|| {
+            self.error(
+                context.ip,
+                ExecErrorKind::Undecodable,
+                format!("{} has no operands", instruction.opcode.get_mnemonic()),
+            )
+        })?;
+
+        // The 8086 traps after an instruction that starts with TF set
+        let trap = self.registers.flag(flags::TF);
+
+        self.inhibit = false;
+        self.registers.ip = context.ip.wrapping_add(instruction.length as u16);
+        self.steps += 1;
+
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.record(self.memory.address(self.registers.cs, start));
+        }
+
+        self.execute(&operation, &context)?;
+        self.tick();
+
+        let interrupt = match self.interrupt.take() {
+            Some(number) => Some(number),
+            None if trap => Some(SINGLE_STEP),
+            None => None,
+        };
+
+        if let Some(stop) = interrupt.and_then(|number| self.dispatch(number)) {
+            return Ok(Some(stop));
+        }
+
+        Ok(self.halted.then_some(Stop::Halted))
+    }
+
+    /// Returns the segment and offset of a memory operand.
+    fn address(&self, memory: &MemoryOperand, context: &Context) -> (u16, u16) {
+        let registers = &self.registers;
+        let (offset, default_segment) = match memory.base {
+            None => (0, segment_register::DS),
+            Some(base) => match base {
+                effective::BX_PLUS_SI => (
+                    registers.bx.wrapping_add(registers.si),
+                    segment_register::DS,
+                ),
+                effective::BX_PLUS_DI => (
+                    registers.bx.wrapping_add(registers.di),
+                    segment_register::DS,
+                ),
+                effective::BP_PLUS_SI => (
+                    registers.bp.wrapping_add(registers.si),
+                    segment_register::SS,
+                ),
+                effective::BP_PLUS_DI => (
+                    registers.bp.wrapping_add(registers.di),
+                    segment_register::SS,
+                ),
+                effective::SI => (registers.si, segment_register::DS),
+                effective::DI => (registers.di, segment_register::DS),
+                effective::BP_OR_DIRECT_ADDRESS => (registers.bp, segment_register::SS),
+                _ => (registers.bx, segment_register::DS),
+            },
+        };
+        let segment = context.segment.unwrap_or(default_segment);
+
+        (
+            registers.segment(segment),
+            offset.wrapping_add(memory.displacement as u16),
+        )
+    }
+
+    fn is_word(operand: &Operand) -> bool {
+        match operand {
+            Operand::Byte(_) => false,
+            Operand::Memory(memory) => memory.size != Size::Byte,
+            _ => true,
+        }
+    }
+
+    fn read(&self, operand: &Operand, context: &Context) -> u16 {
+        match *operand {
+            Operand::Byte(number) => self.registers.byte(number) as u16,
+            Operand::Word(number) => self.registers.word(number),
+            Operand::Segment(number) => self.registers.segment(number),
+            Operand::Memory(memory) => {
+                let (segment, offset) = self.address(&memory, context);
+                let address = self.memory.address(segment, offset);
+
+                match memory.size {
+                    Size::Byte => self.memory.read_byte(address) as u16,
+                    _ => self.memory.read_word(address),
+                }
+            }
+            Operand::Immediate(value) => value,
+            Operand::Relative(relative) => context.ip.wrapping_add(relative as u16),
+            Operand::Far { offset, .. } => offset,
+        }
+    }
+
+    fn write(&mut self, operand: &Operand, value: u16, context: &Context) {
+        match *operand {
+            Operand::Byte(number) => self.registers.set_byte(number, value as u8),
+            Operand::Word(number) => self.registers.set_word(number, value),
+            Operand::Segment(number) => self.registers.set_segment(number, value),
+            Operand::Memory(memory) => {
+                let (segment, offset) = self.address(&memory, context);
+                let address = self.memory.address(segment, offset);
+
+                match memory.size {
+                    Size::Byte => self.memory.write_byte(address, value as u8),
+                    _ => self.memory.write_word(address, value),
+                }
+            }
+            Operand::Immediate(_) | Operand::Relative(_) | Operand::Far { .. } => {}
+        }
+    }
+
+    /// Reads the offset and segment of a far pointer in memory.
+    fn read_far(&self, operand: &Operand, context: &Context) -> Option<(u16, u16)> {
+        match *operand {
+            Operand::Memory(memory) => {
+                let (segment, offset) = self.address(&memory, context);
+
+                Some((
+                    self.memory.read_word(self.memory.address(segment, offset)),
+                    self.memory
+                        .read_word(self.memory.address(segment, offset.wrapping_add(2))),
+                ))
+            }
+            Operand::Far { segment, offset } => Some((offset, segment)),
+            _ => None,
+        }
+    }
+
+    pub fn push(&mut self, value: u16) {
+        self.registers.sp = self.registers.sp.wrapping_sub(2);
+        self.memory.write_word(
+            self.memory.address(self.registers.ss, self.registers.sp),
+            value,
+        );
+    }
+
+    pub fn pop(&mut self) -> u16 {
+        let value = self
+            .memory
+            .read_word(self.memory.address(self.registers.ss, self.registers.sp));
+
+        self.registers.sp = self.registers.sp.wrapping_add(2);
+        value
+    }
+
+    fn condition(&self, opcode: Opcode) -> bool {
+        let flag = |flag| self.registers.flag(flag);
+
+        match opcode {
+            Opcode::JO => flag(flags::OF),
+            Opcode::JNO => !flag(flags::OF),
+            Opcode::JB | Opcode::JC => flag(flags::CF),
+            Opcode::JAE | Opcode::JNC => !flag(flags::CF),
+            Opcode::JE => flag(flags::ZF),
+            Opcode::JNE => !flag(flags::ZF),
+            Opcode::JBE => flag(flags::CF) || flag(flags::ZF),
+            Opcode::JA => !flag(flags::CF) && !flag(flags::ZF),
+            Opcode::JS => flag(flags::SF),
+            Opcode::JNS => !flag(flags::SF),
+            Opcode::JP => flag(flags::PF),
+            Opcode::JNP => !flag(flags::PF),
+            Opcode::JL => flag(flags::SF) != flag(flags::OF),
+            Opcode::JGE => flag(flags::SF) == flag(flags::OF),
+            Opcode::JLE => flag(flags::ZF) || flag(flags::SF) != flag(flags::OF),
+            Opcode::JG => !flag(flags::ZF) && flag(flags::SF) == flag(flags::OF),
+            _ => false,
+        }
+    }
+
+    fn execute(&mut self, operation: &Operation, context: &Context) -> Result<(), ExecError> {
+        let operands = &operation.operands;
+        let first = operands.first();
+        let word = first.is_none_or(Self::is_word);
+        let flags = self.registers.flags;
+
+        match operation.opcode {
+            Opcode::MOV => {
+                let value = self.read(&operands[1], context);
+                self.write(&operands[0], value, context);
+                // So that SP can be loaded before an interrupt uses the stack
+                self.inhibit = operands[0] == Operand::Segment(segment_register::SS);
+            }
+            Opcode::XCHG => {
+                let a = self.read(&operands[0], context);
+                let b = self.read(&operands[1], context);
+                self.write(&operands[0], b, context);
+                self.write(&operands[1], a, context);
+            }
+            Opcode::LEA => {
+                if let Operand::Memory(memory) = operands[1] {
+                    let (_, offset) = self.address(&memory, context);
+                    self.write(&operands[0], offset, context);
+                }
+            }
+            Opcode::LDS | Opcode::LES => {
+                if let Some((offset, segment)) = self.read_far(&operands[1], context) {
+                    self.write(&operands[0], offset, context);
+
+                    match operation.opcode {
+                        Opcode::LDS => self.registers.ds = segment,
+                        _ => self.registers.es = segment,
+                    }
+                }
+            }
+            Opcode::PUSH => {
+                // The 8086 pushes SP after decrementing it
+                let value = match operands[0] {
+                    Operand::Word(register::SP) => self.registers.sp.wrapping_sub(2),
+                    _ => self.read(&operands[0], context),
+                };
+                self.push(value);
+            }
+            Opcode::POP => {
+                let value = self.pop();
+                self.write(&operands[0], value, context);
+                self.inhibit = operands[0] == Operand::Segment(segment_register::SS);
+            }
+            Opcode::PUSHF => self.push(self.registers.flags),
+            Opcode::POPF => {
+                let value = self.pop();
+                self.registers.set_flags(value);
+            }
+            Opcode::LAHF => self.registers.set_byte(0b100, flags as u8),
+            Opcode::SAHF => {
+                let value = flags & 0xff00 | self.registers.byte(0b100) as u16;
+                self.registers.set_flags(value);
+            }
+            Opcode::CBW => self.registers.ax = self.registers.ax as u8 as i8 as i16 as u16,
+            Opcode::CWD => {
+                self.registers.dx = match self.registers.ax & 0x8000 {
+                    0 => 0,
+                    _ => 0xffff,
+                }
+            }
+            Opcode::XLAT => {
+                let segment = context.segment.unwrap_or(segment_register::DS);
+                let offset = self.registers.bx.wrapping_add(self.registers.ax & 0xff);
+                let value = self
+                    .memory
+                    .read_byte(self.memory.address(self.registers.segment(segment), offset));
+                self.registers.set_byte(register::AX, value);
+            }
+            Opcode::SALC => {
+                let value = match self.registers.flag(flags::CF) {
+                    true => 0xff,
+                    false => 0,
+                };
+                self.registers.set_byte(register::AX, value);
+            }
+            Opcode::ADD
+            | Opcode::ADC
+            | Opcode::SUB
+            | Opcode::SBB
+            | Opcode::CMP
+            | Opcode::AND
+            | Opcode::OR
+            | Opcode::XOR
+            | Opcode::TEST => {
+                let a = self.read(&operands[0], context);
+                let b = self.read(&operands[1], context);
+                let carry = self.registers.flag(flags::CF);
+                let (result, flags) = match operation.opcode {
+                    Opcode::ADD => alu::add(a, b, false, word, flags),
+                    Opcode::ADC => alu::add(a, b, carry, word, flags),
+                    Opcode::SUB | Opcode::CMP => alu::sub(a, b, false, word, flags),
+                    Opcode::SBB => alu::sub(a, b, carry, word, flags),
+                    Opcode::AND | Opcode::TEST => alu::logic(a & b, word, flags),
+                    Opcode::OR => alu::logic(a | b, word, flags),
+                    _ => alu::logic(a ^ b, word, flags),
+                };
+
+                if !matches!(operation.opcode, Opcode::CMP | Opcode::TEST) {
+                    self.write(&operands[0], result, context);
+                }
+                self.registers.flags = flags;
+            }
+            Opcode::INC | Opcode::DEC | Opcode::NEG => {
+                let a = self.read(&operands[0], context);
+                let (result, flags) = match operation.opcode {
+                    Opcode::INC => alu::inc(a, word, flags),
+                    Opcode::DEC => alu::dec(a, word, flags),
+                    _ => alu::neg(a, word, flags),
+                };
+
+                self.write(&operands[0], result, context);
+                self.registers.flags = flags;
+            }
+            Opcode::NOT => {
+                let a = self.read(&operands[0], context);
+                self.write(&operands[0], !a, context);
+            }
+            Opcode::SAL
+            | Opcode::SHR
+            | Opcode::SAR
+            | Opcode::ROL
+            | Opcode::ROR
+            | Opcode::RCL
+            | Opcode::RCR
+            | Opcode::SETMO => {
+                let a = self.read(&operands[0], context);
+                let count = self.read(&operands[1], context) as u8;
+                let (result, flags) = alu::shift(operation.opcode, a, count, word, flags);
+
+                self.write(&operands[0], result, context);
+                self.registers.flags = flags;
+            }
+            Opcode::MUL | Opcode::IMUL => {
+                let a = match word {
+                    true => self.registers.ax,
+                    false => self.registers.ax & 0xff,
+                };
+                let b = self.read(&operands[0], context);
+                let signed = operation.opcode == Opcode::IMUL;
+                let (product, flags) = alu::mul(a, b, signed, word, flags);
+
+                self.registers.ax = product as u16;
+
+                if word {
+                    self.registers.dx = (product >> 16) as u16;
+                }
+
+                self.registers.flags = flags;
+            }
+            Opcode::DIV | Opcode::IDIV => {
+                let dividend = match word {
+                    true => (self.registers.dx as u32) << 16 | self.registers.ax as u32,
+                    false => self.registers.ax as u32,
+                };
+                let divisor = self.read(&operands[0], context);
+                let signed = operation.opcode == Opcode::IDIV;
+
+                match alu::div(dividend, divisor, signed, word) {
+                    Some((quotient, remainder)) if word => {
+                        self.registers.ax = quotient;
+                        self.registers.dx = remainder;
+                    }
+                    Some((quotient, remainder)) => {
+                        self.registers.ax = u16::from_le_bytes([quotient as u8, remainder as u8])
+                    }
+                    None => self.interrupt = Some(DIVIDE_ERROR),
+                }
+            }
+            Opcode::AAA | Opcode::AAS => {
+                let (result, flags) = match operation.opcode {
+                    Opcode::AAA => alu::aaa(self.registers.ax, flags),
+                    _ => alu::aas(self.registers.ax, flags),
+                };
+
+                self.registers.ax = result;
+                self.registers.flags = flags;
+            }
+            Opcode::DAA | Opcode::DAS => {
+                let al = self.registers.byte(register::AX);
+                let (result, flags) = match operation.opcode {
+                    Opcode::DAA => alu::daa(al, flags),
+                    _ => alu::das(al, flags),
+                };
+
+                self.registers.set_byte(register::AX, result);
+                self.registers.flags = flags;
+            }
+            Opcode::AAM | Opcode::AAD => {
+                // The base is only encoded when it is not 10
+                let base = match first {
+                    Some(operand) => self.read(operand, context) as u8,
+                    None => 10,
+                };
+                let result = match operation.opcode {
+                    Opcode::AAM => alu::aam(self.registers.byte(register::AX), base, flags),
+                    _ => Some(alu::aad(self.registers.ax, base, flags)),
+                };
+
+                match result {
+                    Some((result, flags)) => {
+                        self.registers.ax = result;
+                        self.registers.flags = flags;
+                    }
+                    None => self.interrupt = Some(DIVIDE_ERROR),
+                }
+            }
+            Opcode::JMP => self.jump(&operands[0], false, context)?,
+            Opcode::CALL => self.jump(&operands[0], true, context)?,
+            Opcode::RET | Opcode::RETF => {
+                self.registers.ip = self.pop();
+
+                if operation.opcode == Opcode::RETF {
+                    self.registers.cs = self.pop();
+                }
+
+                if let Some(operand) = first {
+                    let bytes = self.read(operand, context);
+                    self.registers.sp = self.registers.sp.wrapping_add(bytes);
+                }
+            }
+            Opcode::LOOP | Opcode::LOOPE | Opcode::LOOPNE => {
+                self.registers.cx = self.registers.cx.wrapping_sub(1);
+
+                let zero = self.registers.flag(flags::ZF);
+                let taken = self.registers.cx != 0
+                    && match operation.opcode {
+                        Opcode::LOOPE => zero,
+                        Opcode::LOOPNE => !zero,
+                        _ => true,
+                    };
+
+                if taken {
+                    self.registers.ip = self.read(&operands[0], context);
+                }
+            }
+            Opcode::JCXZ => {
+                if self.registers.cx == 0 {
+                    self.registers.ip = self.read(&operands[0], context);
+                }
+            }
+            opcode if self.is_conditional_jump(opcode) => {
+                if self.condition(opcode) {
+                    self.registers.ip = self.read(&operands[0], context);
+                }
+            }
+            Opcode::MOVSB
+            | Opcode::MOVSW
+            | Opcode::CMPSB
+            | Opcode::CMPSW
+            | Opcode::SCASB
+            | Opcode::SCASW
+            | Opcode::LODSB
+            | Opcode::LODSW
+            | Opcode::STOSB
+            | Opcode::STOSW => self.string(operation.opcode, context),
+            Opcode::CLC => self.registers.set_flag(flags::CF, false),
+            Opcode::STC => self.registers.set_flag(flags::CF, true),
+            Opcode::CMC => self
+                .registers
+                .set_flag(flags::CF, !self.registers.flag(flags::CF)),
+            Opcode::CLD => self.registers.set_flag(flags::DF, false),
+            Opcode::STD => self.registers.set_flag(flags::DF, true),
+            Opcode::CLI => self.registers.set_flag(flags::IF, false),
+            Opcode::STI => {
+                // Interrupts are enabled after the next instruction
+                self.inhibit = !self.registers.flag(flags::IF);
+                self.registers.set_flag(flags::IF, true);
+            }
+            Opcode::NOP | Opcode::WAIT => {}
+            Opcode::HLT => self.halted = true,
+            Opcode::INT => self.interrupt = Some(self.read(&operands[0], context) as u8),
+            Opcode::INT3 => self.interrupt = Some(BREAKPOINT),
+            Opcode::INTO => {
+                if self.registers.flag(flags::OF) {
+                    self.interrupt = Some(OVERFLOW);
+                }
+            }
+            Opcode::IN if self.io.is_some() => {
+                let port = self.read(&operands[1], context);
+                let value = match self.io.as_mut() {
+                    Some(bus) if word => bus.read_word(port),
+                    Some(bus) => bus.read_byte(port) as u16,
+                    None => 0,
+                };
+
+                self.write(&operands[0], value, context);
+            }
+            Opcode::OUT if self.io.is_some() => {
+                let port = self.read(&operands[0], context);
+                let value = self.read(&operands[1], context);
+
+                match self.io.as_mut() {
+                    Some(bus) if Self::is_word(&operands[1]) => bus.write_word(port, value),
+                    Some(bus) => bus.write_byte(port, value as u8),
+                    None => {}
+                }
+            }
+            Opcode::IRET => {
+                self.registers.ip = self.pop();
+                self.registers.cs = self.pop();
+
+                let value = self.pop();
+                self.registers.set_flags(value);
+            }
+            opcode => {
+                return Err(self.error(
+                    context.ip,
+                    ExecErrorKind::Unsupported,
+                    format!("{} is not supported", opcode.get_mnemonic()),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_conditional_jump(&self, opcode: Opcode) -> bool {
+        matches!(
+            opcode,
+            Opcode::JO
+                | Opcode::JNO
+                | Opcode::JB
+                | Opcode::JC
+                | Opcode::JAE
+                | Opcode::JNC
+                | Opcode::JE
+                | Opcode::JNE
+                | Opcode::JBE
+                | Opcode::JA
+                | Opcode::JS
+                | Opcode::JNS
+                | Opcode::JP
+                | Opcode::JNP
+                | Opcode::JL
+                | Opcode::JGE
+                | Opcode::JLE
+                | Opcode::JG
+        )
+    }
+
+    /// Jumps or calls to a relative, near indirect or far target.
+    fn jump(&mut self, operand: &Operand, call: bool, context: &Context) -> Result<(), ExecError> {
+        let far = match operand {
+            Operand::Far { .. } => true,
+            Operand::Memory(memory) => memory.size == Size::Far,
+            _ => false,
+        };
+
+        if far {
+            let (offset, segment) = self.read_far(operand, context).unwrap_or_default();
+
+            if call {
+                self.push(self.registers.cs);
+                self.push(self.registers.ip);
+            }
+
+            self.registers.cs = segment;
+            self.registers.ip = offset;
+        } else {
+            let target = self.read(operand, context);
+
+            if call {
+                self.push(self.registers.ip);
+            }
+
+            self.registers.ip = target;
+        }
+
+        Ok(())
+    }
+
+    /// Executes a string instruction, repeated CX times with a prefix.
+    fn string(&mut self, opcode: Opcode, context: &Context) {
+        let word = matches!(
+            opcode,
+            Opcode::MOVSW | Opcode::CMPSW | Opcode::SCASW | Opcode::LODSW | Opcode::STOSW
+        );
+        let compares = matches!(
+            opcode,
+            Opcode::CMPSB | Opcode::CMPSW | Opcode::SCASB | Opcode::SCASW
+        );
+        let step = match (word, self.registers.flag(flags::DF)) {
+            (false, false) => 1,
+            (true, false) => 2,
+            (false, true) => 1u16.wrapping_neg(),
+            (true, true) => 2u16.wrapping_neg(),
+        };
+
+        loop {
+            if context.repeat.is_some() && self.registers.cx == 0 {
+                break;
+            }
+
+            let source_segment = self
+                .registers
+                .segment(context.segment.unwrap_or(segment_register::DS));
+            let source = self.memory.address(source_segment, self.registers.si);
+            let destination = self.memory.address(self.registers.es, self.registers.di);
+            let read = |memory: &Memory, address| match word {
+                true => memory.read_word(address),
+                false => memory.read_byte(address) as u16,
+            };
+            let accumulator = match word {
+                true => self.registers.ax,
+                false => self.registers.ax & 0xff,
+            };
+
+            match opcode {
+                Opcode::MOVSB | Opcode::MOVSW => {
+                    let value = read(&self.memory, source);
+
+                    match word {
+                        true => self.memory.write_word(destination, value),
+                        false => self.memory.write_byte(destination, value as u8),
+                    }
+                }
+                Opcode::CMPSB | Opcode::CMPSW => {
+                    let a = read(&self.memory, source);
+                    let b = read(&self.memory, destination);
+                    self.registers.flags = alu::sub(a, b, false, word, self.registers.flags).1;
+                }
+                Opcode::SCASB | Opcode::SCASW => {
+                    let b = read(&self.memory, destination);
+                    self.registers.flags =
+                        alu::sub(accumulator, b, false, word, self.registers.flags).1;
+                }
+                Opcode::LODSB | Opcode::LODSW => {
+                    let value = read(&self.memory, source);
+
+                    match word {
+                        true => self.registers.ax = value,
+                        false => self.registers.set_byte(register::AX, value as u8),
+                    }
+                }
+                _ => match word {
+                    true => self.memory.write_word(destination, accumulator),
+                    false => self.memory.write_byte(destination, accumulator as u8),
+                },
+            }
+
+            if matches!(
+                opcode,
+                Opcode::MOVSB
+                    | Opcode::MOVSW
+                    | Opcode::CMPSB
+                    | Opcode::CMPSW
+                    | Opcode::LODSB
+                    | Opcode::LODSW
+            ) {
+                self.registers.si = self.registers.si.wrapping_add(step);
+            }
+
+            if !matches!(opcode, Opcode::LODSB | Opcode::LODSW) {
+                self.registers.di = self.registers.di.wrapping_add(step);
+            }
+
+            let Some(repeat) = context.repeat else {
+                break;
+            };
+
+            self.registers.cx = self.registers.cx.wrapping_sub(1);
+
+            let zero = self.registers.flag(flags::ZF);
+
+            if compares && (repeat == Repeat::WhileEqual) != zero {
+                break;
+            }
+        }
+    }
+}
+
+#[test]
+fn test_machine() {
+    use memory::physical;
+
+    // mov cx, 3; xor ax, ax; loop: add ax, cx; loop loop;
+    // mov [0x0100], ax; mov si, 0x0100; mov di, 0x0102; movsw; hlt
+    let bytes = [
+        0xb9, 0x03, 0x00, 0x31, 0xc0, 0x01, 0xc8, 0xe2, 0xfc, 0xa3, 0x00, 0x01, 0xbe, 0x00, 0x01,
+        0xbf, 0x02, 0x01, 0xa5, 0xf4,
+    ];
+    let mut machine = Machine::new();
+
+    machine.load(&bytes, LOAD_SEGMENT, 0);
+
+    assert_eq!(machine.run(100).unwrap(), Stop::Halted);
+    assert_eq!(machine.registers.ax, 6);
+    assert_eq!(machine.registers.cx, 0);
+    assert_eq!(machine.memory.read_word(physical(LOAD_SEGMENT, 0x0102)), 6);
+    assert_eq!(machine.steps, 13);
+
+    // call to a subroutine that returns; then falls off the end
+    let bytes = [0xe8, 0x01, 0x00, 0x90, 0x40, 0xc3];
+    let mut machine = Machine::new();
+
+    machine.load(&bytes, LOAD_SEGMENT, 0x100);
+
+    // The subroutine returns to the nop, then runs into it again and
+    // returns to wherever the stack points
+    assert_eq!(machine.run(3).unwrap(), Stop::StepLimit);
+    assert_eq!(machine.registers.ip, 0x103);
+    assert_eq!(machine.registers.ax, 1);
+
+    // An interrupt stops the machine after the int
+    let mut machine = Machine::new();
+
+    machine.load(&[0xcd, 0x21, 0xf4], LOAD_SEGMENT, 0);
+
+    assert_eq!(machine.run(10).unwrap(), Stop::Interrupt(0x21));
+    assert_eq!(machine.registers.ip, 2);
+    assert_eq!(machine.run(10).unwrap(), Stop::Halted);
+
+    // Port I/O needs a bus
+    let mut machine = Machine::new();
+
+    machine.load(&[0xec], LOAD_SEGMENT, 0);
+
+    let error = machine.run(1).unwrap_err();
+
+    assert_eq!(error.kind, ExecErrorKind::Unsupported);
+    assert_eq!(
+        error.to_string(),
+        "Execution error at 1000:0000: in is not supported"
+    );
+}
+
+#[test]
+fn test_machine_multiply_and_divide() {
+    use crate::assembler::assemble;
+    use memory::physical;
+
+    let program = assemble(
+        "\
+bits 16
+mov ax, 1000
+mov bx, 300
+mul bx
+mov cx, 7
+div cx
+mov cl, 4
+shl word [value], cl
+mov al, 0x19
+add al, 0x28
+daa
+aam
+mov bl, 0
+div bl
+hlt
+value:
+dw 0x1234
+",
+    )
+    .unwrap();
+    let mut machine = Machine::new();
+
+    machine.load(&program, LOAD_SEGMENT, 0);
+
+    // 300000 / 7 is 42857 remainder 1, then the divide by zero interrupts
+    assert_eq!(machine.run(100).unwrap(), Stop::Interrupt(DIVIDE_ERROR));
+    assert_eq!(machine.registers.dx, 1);
+    // 19 + 28 is 0x47 in BCD, which aam splits into the digits of 71
+    assert_eq!(machine.registers.ax, 0x0701);
+    assert_eq!(
+        machine
+            .memory
+            .read_word(physical(LOAD_SEGMENT, program.len() as u16 - 2)),
+        0x2340
+    );
+    assert_eq!(machine.run(100).unwrap(), Stop::Halted);
+}
+
+#[test]
+fn test_interrupts() {
+    use crate::assembler::assemble;
+
+    let program = assemble(
+        "\
+bits 16
+xor ax, ax
+mov es, ax
+mov word [es:0x0200], handler
+mov [es:0x0202], cs
+mov word [es:0x0000], divide
+mov [es:0x0002], cs
+stc
+int 0x80
+mov dl, 0
+div dl
+int 0x10
+cli
+nop
+sti
+nop
+hlt
+hlt
+handler:
+inc bx
+iret
+divide:
+pop si
+push si
+iret
+",
+    )
+    .unwrap();
+    let mut machine = Machine::new();
+
+    machine.load(&program, LOAD_SEGMENT, 0);
+    machine.hook(0x10, |machine| {
+        machine.registers.cx = 0x1234;
+        None
+    });
+
+    // Up to the int 0x80, which returns with CF restored
+    machine.run(8).unwrap();
+    assert_eq!(machine.registers.cs, LOAD_SEGMENT);
+    assert_eq!(machine.registers.sp, 0xfffe - 6);
+    assert!(!machine.registers.flag(flags::IF));
+    machine.run(2).unwrap();
+    assert_eq!(machine.registers.bx, 1);
+    assert_eq!(machine.registers.sp, 0xfffe);
+    assert!(machine.registers.flag(flags::CF));
+
+    // The divide error pushes the address after the div
+    machine.run(6).unwrap();
+    assert_eq!(machine.registers.si, 0x23);
+    assert_eq!(machine.registers.cx, 0x1234);
+
+    // An external interrupt waits for sti and the instruction after it
+    machine.request_interrupt(0x80);
+    machine.run(2).unwrap();
+    assert_eq!(machine.registers.bx, 1);
+    machine.run(2).unwrap();
+    assert_eq!(machine.registers.bx, 1);
+    assert_eq!(machine.run(1).unwrap(), Stop::StepLimit);
+    assert_eq!(machine.registers.bx, 2);
+    assert_eq!(machine.run(10).unwrap(), Stop::Halted);
+
+    // An interrupt wakes the halted machine and returns after the hlt
+    machine.request_interrupt(0x80);
+    assert_eq!(machine.run(10).unwrap(), Stop::Halted);
+    assert_eq!(machine.registers.ip, 0x2b);
+    assert_eq!(machine.registers.bx, 3);
+
+    // Single steps trap to an unset vector after the instruction that
+    // started with TF set
+    let mut machine = Machine::new();
+
+    machine.load(&[0x9d, 0x90, 0x90], LOAD_SEGMENT, 0);
+    machine.push(flags::TF);
+
+    assert_eq!(machine.run(10).unwrap(), Stop::Interrupt(SINGLE_STEP));
+    assert_eq!(machine.registers.ip, 2);
+}
+
+#[test]
+fn test_machine_io() {
+    use crate::assembler::assemble;
+    use io::{keyboard::Keyboard, pic::Pic, pit::Pit, Ports};
+
+    // The timer interrupts twice after the scan code is read, with the
+    // keyboard interrupt masked
+    let program = assemble(
+        "\
+bits 16
+org 0
+xor ax, ax
+mov es, ax
+mov word [es:0x20], timer
+mov ax, cs
+mov [es:0x22], ax
+mov al, 0x02
+out 0x21, al
+mov al, 0x34
+out 0x43, al
+mov al, 20
+out 0x40, al
+mov al, 0
+out 0x40, al
+in al, 0x60
+mov cl, al
+mov dx, 0x80
+in ax, dx
+mov si, ax
+sti
+wait:
+cmp bx, 2
+jne wait
+cli
+hlt
+timer:
+inc bx
+mov al, 0x20
+out 0x20, al
+iret
+",
+    )
+    .unwrap();
+    let ports = Ports::new()
+        .with_device([io::pic::COMMAND, io::pic::DATA], Pic::new())
+        .with_device(io::pit::PORTS, Pit::new())
+        .with_device([0x60, 0x64], Keyboard::new().with_scan_codes(&[0x1e]));
+    let mut machine = Machine::new().with_io(ports);
+
+    machine.load(&program, LOAD_SEGMENT, 0);
+
+    assert_eq!(machine.run(1000).unwrap(), Stop::Halted);
+    assert_eq!(machine.registers.bx, 2);
+    assert_eq!(machine.registers.byte(register::CX), 0x1e);
+    assert_eq!(machine.registers.si, 0xffff);
+}
+
+#[test]
+fn test_effective_addresses() {
+    use crate::assembler::assemble;
+    use memory::physical;
+
+    // BP based addresses default to SS, the others to DS, unless overridden
+    let program = assemble(
+        "\
+bits 16
+org 0
+mov ax, 0x2000
+mov ss, ax
+mov ax, 0x3000
+mov es, ax
+mov bx, 0x10
+mov bp, 0x20
+mov si, 1
+mov di, 2
+mov byte [bx+si], 1
+mov byte [bp+di+4], 2
+mov byte [bp], 3
+mov byte [es:bp+si], 4
+mov byte [cs:bx+di], 5
+mov byte [di], 6
+mov ax, 0xffff
+mov ds, ax
+mov byte [0x0010], 7
+hlt
+",
+    )
+    .unwrap();
+    let mut machine = Machine::new();
+
+    machine.load(&program, LOAD_SEGMENT, 0);
+
+    assert_eq!(machine.run(100).unwrap(), Stop::Halted);
+
+    let byte = |segment: u16, offset: u16| machine.memory.read_byte(physical(segment, offset));
+
+    assert_eq!(byte(LOAD_SEGMENT, 0x11), 1);
+    assert_eq!(byte(0x2000, 0x26), 2);
+    assert_eq!(byte(0x2000, 0x20), 3);
+    assert_eq!(byte(0x3000, 0x21), 4);
+    assert_eq!(byte(LOAD_SEGMENT, 0x12), 5);
+    assert_eq!(byte(LOAD_SEGMENT, 0x02), 6);
+    // FFFF:0010 wraps around to 0
+    assert_eq!(byte(0, 0), 7);
+
+    // With the A20 line enabled, it is in the high memory area
+    let mut machine = Machine::new();
+
+    machine.memory.set_a20(true);
+    machine.load(&program, LOAD_SEGMENT, 0);
+
+    assert_eq!(machine.run(100).unwrap(), Stop::Halted);
+    assert_eq!(machine.memory.read_byte(0), 0);
+    assert_eq!(machine.memory.read_byte(0x100000), 7);
+}
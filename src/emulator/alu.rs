@@ -0,0 +1,471 @@
+use super::registers::flags::{AF, CF, OF, PF, SF, ZF};
+use crate::instructions::opcode::Opcode;
+
+/// Arithmetic on bytes and words. Each operation takes the current FLAGS and
+/// returns the result along with the new FLAGS. Flags the 8086 leaves
+/// undefined keep their value, except AF after logic operations, which the
+/// 8086 clears.
+fn sign_bit(word: bool) -> u16 {
+    match word {
+        true => 0x8000,
+        false => 0x80,
+    }
+}
+
+fn mask(word: bool) -> u32 {
+    match word {
+        true => 0xffff,
+        false => 0xff,
+    }
+}
+
+fn set(flags: u16, flag: u16, value: bool) -> u16 {
+    match value {
+        true => flags | flag,
+        false => flags & !flag,
+    }
+}
+
+/// Sets SF, ZF and PF from the result. PF only looks at the low byte.
+pub fn sign_zero_parity(result: u16, word: bool, flags: u16) -> u16 {
+    let result = result & mask(word) as u16;
+    let flags = set(flags, SF, result & sign_bit(word) != 0);
+    let flags = set(flags, ZF, result == 0);
+
+    set(flags, PF, (result as u8).count_ones().is_multiple_of(2))
+}
+
+pub fn add(a: u16, b: u16, carry: bool, word: bool, flags: u16) -> (u16, u16) {
+    let full = a as u32 + b as u32 + carry as u32;
+    let result = (full & mask(word)) as u16;
+    let sign = sign_bit(word);
+
+    let flags = set(flags, CF, full > mask(word));
+    let flags = set(flags, AF, (a ^ b ^ result) & 0x10 != 0);
+    let flags = set(flags, OF, (a ^ result) & (b ^ result) & sign != 0);
+
+    (result, sign_zero_parity(result, word, flags))
+}
+
+pub fn sub(a: u16, b: u16, borrow: bool, word: bool, flags: u16) -> (u16, u16) {
+    let full = (a as u32).wrapping_sub(b as u32 + borrow as u32);
+    let result = (full & mask(word)) as u16;
+    let sign = sign_bit(word);
+
+    let flags = set(flags, CF, (b as u32 + borrow as u32) > a as u32);
+    let flags = set(flags, AF, (a ^ b ^ result) & 0x10 != 0);
+    let flags = set(flags, OF, (a ^ b) & (a ^ result) & sign != 0);
+
+    (result, sign_zero_parity(result, word, flags))
+}
+
+/// Flags of `and`, `or`, `xor` and `test`, which clear CF and OF.
+pub fn logic(result: u16, word: bool, flags: u16) -> (u16, u16) {
+    let flags = flags & !(CF | OF | AF);
+
+    (result, sign_zero_parity(result, word, flags))
+}
+
+/// Like `add` of one, but keeps CF.
+pub fn inc(a: u16, word: bool, flags: u16) -> (u16, u16) {
+    let (result, new_flags) = add(a, 1, false, word, flags);
+
+    (result, set(new_flags, CF, flags & CF != 0))
+}
+
+/// Like `sub` of one, but keeps CF.
+pub fn dec(a: u16, word: bool, flags: u16) -> (u16, u16) {
+    let (result, new_flags) = sub(a, 1, false, word, flags);
+
+    (result, set(new_flags, CF, flags & CF != 0))
+}
+
+/// Subtracts from zero, setting CF unless the operand is zero.
+pub fn neg(a: u16, word: bool, flags: u16) -> (u16, u16) {
+    sub(0, a, false, word, flags)
+}
+
+/// Shifts or rotates by the count like the 8086, which does not mask it and
+/// repeats a one-bit step that many times. OF is set by the last step: for
+/// left shifts to the top bit of the result XOR CF, for right shifts to the
+/// XOR of the two top bits. A count of zero changes nothing. The
+/// undocumented SETMO sets all the bits, with the flags of an OR.
+pub fn shift(opcode: Opcode, a: u16, count: u8, word: bool, flags: u16) -> (u16, u16) {
+    let sign = sign_bit(word);
+    let mut result = a & mask(word) as u16;
+    let mut carry = flags & CF != 0;
+
+    if count == 0 {
+        return (result, flags);
+    }
+
+    if opcode == Opcode::SETMO {
+        return logic(mask(word) as u16, word, flags);
+    }
+
+    for _ in 0..count {
+        let top = result & sign != 0;
+        let bottom = result & 1 != 0;
+        let (shifted, out) = match opcode {
+            Opcode::ROL => (result << 1 | bottom_of(top), top),
+            Opcode::ROR => (result >> 1 | top_of(bottom, sign), bottom),
+            Opcode::RCL => (result << 1 | bottom_of(carry), top),
+            Opcode::RCR => (result >> 1 | top_of(carry, sign), bottom),
+            Opcode::SHR => (result >> 1, bottom),
+            Opcode::SAR => (result >> 1 | result & sign, bottom),
+            _ => (result << 1, top),
+        };
+
+        result = shifted & mask(word) as u16;
+        carry = out;
+    }
+
+    let top = result & sign != 0;
+    let overflow = match opcode {
+        Opcode::ROL | Opcode::RCL | Opcode::SAL => top != carry,
+        _ => top != (result & sign >> 1 != 0),
+    };
+    let flags = set(flags, CF, carry);
+    let flags = set(flags, OF, overflow);
+
+    match opcode {
+        Opcode::ROL | Opcode::ROR | Opcode::RCL | Opcode::RCR => (result, flags),
+        _ => (result, sign_zero_parity(result, word, flags)),
+    }
+}
+
+fn bottom_of(bit: bool) -> u16 {
+    bit as u16
+}
+
+fn top_of(bit: bool, sign: u16) -> u16 {
+    match bit {
+        true => sign,
+        false => 0,
+    }
+}
+
+/// Multiplies AL or AX by the operand, returning the double-width product.
+/// CF and OF are set when the upper half is significant: for `mul` when it
+/// is not zero, for `imul` when it is not the sign extension of the lower.
+pub fn mul(a: u16, b: u16, signed: bool, word: bool, flags: u16) -> (u32, u16) {
+    let (product, significant) = match (signed, word) {
+        (false, false) => {
+            let product = (a as u8 as u32) * (b as u8 as u32);
+            (product, product > 0xff)
+        }
+        (false, true) => {
+            let product = a as u32 * b as u32;
+            (product, product > 0xffff)
+        }
+        (true, false) => {
+            let product = (a as u8 as i8 as i32) * (b as u8 as i8 as i32);
+            (product as u32 & 0xffff, product != product as i8 as i32)
+        }
+        (true, true) => {
+            let product = (a as i16 as i32) * (b as i16 as i32);
+            (product as u32, product != product as i16 as i32)
+        }
+    };
+    let flags = set(flags, CF, significant);
+
+    (product, set(flags, OF, significant))
+}
+
+/// Divides AX or DX:AX, returning the quotient and remainder, or `None` for
+/// a divide error when the divisor is zero or the quotient does not fit.
+/// The 8086 also rejects the most negative quotient of `idiv`, so a byte
+/// quotient is between -127 and 127. The remainder has the sign of the
+/// dividend.
+pub fn div(dividend: u32, divisor: u16, signed: bool, word: bool) -> Option<(u16, u16)> {
+    if divisor & mask(word) as u16 == 0 {
+        return None;
+    }
+
+    match signed {
+        false => {
+            let divisor = (divisor & mask(word) as u16) as u32;
+            let quotient = dividend / divisor;
+
+            (quotient <= mask(word)).then_some((quotient as u16, (dividend % divisor) as u16))
+        }
+        true => {
+            let (dividend, divisor) = match word {
+                true => (dividend as i32 as i64, divisor as i16 as i64),
+                false => (dividend as u16 as i16 as i64, divisor as u8 as i8 as i64),
+            };
+            let quotient = dividend / divisor;
+            let limit = (mask(word) >> 1) as i64;
+
+            (-limit..=limit)
+                .contains(&quotient)
+                .then_some((quotient as u16, (dividend % divisor) as u16))
+        }
+    }
+}
+
+/// Whether the low nibble of AL needs adjusting after BCD arithmetic.
+fn adjust_low(al: u8, flags: u16) -> bool {
+    al & 0x0f > 9 || flags & AF != 0
+}
+
+/// ASCII adjust after addition. The 8086 adds 6 to AL without carrying into
+/// AH, which it increments separately.
+pub fn aaa(ax: u16, flags: u16) -> (u16, u16) {
+    let [al, ah] = ax.to_le_bytes();
+    let adjust = adjust_low(al, flags);
+    let (al, ah) = match adjust {
+        true => (al.wrapping_add(6), ah.wrapping_add(1)),
+        false => (al, ah),
+    };
+    let flags = set(set(flags, AF, adjust), CF, adjust);
+
+    (u16::from_le_bytes([al & 0x0f, ah]), flags)
+}
+
+/// ASCII adjust after subtraction.
+pub fn aas(ax: u16, flags: u16) -> (u16, u16) {
+    let [al, ah] = ax.to_le_bytes();
+    let adjust = adjust_low(al, flags);
+    let (al, ah) = match adjust {
+        true => (al.wrapping_sub(6), ah.wrapping_sub(1)),
+        false => (al, ah),
+    };
+    let flags = set(set(flags, AF, adjust), CF, adjust);
+
+    (u16::from_le_bytes([al & 0x0f, ah]), flags)
+}
+
+/// Decimal adjust AL after addition.
+pub fn daa(al: u8, flags: u16) -> (u8, u16) {
+    let carry = flags & CF != 0;
+    let low = adjust_low(al, flags);
+    let high = al > 0x99 || carry;
+    let mut result = al;
+
+    if low {
+        result = result.wrapping_add(6);
+    }
+
+    if high {
+        result = result.wrapping_add(0x60);
+    }
+
+    let flags = set(set(flags, AF, low), CF, high);
+
+    (result, sign_zero_parity(result as u16, false, flags))
+}
+
+/// Decimal adjust AL after subtraction.
+pub fn das(al: u8, flags: u16) -> (u8, u16) {
+    let carry = flags & CF != 0;
+    let low = adjust_low(al, flags);
+    let high = al > 0x99 || carry;
+    let mut result = al;
+
+    if low {
+        result = result.wrapping_sub(6);
+    }
+
+    if high {
+        result = result.wrapping_sub(0x60);
+    }
+
+    let flags = set(set(flags, AF, low), CF, high || low && al < 6);
+
+    (result, sign_zero_parity(result as u16, false, flags))
+}
+
+/// ASCII adjust after multiplication, splitting AL into digits of the base.
+/// Returns `None` for a divide error when the base is zero.
+pub fn aam(al: u8, base: u8, flags: u16) -> Option<(u16, u16)> {
+    if base == 0 {
+        return None;
+    }
+
+    let result = u16::from_le_bytes([al % base, al / base]);
+
+    Some((result, sign_zero_parity(result & 0xff, false, flags)))
+}
+
+/// ASCII adjust before division, joining the digits of the base in AX.
+pub fn aad(ax: u16, base: u8, flags: u16) -> (u16, u16) {
+    let [al, ah] = ax.to_le_bytes();
+    let result = al.wrapping_add(ah.wrapping_mul(base)) as u16;
+
+    (result, sign_zero_parity(result, false, flags))
+}
+
+#[test]
+fn test_alu() {
+    // 0x7f + 1 overflows into the sign
+    assert_eq!(add(0x7f, 1, false, false, 0), (0x80, OF | SF | AF));
+    // 0xff + 1 carries out and is zero with even parity
+    assert_eq!(add(0xff, 1, false, false, 0), (0, CF | ZF | PF | AF));
+    assert_eq!(add(0xffff, 1, true, true, 0), (1, CF | AF));
+    // 0 - 1 borrows
+    assert_eq!(sub(0, 1, false, true, 0), (0xffff, CF | SF | PF | AF));
+    assert_eq!(sub(0x8000, 1, false, true, 0), (0x7fff, OF | PF | AF));
+    assert_eq!(inc(0xffff, true, CF), (0, CF | ZF | PF | AF));
+    assert_eq!(dec(1, false, 0), (0, ZF | PF));
+    assert_eq!(neg(0, true, CF), (0, ZF | PF));
+    assert_eq!(logic(0x0300, true, CF | OF), (0x0300, PF));
+}
+
+#[test]
+fn test_alu_table() {
+    const NONE: u16 = 0;
+
+    // Operation, word, operands, FLAGS before, result and FLAGS after
+    let table: &[(&str, bool, u16, u16, u16, u32, u16)] = &[
+        ("add", false, 0x7f, 0x01, NONE, 0x80, OF | SF | AF),
+        ("add", false, 0xff, 0x01, NONE, 0x00, CF | ZF | PF | AF),
+        ("add", false, 0x80, 0x80, NONE, 0x00, CF | OF | ZF | PF),
+        ("adc", true, 0xffff, 0x0000, CF, 0x0000, CF | ZF | PF | AF),
+        ("sub", false, 0x00, 0x01, NONE, 0xff, CF | SF | PF | AF),
+        ("sub", false, 0x80, 0x01, NONE, 0x7f, OF | AF),
+        ("sbb", true, 0x1000, 0x0001, CF, 0x0ffe, AF),
+        ("cmp", false, 0x05, 0x05, NONE, 0x00, ZF | PF),
+        ("and", false, 0xf0, 0x0f, CF | OF | AF, 0x00, ZF | PF),
+        ("or", false, 0x80, 0x01, NONE, 0x81, SF | PF),
+        ("xor", true, 0xffff, 0x00ff, NONE, 0xff00, SF | PF),
+        ("inc", false, 0x7f, 0, CF, 0x80, CF | OF | SF | AF),
+        ("dec", false, 0x80, 0, NONE, 0x7f, OF | AF),
+        ("dec", true, 0x0000, 0, NONE, 0xffff, SF | PF | AF),
+        ("neg", false, 0x80, 0, NONE, 0x80, CF | OF | SF),
+        ("neg", false, 0x01, 0, NONE, 0xff, CF | SF | PF | AF),
+        ("neg", true, 0x0000, 0, CF, 0x0000, ZF | PF),
+        ("shl", false, 0x81, 1, NONE, 0x02, CF | OF),
+        ("shl", false, 0x40, 1, NONE, 0x80, OF | SF),
+        ("shl", true, 0x0001, 16, NONE, 0x0000, CF | OF | ZF | PF),
+        ("shl", false, 0x55, 0, ZF, 0x55, ZF),
+        ("shr", false, 0x81, 1, NONE, 0x40, CF | OF),
+        ("shr", false, 0x10, 4, NONE, 0x01, NONE),
+        ("sar", false, 0x81, 1, NONE, 0xc0, CF | SF | PF),
+        ("rol", false, 0x81, 1, NONE, 0x03, CF | OF),
+        ("rol", true, 0x8001, 4, ZF, 0x0018, ZF),
+        ("ror", false, 0x01, 1, NONE, 0x80, CF | OF),
+        ("rcl", false, 0x80, 1, NONE, 0x00, CF | OF),
+        ("rcr", false, 0x01, 1, CF, 0x80, CF | OF),
+        ("setmo", false, 0x12, 1, CF | ZF, 0xff, SF | PF),
+        ("setmo", true, 0x1234, 3, OF, 0xffff, SF | PF),
+        ("setmo", true, 0x1234, 0, OF, 0x1234, OF),
+        ("mul", false, 0x80, 0x02, NONE, 0x0100, CF | OF),
+        ("mul", false, 0x10, 0x0f, CF | OF, 0x00f0, NONE),
+        ("mul", true, 0xffff, 0xffff, NONE, 0xfffe_0001, CF | OF),
+        ("imul", false, 0xff, 0x02, NONE, 0xfffe, NONE),
+        ("imul", false, 0x40, 0x02, NONE, 0x0080, CF | OF),
+        ("imul", true, 0x8000, 0xffff, NONE, 0x0000_8000, CF | OF),
+        ("aaa", true, 0x000b, 0, NONE, 0x0101, AF | CF),
+        ("aaa", true, 0x0005, 0, CF, 0x0005, NONE),
+        // The 8086 does not carry from AL into AH
+        ("aaa", true, 0x00fa, 0, NONE, 0x0100, AF | CF),
+        ("aas", true, 0x0208, 0, AF, 0x0102, AF | CF),
+        ("daa", false, 0x1b, 0, NONE, 0x21, AF | PF),
+        ("daa", false, 0x9a, 0, NONE, 0x00, AF | CF | ZF | PF),
+        ("daa", false, 0x00, 0, CF, 0x60, CF | PF),
+        ("das", false, 0x0f, 0, NONE, 0x09, AF | PF),
+        ("das", false, 0xff, 0, CF | AF, 0x99, AF | CF | SF | PF),
+        ("aam", true, 0x003f, 10, NONE, 0x0603, PF),
+        ("aad", true, 0x0603, 10, NONE, 0x003f, PF),
+        ("aad", true, 0x0102, 16, NONE, 0x0012, PF),
+    ];
+
+    for &(operation, word, a, b, flags, result, expected_flags) in table {
+        let carry = flags & CF != 0;
+        let (actual, actual_flags) = match operation {
+            "add" | "adc" => {
+                let (result, flags) = add(a, b, operation == "adc" && carry, word, flags);
+                (result as u32, flags)
+            }
+            "sub" | "sbb" | "cmp" => {
+                let (result, flags) = sub(a, b, operation == "sbb" && carry, word, flags);
+                (result as u32, flags)
+            }
+            "and" | "or" | "xor" => {
+                let value = match operation {
+                    "and" => a & b,
+                    "or" => a | b,
+                    _ => a ^ b,
+                };
+                let (result, flags) = logic(value, word, flags);
+                (result as u32, flags)
+            }
+            "inc" | "dec" | "neg" => {
+                let (result, flags) = match operation {
+                    "inc" => inc(a, word, flags),
+                    "dec" => dec(a, word, flags),
+                    _ => neg(a, word, flags),
+                };
+                (result as u32, flags)
+            }
+            "shl" | "shr" | "sar" | "rol" | "ror" | "rcl" | "rcr" | "setmo" => {
+                let opcode = match operation {
+                    "shl" => Opcode::SAL,
+                    "shr" => Opcode::SHR,
+                    "sar" => Opcode::SAR,
+                    "rol" => Opcode::ROL,
+                    "ror" => Opcode::ROR,
+                    "rcl" => Opcode::RCL,
+                    "rcr" => Opcode::RCR,
+                    _ => Opcode::SETMO,
+                };
+                let (result, flags) = shift(opcode, a, b as u8, word, flags);
+                (result as u32, flags)
+            }
+            "mul" | "imul" => mul(a, b, operation == "imul", word, flags),
+            "aaa" | "aas" | "aam" | "aad" => {
+                let (result, flags) = match operation {
+                    "aaa" => aaa(a, flags),
+                    "aas" => aas(a, flags),
+                    "aam" => aam(a as u8, b as u8, flags).unwrap(),
+                    _ => aad(a, b as u8, flags),
+                };
+                (result as u32, flags)
+            }
+            _ => {
+                let (result, flags) = match operation {
+                    "daa" => daa(a as u8, flags),
+                    _ => das(a as u8, flags),
+                };
+                (result as u32, flags)
+            }
+        };
+
+        assert_eq!(
+            (actual, actual_flags),
+            (result, expected_flags),
+            "{} {:#x}, {:#x}",
+            operation,
+            a,
+            b
+        );
+    }
+
+    // Signed, word, dividend, divisor and quotient with remainder
+    type Division = (bool, bool, u32, u16, Option<(u16, u16)>);
+
+    let divisions: &[Division] = &[
+        (false, false, 0x0100, 0x02, Some((0x80, 0x00))),
+        (false, false, 0x0200, 0x02, None),
+        (false, true, 0x0001_0000, 0x0002, Some((0x8000, 0x0000))),
+        (false, true, 0x1234, 0x0000, None),
+        (true, false, 0xfff9, 0x02, Some((0xfffd, 0xffff))),
+        // The 8086 can not return the most negative quotient
+        (true, false, 0xff00, 0x02, None),
+        (true, true, 0xffff_8000, 0x0001, None),
+        (true, true, 0x0000_0064, 0xfff6, Some((0xfff6, 0x0000))),
+    ];
+
+    for &(signed, word, dividend, divisor, expected) in divisions {
+        assert_eq!(
+            div(dividend, divisor, signed, word),
+            expected,
+            "{:#x} / {:#x}",
+            dividend,
+            divisor
+        );
+    }
+
+    assert_eq!(aam(0x3f, 0, NONE), None);
+}
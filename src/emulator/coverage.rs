@@ -0,0 +1,29 @@
+use std::collections::HashMap;
+
+/// How many times the instructions starting at each physical address were
+/// executed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Coverage {
+    hits: HashMap<u32, u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, address: u32) {
+        *self.hits.entry(address).or_insert(0) += 1;
+    }
+
+    pub fn hits(&self, address: u32) -> u64 {
+        self.hits.get(&address).copied().unwrap_or(0)
+    }
+
+    /// Returns the hits of each byte of a program loaded at the address.
+    pub fn offsets(&self, base: u32, length: usize) -> Vec<u64> {
+        (0..length as u32)
+            .map(|offset| self.hits(base + offset))
+            .collect()
+    }
+}
@@ -0,0 +1,834 @@
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+use crate::instructions::common::register;
+
+use super::{
+    memory::physical,
+    mz::MzHeader,
+    registers::{flags, Registers},
+    ExecError, ExecErrorKind, Machine, Stop, LOAD_SEGMENT,
+};
+
+/// Offset `.com` programs are loaded at, after the PSP.
+pub const COM_OFFSET: u16 = 0x100;
+
+/// Segment after the conventional memory programs can allocate.
+const MEMORY_TOP: u16 = 0xa000;
+
+/// Handles 0 to 4 are stdin, stdout, stderr, AUX and PRN.
+const FIRST_FILE_HANDLE: u16 = 5;
+
+const MAX_HANDLES: usize = 20;
+
+/// Longest file name read from memory.
+const MAX_PATH_LENGTH: u16 = 128;
+
+/// DOS error codes returned in AX with the carry flag set.
+mod error {
+    pub const INVALID_FUNCTION: u16 = 0x01;
+    pub const FILE_NOT_FOUND: u16 = 0x02;
+    pub const PATH_NOT_FOUND: u16 = 0x03;
+    pub const TOO_MANY_OPEN_FILES: u16 = 0x04;
+    pub const ACCESS_DENIED: u16 = 0x05;
+    pub const INVALID_HANDLE: u16 = 0x06;
+    pub const INSUFFICIENT_MEMORY: u16 = 0x08;
+    pub const INVALID_BLOCK: u16 = 0x09;
+}
+
+/// How a program run under DOS ended.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Exit {
+    /// The program exited with the return code.
+    Code(u8),
+    /// The machine stopped before the program exited.
+    Stopped(Stop),
+}
+
+/// Runs `.com` and MZ programs with the common INT 21h services, reading the
+/// console from `input` and writing it to `output`. Files are only opened
+/// inside the sandbox directory, if one is given.
+pub struct Dos<R: BufRead, W: Write> {
+    pub machine: Machine,
+    input: R,
+    output: W,
+    sandbox: Option<PathBuf>,
+    /// Open files by handle, starting from `FIRST_FILE_HANDLE`.
+    files: Vec<Option<File>>,
+    /// Start segments and sizes in paragraphs of the allocated memory,
+    /// sorted by segment. The program owns the first one.
+    blocks: Vec<(u16, u16)>,
+}
+
+impl<R: BufRead, W: Write> Dos<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self {
+            machine: Machine::new(),
+            input,
+            output,
+            sandbox: None,
+            files: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    /// Maps file names to paths in the directory. Without one, opening and
+    /// creating files is denied.
+    pub fn with_sandbox(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.sandbox = Some(directory.into());
+        self
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    pub fn into_output(self) -> W {
+        self.output
+    }
+
+    /// Writes the PSP of a program at the segment.
+    fn write_psp(&mut self, segment: u16, command_tail: &str) -> Result<(), Box<dyn Error>> {
+        let tail = command_tail.as_bytes();
+
+        if tail.len() > 126 {
+            return Err("the command tail is longer than 126 bytes".into());
+        }
+
+        let memory = &mut self.machine.memory;
+        let psp = |offset: u16| physical(segment, offset);
+
+        // Returning to the start of the PSP runs its int 20h
+        memory.write_word(psp(0x00), 0x20cd);
+        memory.write_word(psp(0x02), MEMORY_TOP);
+        memory.write_byte(psp(0x80), tail.len() as u8);
+        memory.load(psp(0x81), tail);
+        memory.write_byte(psp(0x81 + tail.len() as u16), b'\r');
+
+        Ok(())
+    }
+
+    /// Loads the program after a PSP with the command tail, the way DOS
+    /// starts `.com` programs.
+    pub fn load_com(&mut self, bytes: &[u8], command_tail: &str) -> Result<(), Box<dyn Error>> {
+        let segment = LOAD_SEGMENT;
+
+        // The stack takes the last word of the segment
+        if bytes.len() > 0xfffe - COM_OFFSET as usize {
+            return Err("the program does not fit in a segment".into());
+        }
+
+        self.machine.load(bytes, segment, COM_OFFSET);
+        self.write_psp(segment, command_tail)?;
+
+        // Returning from the program jumps to the start of the PSP
+        self.machine.memory.write_word(physical(segment, 0xfffe), 0);
+        self.machine.registers.ax = 0;
+        self.blocks = vec![(segment, MEMORY_TOP - segment)];
+
+        Ok(())
+    }
+
+    /// Loads an MZ executable after a PSP with the command tail, adding the
+    /// load segment to the relocated words.
+    pub fn load_exe(&mut self, bytes: &[u8], command_tail: &str) -> Result<(), Box<dyn Error>> {
+        let header = MzHeader::parse(bytes)?;
+        let image = header.image(bytes);
+        let psp = LOAD_SEGMENT;
+        let segment = psp + 0x10;
+        let available = (MEMORY_TOP - psp) as usize;
+        let paragraphs = 0x10 + image.len().div_ceil(16);
+
+        if paragraphs + header.min_alloc as usize > available {
+            return Err("the program does not fit in memory".into());
+        }
+
+        self.machine.load(image, segment, 0);
+        // Unlike a `.com` program, the code may end before the data
+        self.machine.end = None;
+        self.write_psp(psp, command_tail)?;
+
+        for &(offset, relocated) in &header.relocations {
+            let address = physical(segment.wrapping_add(relocated), offset);
+            let value = self.machine.memory.read_word(address);
+
+            self.machine
+                .memory
+                .write_word(address, value.wrapping_add(segment));
+        }
+
+        let registers = &mut self.machine.registers;
+
+        registers.cs = segment.wrapping_add(header.cs);
+        registers.ip = header.ip;
+        registers.ss = segment.wrapping_add(header.ss);
+        registers.sp = header.sp;
+        registers.ds = psp;
+        registers.es = psp;
+        registers.ax = 0;
+
+        let size = (paragraphs + header.max_alloc as usize).min(available);
+
+        self.blocks = vec![(psp, size as u16)];
+
+        Ok(())
+    }
+
+    /// Runs the program until it exits or the machine stops, servicing the
+    /// DOS interrupts.
+    pub fn run(&mut self, max_steps: u64) -> Result<Exit, Box<dyn Error>> {
+        while self.machine.steps < max_steps {
+            let remaining = max_steps - self.machine.steps;
+
+            match self.machine.run(remaining)? {
+                Stop::Interrupt(number) => {
+                    if let Some(code) = self.interrupt(number)? {
+                        self.output.flush()?;
+                        return Ok(Exit::Code(code));
+                    }
+                }
+                stop => {
+                    self.output.flush()?;
+                    return Ok(Exit::Stopped(stop));
+                }
+            }
+        }
+
+        self.output.flush()?;
+
+        Ok(Exit::Stopped(Stop::StepLimit))
+    }
+
+    /// Executes an instruction, servicing the interrupt it raises. Returns
+    /// how the program ended if it did.
+    pub fn step(&mut self) -> Result<Option<Exit>, Box<dyn Error>> {
+        let exit = match self.machine.step()? {
+            None => None,
+            Some(Stop::Interrupt(number)) => self.interrupt(number)?.map(Exit::Code),
+            Some(stop) => Some(Exit::Stopped(stop)),
+        };
+
+        self.output.flush()?;
+
+        Ok(exit)
+    }
+
+    fn unsupported(&self, message: String) -> Box<dyn Error> {
+        let registers = &self.machine.registers;
+
+        Box::new(ExecError {
+            cs: registers.cs,
+            // The int is two bytes long
+            ip: registers.ip.wrapping_sub(2),
+            kind: ExecErrorKind::Unsupported,
+            message,
+        })
+    }
+
+    /// Services an interrupt, returning the exit code if the program exits.
+    fn interrupt(&mut self, number: u8) -> Result<Option<u8>, Box<dyn Error>> {
+        match number {
+            0x20 | 0x27 => Ok(Some(0)),
+            0x21 => self.int21(),
+            _ => Err(self.unsupported(format!("int {:#04x} is not supported", number))),
+        }
+    }
+
+    fn registers(&mut self) -> &mut Registers {
+        &mut self.machine.registers
+    }
+
+    fn succeed(&mut self) {
+        self.registers().set_flag(flags::CF, false);
+    }
+
+    fn fail(&mut self, code: u16) {
+        self.registers().ax = code;
+        self.registers().set_flag(flags::CF, true);
+    }
+
+    fn read_byte(&self, segment: u16, offset: u16) -> u8 {
+        self.machine.memory.read_byte(physical(segment, offset))
+    }
+
+    fn write_byte(&mut self, segment: u16, offset: u16, value: u8) {
+        self.machine
+            .memory
+            .write_byte(physical(segment, offset), value);
+    }
+
+    /// Reads a character from the console, or ^Z at the end of the input.
+    fn read_char(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+
+        match self.input.read(&mut byte)? {
+            0 => Ok(0x1a),
+            _ => Ok(byte[0]),
+        }
+    }
+
+    fn int21(&mut self) -> Result<Option<u8>, Box<dyn Error>> {
+        let registers = self.machine.registers;
+        let function = (registers.ax >> 8) as u8;
+        let al = registers.ax as u8;
+
+        match function {
+            0x00 => return Ok(Some(0)),
+            0x01 => {
+                let char = self.read_char()?;
+
+                self.output.write_all(&[char])?;
+                self.registers().set_byte(register::AX, char);
+            }
+            0x02 => self.output.write_all(&[registers.dx as u8])?,
+            0x06 if registers.dx as u8 == 0xff => {
+                let char = self.read_char()?;
+
+                self.registers().set_byte(register::AX, char);
+                self.registers().set_flag(flags::ZF, false);
+            }
+            0x06 => self.output.write_all(&[registers.dx as u8])?,
+            0x07 | 0x08 => {
+                let char = self.read_char()?;
+
+                self.registers().set_byte(register::AX, char);
+            }
+            0x09 => {
+                // The string ends at a `$` before the end of the segment
+                let string: Vec<u8> = (registers.dx..=u16::MAX)
+                    .map(|offset| self.read_byte(registers.ds, offset))
+                    .take_while(|&byte| byte != b'$')
+                    .collect();
+
+                if string.len() > (u16::MAX - registers.dx) as usize {
+                    return Err(self.unsupported(
+                        "int 0x21 function 0x09 found no $ before the end of the segment"
+                            .to_string(),
+                    ));
+                }
+
+                self.output.write_all(&string)?;
+            }
+            0x0a => self.buffered_input(registers.ds, registers.dx)?,
+            0x25 => {
+                let vector = al as u32 * 4;
+
+                self.machine.memory.write_word(vector, registers.dx);
+                self.machine.memory.write_word(vector + 2, registers.ds);
+            }
+            0x30 => {
+                // DOS 5.0
+                self.registers().ax = 0x0005;
+                self.registers().bx = 0;
+                self.registers().cx = 0;
+            }
+            0x31 => return Ok(Some(al)),
+            0x35 => {
+                let vector = al as u32 * 4;
+                let offset = self.machine.memory.read_word(vector);
+                let segment = self.machine.memory.read_word(vector + 2);
+
+                self.registers().bx = offset;
+                self.registers().es = segment;
+            }
+            0x3c..=0x42 => self.file(function)?,
+            0x48 => self.allocate(registers.bx),
+            0x49 => self.free(registers.es),
+            0x4a => self.resize(registers.es, registers.bx),
+            0x4c => return Ok(Some(al)),
+            _ => {
+                return Err(self.unsupported(format!(
+                    "int 21h function {:#04x} is not supported",
+                    function
+                )))
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Reads a line into the buffer at DS:DX, which starts with its size
+    /// and is followed by the number of characters read.
+    fn buffered_input(&mut self, segment: u16, offset: u16) -> io::Result<()> {
+        let size = self.read_byte(segment, offset) as usize;
+        let mut line = Vec::new();
+
+        self.input.read_until(b'\n', &mut line)?;
+
+        while line
+            .last()
+            .is_some_and(|&byte| byte == b'\n' || byte == b'\r')
+        {
+            line.pop();
+        }
+
+        // The carriage return takes the last byte of the buffer
+        line.truncate(size.saturating_sub(1));
+        line.push(b'\r');
+
+        self.output.write_all(&line)?;
+        self.write_byte(segment, offset.wrapping_add(1), line.len() as u8 - 1);
+
+        for (index, &byte) in line.iter().enumerate() {
+            self.write_byte(segment, offset.wrapping_add(2 + index as u16), byte);
+        }
+
+        Ok(())
+    }
+
+    /// Reads the zero terminated file name at DS:DX.
+    fn path_name(&self) -> String {
+        let registers = &self.machine.registers;
+        let bytes: Vec<u8> = (0..MAX_PATH_LENGTH)
+            .map(|index| self.read_byte(registers.ds, registers.dx.wrapping_add(index)))
+            .take_while(|&byte| byte != 0)
+            .collect();
+
+        String::from_utf8_lossy(&bytes).to_string()
+    }
+
+    /// Maps a DOS file name to a path in the sandbox. Drive letters are
+    /// ignored and names leaving the sandbox are rejected.
+    fn sandbox_path(&self, name: &str) -> Option<PathBuf> {
+        let mut path = self.sandbox.clone()?;
+        let name = match name.as_bytes() {
+            [_, b':', ..] => &name[2..],
+            _ => name,
+        };
+
+        for component in name.split(['\\', '/']) {
+            match component {
+                "" | "." => {}
+                ".." => return None,
+                component => path.push(component),
+            }
+        }
+
+        Some(path)
+    }
+
+    fn file_error(&mut self, error: io::Error) {
+        let code = match error.kind() {
+            ErrorKind::NotFound => error::FILE_NOT_FOUND,
+            _ => error::ACCESS_DENIED,
+        };
+
+        self.fail(code);
+    }
+
+    fn open(&mut self, file: io::Result<File>) {
+        let file = match file {
+            Ok(file) => file,
+            Err(error) => return self.file_error(error),
+        };
+        let free = self.files.iter().position(Option::is_none);
+        let index = match free {
+            Some(index) => index,
+            None if self.files.len() + (FIRST_FILE_HANDLE as usize) < MAX_HANDLES => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return self.fail(error::TOO_MANY_OPEN_FILES),
+        };
+
+        self.files[index] = Some(file);
+        self.registers().ax = FIRST_FILE_HANDLE + index as u16;
+        self.succeed();
+    }
+
+    fn file_mut(&mut self, handle: u16) -> Option<&mut File> {
+        let index = handle.checked_sub(FIRST_FILE_HANDLE)? as usize;
+
+        self.files.get_mut(index)?.as_mut()
+    }
+
+    /// Services the file handle functions, 3Ch to 42h.
+    fn file(&mut self, function: u8) -> io::Result<()> {
+        let registers = self.machine.registers;
+
+        match function {
+            0x3c | 0x3d | 0x41 => {
+                let Some(path) = self.sandbox_path(&self.path_name()) else {
+                    self.fail(match self.sandbox {
+                        Some(_) => error::PATH_NOT_FOUND,
+                        None => error::ACCESS_DENIED,
+                    });
+                    return Ok(());
+                };
+
+                match function {
+                    0x3c => self.open(
+                        OpenOptions::new()
+                            .read(true)
+                            .write(true)
+                            .create(true)
+                            .truncate(true)
+                            .open(path),
+                    ),
+                    0x3d => {
+                        let mut options = OpenOptions::new();
+
+                        match registers.ax & 0b11 {
+                            0 => options.read(true),
+                            1 => options.write(true),
+                            _ => options.read(true).write(true),
+                        };
+
+                        self.open(options.open(path));
+                    }
+                    _ => match fs::remove_file(path) {
+                        Ok(()) => self.succeed(),
+                        Err(error) => self.file_error(error),
+                    },
+                }
+            }
+            0x3e => {
+                let index = registers.bx.wrapping_sub(FIRST_FILE_HANDLE) as usize;
+
+                match self.files.get_mut(index).and_then(Option::take) {
+                    Some(_) => self.succeed(),
+                    // Closing the standard handles is allowed
+                    None if registers.bx < FIRST_FILE_HANDLE => self.succeed(),
+                    None => self.fail(error::INVALID_HANDLE),
+                }
+            }
+            0x3f => {
+                let mut buffer = vec![0; registers.cx as usize];
+                let read = match registers.bx {
+                    0 => Ok(self.input.read(&mut buffer)?),
+                    handle => match self.file_mut(handle) {
+                        Some(file) => file.read(&mut buffer),
+                        None => {
+                            self.fail(error::INVALID_HANDLE);
+                            return Ok(());
+                        }
+                    },
+                };
+
+                match read {
+                    Ok(length) => {
+                        for (index, &byte) in buffer[..length].iter().enumerate() {
+                            self.write_byte(
+                                registers.ds,
+                                registers.dx.wrapping_add(index as u16),
+                                byte,
+                            );
+                        }
+
+                        self.registers().ax = length as u16;
+                        self.succeed();
+                    }
+                    Err(error) => self.file_error(error),
+                }
+            }
+            0x40 => {
+                let buffer: Vec<u8> = (0..registers.cx)
+                    .map(|index| self.read_byte(registers.ds, registers.dx.wrapping_add(index)))
+                    .collect();
+                let written = match registers.bx {
+                    1 | 2 => Ok(self.output.write(&buffer)?),
+                    handle => match self.file_mut(handle) {
+                        // Writing nothing truncates the file at the position
+                        Some(file) if buffer.is_empty() => file
+                            .stream_position()
+                            .and_then(|position| file.set_len(position))
+                            .map(|_| 0),
+                        Some(file) => file.write(&buffer),
+                        None => {
+                            self.fail(error::INVALID_HANDLE);
+                            return Ok(());
+                        }
+                    },
+                };
+
+                match written {
+                    Ok(length) => {
+                        self.registers().ax = length as u16;
+                        self.succeed();
+                    }
+                    Err(error) => self.file_error(error),
+                }
+            }
+            _ => {
+                let offset = ((registers.cx as u32) << 16 | registers.dx as u32) as i32;
+                let from = match registers.ax & 0xff {
+                    0 => SeekFrom::Start(offset as u32 as u64),
+                    1 => SeekFrom::Current(offset as i64),
+                    2 => SeekFrom::End(offset as i64),
+                    _ => {
+                        self.fail(error::INVALID_FUNCTION);
+                        return Ok(());
+                    }
+                };
+                let Some(file) = self.file_mut(registers.bx) else {
+                    self.fail(error::INVALID_HANDLE);
+                    return Ok(());
+                };
+
+                match file.seek(from) {
+                    Ok(position) => {
+                        self.registers().dx = (position >> 16) as u16;
+                        self.registers().ax = position as u16;
+                        self.succeed();
+                    }
+                    Err(error) => self.file_error(error),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first segment and the paragraphs free after the block.
+    fn gap_after(&self, index: usize) -> (u16, u16) {
+        let (segment, size) = self.blocks[index];
+        let end = segment + size;
+        let next = self
+            .blocks
+            .get(index + 1)
+            .map_or(MEMORY_TOP, |&(segment, _)| segment);
+
+        (end, next - end)
+    }
+
+    fn allocate(&mut self, paragraphs: u16) {
+        let gaps: Vec<(usize, (u16, u16))> = (0..self.blocks.len())
+            .map(|index| (index, self.gap_after(index)))
+            .collect();
+
+        match gaps.iter().find(|(_, (_, free))| *free >= paragraphs) {
+            Some(&(index, (segment, _))) => {
+                self.blocks.insert(index + 1, (segment, paragraphs));
+                self.registers().ax = segment;
+                self.succeed();
+            }
+            None => {
+                let largest = gaps.iter().map(|(_, (_, free))| *free).max();
+
+                self.fail(error::INSUFFICIENT_MEMORY);
+                self.registers().bx = largest.unwrap_or(0);
+            }
+        }
+    }
+
+    fn free(&mut self, segment: u16) {
+        // The block of the program is freed when it exits
+        match self
+            .blocks
+            .iter()
+            .skip(1)
+            .position(|&(start, _)| start == segment)
+        {
+            Some(index) => {
+                self.blocks.remove(index + 1);
+                self.succeed();
+            }
+            None => self.fail(error::INVALID_BLOCK),
+        }
+    }
+
+    fn resize(&mut self, segment: u16, paragraphs: u16) {
+        let Some(index) = self.blocks.iter().position(|&(start, _)| start == segment) else {
+            return self.fail(error::INVALID_BLOCK);
+        };
+        let (end, free) = self.gap_after(index);
+        let available = end - segment + free;
+
+        if paragraphs <= available {
+            self.blocks[index].1 = paragraphs;
+            self.succeed();
+        } else {
+            self.fail(error::INSUFFICIENT_MEMORY);
+            self.registers().bx = available;
+        }
+    }
+}
+
+#[test]
+fn test_dos() {
+    use crate::assembler::assemble;
+
+    let program = assemble(
+        "\
+bits 16
+org 0x100
+mov ah, 0x09
+mov dx, prompt
+int 0x21
+mov ah, 0x0a
+mov dx, buffer
+int 0x21
+mov ah, 0x02
+mov dl, [buffer+2]
+int 0x21
+; shrink the program to 0x1000 paragraphs and allocate 0x10
+mov ah, 0x4a
+mov bx, 0x1000
+int 0x21
+mov ah, 0x48
+mov bx, 0x10
+int 0x21
+mov [segment], ax
+mov ah, 0x4c
+mov al, [0x0080]
+int 0x21
+prompt:
+db 'Name? $'
+segment:
+dw 0
+buffer:
+db 4, 0, 0, 0, 0, 0
+",
+    )
+    .unwrap();
+    let mut dos = Dos::new("Joni\n".as_bytes(), Vec::new());
+
+    dos.load_com(&program, " a b").unwrap();
+
+    assert_eq!(dos.run(1000).unwrap(), Exit::Code(4));
+    assert_eq!(dos.output(), b"Name? Jon\rJ");
+    assert_eq!(dos.blocks, vec![(0x1000, 0x1000), (0x2000, 0x10)]);
+    assert_eq!(dos.machine.registers.ax & 0xff00, 0x4c00);
+
+    // Files are denied without a sandbox, and names can not leave it
+    let dos = Dos::new(io::empty(), Vec::new()).with_sandbox("/tmp/sandbox");
+
+    assert_eq!(
+        dos.sandbox_path("C:\\DATA\\FILE.TXT"),
+        Some(PathBuf::from("/tmp/sandbox/DATA/FILE.TXT"))
+    );
+    assert_eq!(dos.sandbox_path("..\\FILE.TXT"), None);
+
+    // A string without a `$` up to the end of the segment is not printed
+    let program = assemble("bits 16\norg 0x100\nmov ah, 0x09\nmov dx, 0xfff0\nint 0x21\n").unwrap();
+    let mut dos = Dos::new(io::empty(), Vec::new());
+
+    dos.load_com(&program, "").unwrap();
+
+    let error = dos.run(1000).unwrap_err();
+
+    assert!(error
+        .to_string()
+        .ends_with("found no $ before the end of the segment"));
+    assert_eq!(dos.output(), b"");
+}
+
+#[test]
+fn test_dos_exe() {
+    use crate::assembler::assemble;
+
+    // The word at offset 1 is relocated to the segment of the module
+    let module = assemble(
+        "\
+bits 16
+mov ax, 0
+mov ds, ax
+mov ah, 0x09
+mov dx, message
+int 0x21
+mov ax, 0x4c03
+int 0x21
+message:
+db 'MZ!$'
+",
+    )
+    .unwrap();
+    let length = 0x20 + module.len() as u16;
+    let mut bytes = Vec::new();
+
+    for word in [
+        0x5a4d, length, 1, 1, 2, 0x20, 0xffff, 0, 0x200, 0, 0, 0, 0x1c, 0,
+    ] {
+        bytes.extend(u16::to_le_bytes(word));
+    }
+
+    bytes.extend([0x01, 0x00, 0x00, 0x00]);
+    bytes.extend(module);
+
+    let mut dos = Dos::new(io::empty(), Vec::new());
+
+    dos.load_exe(&bytes, "").unwrap();
+
+    assert_eq!(dos.machine.registers.cs, 0x1010);
+    assert_eq!(dos.machine.registers.ds, 0x1000);
+    assert_eq!(dos.run(1000).unwrap(), Exit::Code(3));
+    assert_eq!(dos.output(), b"MZ!");
+}
+
+#[test]
+fn test_dos_files() {
+    use crate::assembler::assemble;
+
+    let sandbox = std::env::temp_dir().join(format!("de8086-dos-{}", std::process::id()));
+
+    fs::create_dir_all(&sandbox).unwrap();
+
+    // Create a file, write to it, read it back, print it, close it, fail
+    // to read it again and delete it, exiting with the error of the read
+    let program = assemble(
+        "\
+bits 16
+org 0x100
+mov ah, 0x3c
+mov cx, 0
+mov dx, name
+int 0x21
+mov si, ax
+mov bx, ax
+mov ah, 0x40
+mov cx, 3
+mov dx, text
+int 0x21
+mov ax, 0x4200
+mov cx, 0
+mov dx, 1
+int 0x21
+mov ah, 0x3f
+mov cx, 8
+mov dx, buffer
+int 0x21
+mov cx, ax
+mov ah, 0x40
+mov bx, 1
+int 0x21
+mov ah, 0x3e
+mov bx, si
+int 0x21
+mov ah, 0x3f
+mov cx, 1
+mov dx, buffer
+int 0x21
+mov di, ax
+mov ah, 0x41
+mov dx, name
+int 0x21
+mov ax, di
+mov ah, 0x4c
+int 0x21
+name:
+db 'C:\\OUT.TXT', 0
+text:
+db 'abc'
+buffer:
+db 0, 0, 0, 0, 0, 0, 0, 0
+",
+    )
+    .unwrap();
+    let mut dos = Dos::new(io::empty(), Vec::new()).with_sandbox(&sandbox);
+
+    dos.load_com(&program, "").unwrap();
+
+    let exit = dos.run(1000).unwrap();
+
+    assert_eq!(dos.output(), b"bc");
+    assert_eq!(exit, Exit::Code(error::INVALID_HANDLE as u8));
+    assert!(!sandbox.join("OUT.TXT").exists());
+
+    fs::remove_dir_all(&sandbox).unwrap();
+}
@@ -0,0 +1,160 @@
+pub mod console;
+pub mod keyboard;
+pub mod pic;
+pub mod pit;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use console::DebugConsole;
+use keyboard::Keyboard;
+use pic::Pic;
+use pit::Pit;
+
+/// Byte read from a port no device answers.
+pub const OPEN_BUS: u8 = 0xff;
+
+/// Devices on the I/O ports, which `in` and `out` read and write.
+pub trait IoBus: Send {
+    fn read_byte(&mut self, port: u16) -> u8;
+
+    fn write_byte(&mut self, port: u16, value: u8);
+
+    /// Reads a word as bytes from the port and the one after it.
+    fn read_word(&mut self, port: u16) -> u16 {
+        let low = self.read_byte(port);
+        let high = self.read_byte(port.wrapping_add(1));
+
+        u16::from_le_bytes([low, high])
+    }
+
+    /// Writes a word as bytes to the port and the one after it.
+    fn write_word(&mut self, port: u16, value: u16) {
+        let [low, high] = value.to_le_bytes();
+
+        self.write_byte(port, low);
+        self.write_byte(port.wrapping_add(1), high);
+    }
+
+    /// Advances the devices by an instruction, returning the interrupt
+    /// request lines they raise as a mask.
+    fn tick(&mut self) -> u8 {
+        0
+    }
+
+    /// Raises the interrupt request lines in the mask.
+    fn raise(&mut self, _lines: u8) {}
+
+    /// Returns the vector of the interrupt the devices request, if any, as
+    /// the CPU acknowledges it once IF is set.
+    fn acknowledge(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Devices attached to ports, with the interrupt request lines they raise
+/// wired to all of them, so that a `Pic` sees the lines of a `Pit`.
+#[derive(Default)]
+pub struct Ports {
+    devices: Vec<Box<dyn IoBus>>,
+    /// Index of the device on each port.
+    ports: HashMap<u16, usize>,
+}
+
+impl Ports {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The devices of a PC: a PIC, a PIT, the keyboard and a debug console
+    /// writing to `console`.
+    pub fn pc(console: impl Write + Send + 'static) -> Self {
+        Self::new()
+            .with_device([pic::COMMAND, pic::DATA], Pic::new())
+            .with_device(pit::PORTS, Pit::new())
+            .with_device([keyboard::DATA, keyboard::STATUS], Keyboard::new())
+            .with_device([console::PORT], DebugConsole::new(console))
+    }
+
+    /// Attaches the device to the ports, in place of the ones on them.
+    pub fn attach(&mut self, ports: impl IntoIterator<Item = u16>, device: impl IoBus + 'static) {
+        let index = self.devices.len();
+
+        self.devices.push(Box::new(device));
+
+        for port in ports {
+            self.ports.insert(port, index);
+        }
+    }
+
+    pub fn with_device(
+        mut self,
+        ports: impl IntoIterator<Item = u16>,
+        device: impl IoBus + 'static,
+    ) -> Self {
+        self.attach(ports, device);
+        self
+    }
+
+    fn device(&mut self, port: u16) -> Option<&mut Box<dyn IoBus>> {
+        let index = *self.ports.get(&port)?;
+
+        self.devices.get_mut(index)
+    }
+}
+
+impl IoBus for Ports {
+    fn read_byte(&mut self, port: u16) -> u8 {
+        match self.device(port) {
+            Some(device) => device.read_byte(port),
+            None => OPEN_BUS,
+        }
+    }
+
+    fn write_byte(&mut self, port: u16, value: u8) {
+        if let Some(device) = self.device(port) {
+            device.write_byte(port, value);
+        }
+    }
+
+    fn tick(&mut self) -> u8 {
+        self.devices
+            .iter_mut()
+            .fold(0, |lines, device| lines | device.tick())
+    }
+
+    fn raise(&mut self, lines: u8) {
+        for device in &mut self.devices {
+            device.raise(lines);
+        }
+    }
+
+    fn acknowledge(&mut self) -> Option<u8> {
+        self.devices
+            .iter_mut()
+            .find_map(|device| device.acknowledge())
+    }
+}
+
+#[test]
+fn test_ports() {
+    let mut ports = Ports::pc(Vec::new());
+
+    // Nothing answers on port 0x80
+    assert_eq!(ports.read_byte(0x80), OPEN_BUS);
+    assert_eq!(ports.read_word(0x80), 0xffff);
+    assert_eq!(ports.read_byte(console::PORT), 0xe9);
+
+    // Channel 0 of the PIT raises IRQ 0 every 4 instructions
+    ports.write_byte(0x43, 0b0011_0100);
+    ports.write_byte(0x40, 4);
+    ports.write_byte(0x40, 0);
+
+    assert_eq!((0..3).map(|_| ports.tick()).collect::<Vec<_>>(), [0, 0, 0]);
+    assert_eq!(ports.tick(), 0b1);
+
+    ports.raise(0b1);
+
+    assert_eq!(ports.acknowledge(), Some(0x08));
+    assert_eq!(ports.acknowledge(), None);
+}
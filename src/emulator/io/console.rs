@@ -0,0 +1,52 @@
+use std::io::Write;
+
+use super::IoBus;
+
+/// The port of the Bochs and QEMU debug console.
+pub const PORT: u16 = 0xe9;
+
+/// Writes the bytes written to its port to `output`, like the debug console
+/// of Bochs. Reading the port returns 0xe9, so that programs can detect it.
+pub struct DebugConsole<W: Write + Send> {
+    output: W,
+}
+
+impl<W: Write + Send> DebugConsole<W> {
+    pub fn new(output: W) -> Self {
+        Self { output }
+    }
+
+    pub fn output(&self) -> &W {
+        &self.output
+    }
+}
+
+impl<W: Write + Send> IoBus for DebugConsole<W> {
+    fn read_byte(&mut self, _port: u16) -> u8 {
+        PORT as u8
+    }
+
+    fn write_byte(&mut self, _port: u16, value: u8) {
+        // `out` can not fail, so the output is written on a best effort basis
+        let _ = self.output.write_all(&[value]);
+
+        if value == b'\n' {
+            let _ = self.output.flush();
+        }
+    }
+}
+
+#[test]
+fn test_debug_console() {
+    let mut console = DebugConsole::new(Vec::new());
+
+    for byte in b"hi\n" {
+        console.write_byte(PORT, *byte);
+    }
+
+    // A word is written as two bytes
+    console.write_word(PORT, u16::from_le_bytes(*b"ok"));
+
+    assert_eq!(console.output(), b"hi\nok");
+    assert_eq!(console.read_byte(PORT), 0xe9);
+}
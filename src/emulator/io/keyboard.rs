@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+
+use super::IoBus;
+
+/// Port of the scan code in the output buffer.
+pub const DATA: u16 = 0x60;
+
+/// Port of the controller status.
+pub const STATUS: u16 = 0x64;
+
+/// The interrupt request line of the keyboard.
+const IRQ: u8 = 0b10;
+
+/// Status bit of a full output buffer.
+const OUTPUT_FULL: u8 = 0b1;
+
+/// A keyboard controller fed with scan codes up front. Each one moves to
+/// the output buffer once the one before it is read, raising IRQ 1.
+/// Commands written to the controller are ignored.
+#[derive(Debug, Default)]
+pub struct Keyboard {
+    scan_codes: VecDeque<u8>,
+    output: Option<u8>,
+    /// Scan code read last, which the data port keeps returning.
+    data: u8,
+}
+
+impl Keyboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_scan_codes(mut self, scan_codes: &[u8]) -> Self {
+        self.scan_codes.extend(scan_codes);
+        self
+    }
+
+    /// Queues a scan code, like a key being pressed or released.
+    pub fn push(&mut self, scan_code: u8) {
+        self.scan_codes.push_back(scan_code);
+    }
+}
+
+impl IoBus for Keyboard {
+    fn read_byte(&mut self, port: u16) -> u8 {
+        match port {
+            STATUS => match self.output {
+                Some(_) => OUTPUT_FULL,
+                None => 0,
+            },
+            _ => {
+                if let Some(scan_code) = self.output.take() {
+                    self.data = scan_code;
+                }
+
+                self.data
+            }
+        }
+    }
+
+    fn write_byte(&mut self, _port: u16, _value: u8) {}
+
+    fn tick(&mut self) -> u8 {
+        if self.output.is_some() {
+            return 0;
+        }
+
+        match self.scan_codes.pop_front() {
+            Some(scan_code) => {
+                self.output = Some(scan_code);
+                IRQ
+            }
+            None => 0,
+        }
+    }
+}
+
+#[test]
+fn test_keyboard() {
+    // A pressed and released
+    let mut keyboard = Keyboard::new().with_scan_codes(&[0x1e, 0x9e]);
+
+    assert_eq!(keyboard.read_byte(STATUS), 0);
+    assert_eq!(keyboard.tick(), IRQ);
+    assert_eq!(keyboard.tick(), 0);
+    assert_eq!(keyboard.read_byte(STATUS), OUTPUT_FULL);
+    assert_eq!(keyboard.read_byte(DATA), 0x1e);
+    assert_eq!(keyboard.read_byte(DATA), 0x1e);
+    assert_eq!(keyboard.read_byte(STATUS), 0);
+    assert_eq!(keyboard.tick(), IRQ);
+    assert_eq!(keyboard.read_byte(DATA), 0x9e);
+    assert_eq!(keyboard.tick(), 0);
+}
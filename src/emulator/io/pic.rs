@@ -0,0 +1,193 @@
+use super::IoBus;
+
+pub const COMMAND: u16 = 0x20;
+pub const DATA: u16 = 0x21;
+
+/// Vector of IRQ 0 as the BIOS sets it up.
+const BASE: u8 = 0x08;
+
+/// Initialization command words still expected on the data port.
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Init {
+    Done,
+    Icw2 { single: bool, icw4: bool },
+    Icw3 { icw4: bool },
+    Icw4,
+}
+
+/// An 8259 interrupt controller without a slave, prioritizing IRQ 0.
+/// Until it is initialized, it is set up like the BIOS leaves it, with IRQ 0
+/// at vector 8 and no lines masked. Rotating priorities and the special
+/// mask mode are not supported.
+#[derive(Debug)]
+pub struct Pic {
+    /// Vector of IRQ 0.
+    base: u8,
+    mask: u8,
+    requests: u8,
+    in_service: u8,
+    init: Init,
+    auto_eoi: bool,
+    /// Whether the command port reads the in service register instead of
+    /// the requests.
+    read_in_service: bool,
+}
+
+impl Default for Pic {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Pic {
+    pub fn new() -> Self {
+        Self {
+            base: BASE,
+            mask: 0,
+            requests: 0,
+            in_service: 0,
+            init: Init::Done,
+            auto_eoi: false,
+            read_in_service: false,
+        }
+    }
+
+    fn command(&mut self, value: u8) {
+        if value & 0x10 != 0 {
+            // ICW1 starts the initialization over
+            *self = Self {
+                init: Init::Icw2 {
+                    single: value & 0b10 != 0,
+                    icw4: value & 0b1 != 0,
+                },
+                ..Self::new()
+            };
+        } else if value & 0x08 != 0 {
+            // OCW3 selects the register the command port reads
+            if value & 0b10 != 0 {
+                self.read_in_service = value & 0b1 != 0;
+            }
+        } else if value & 0x20 != 0 {
+            // OCW2 ends the given interrupt, or the one with the highest
+            // priority
+            let line = match value & 0x40 {
+                0 => self.in_service.trailing_zeros(),
+                _ => (value & 0b111) as u32,
+            };
+
+            self.in_service &= !1u8.checked_shl(line).unwrap_or(0);
+        }
+    }
+
+    fn data(&mut self, value: u8) {
+        self.init = match self.init {
+            Init::Icw2 { single, icw4 } => {
+                self.base = value & 0xf8;
+
+                match (single, icw4) {
+                    (false, _) => Init::Icw3 { icw4 },
+                    (true, true) => Init::Icw4,
+                    (true, false) => Init::Done,
+                }
+            }
+            Init::Icw3 { icw4: true } => Init::Icw4,
+            Init::Icw3 { icw4: false } => Init::Done,
+            Init::Icw4 => {
+                self.auto_eoi = value & 0b10 != 0;
+                Init::Done
+            }
+            Init::Done => {
+                self.mask = value;
+                Init::Done
+            }
+        };
+    }
+}
+
+impl IoBus for Pic {
+    fn read_byte(&mut self, port: u16) -> u8 {
+        match (port & 1, self.read_in_service) {
+            (0, true) => self.in_service,
+            (0, false) => self.requests,
+            _ => self.mask,
+        }
+    }
+
+    fn write_byte(&mut self, port: u16, value: u8) {
+        match port & 1 {
+            0 => self.command(value),
+            _ => self.data(value),
+        }
+    }
+
+    fn raise(&mut self, lines: u8) {
+        self.requests |= lines;
+    }
+
+    fn acknowledge(&mut self) -> Option<u8> {
+        let pending = self.requests & !self.mask;
+
+        if pending == 0 {
+            return None;
+        }
+
+        let line = pending.trailing_zeros();
+        let bit = 1 << line;
+
+        // An interrupt in service holds off those of the same or lower
+        // priority
+        if self.in_service & (bit | (bit - 1)) != 0 {
+            return None;
+        }
+
+        self.requests &= !bit;
+
+        if !self.auto_eoi {
+            self.in_service |= bit;
+        }
+
+        Some(self.base + line as u8)
+    }
+}
+
+#[test]
+fn test_pic() {
+    let mut pic = Pic::new();
+
+    // Initialized like the BIOS, with IRQ 0 at 0x08 and IRQ 2 masked
+    for (port, value) in [(COMMAND, 0x11), (DATA, 0x08), (DATA, 0x04), (DATA, 0x01)] {
+        pic.write_byte(port, value);
+    }
+
+    pic.write_byte(DATA, 0b100);
+    pic.raise(0b110);
+
+    assert_eq!(pic.read_byte(COMMAND), 0b110);
+    assert_eq!(pic.read_byte(DATA), 0b100);
+    assert_eq!(pic.acknowledge(), Some(0x09));
+
+    // IRQ 0 has a higher priority than IRQ 1 in service
+    pic.raise(0b1);
+
+    assert_eq!(pic.acknowledge(), Some(0x08));
+    assert_eq!(pic.acknowledge(), None);
+
+    pic.write_byte(COMMAND, 0x0b);
+
+    assert_eq!(pic.read_byte(COMMAND), 0b11);
+
+    // A non-specific EOI ends IRQ 0, and a specific one IRQ 1
+    pic.write_byte(COMMAND, 0x20);
+
+    assert_eq!(pic.read_byte(COMMAND), 0b10);
+
+    pic.write_byte(COMMAND, 0x61);
+
+    assert_eq!(pic.read_byte(COMMAND), 0);
+    assert_eq!(pic.acknowledge(), None);
+
+    // Unmasked, IRQ 2 is taken
+    pic.write_byte(DATA, 0);
+
+    assert_eq!(pic.acknowledge(), Some(0x0a));
+}
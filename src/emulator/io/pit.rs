@@ -0,0 +1,207 @@
+use std::ops::RangeInclusive;
+
+use super::{IoBus, OPEN_BUS};
+
+/// Counters 0 to 2 and the control word.
+pub const PORTS: RangeInclusive<u16> = 0x40..=0x43;
+
+/// The interrupt request line of counter 0.
+const IRQ: u8 = 0b1;
+
+/// How a counter is read and written, from the control word.
+mod access {
+    pub const LATCH: u8 = 0;
+    pub const LOW: u8 = 1;
+    pub const HIGH: u8 = 2;
+    pub const LOW_HIGH: u8 = 3;
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+struct Counter {
+    mode: u8,
+    access: u8,
+    /// Count loaded at the start of each period, where 0 is 65536.
+    reload: u16,
+    count: u16,
+    /// Count saved by a latch command until it is read.
+    latch: Option<u16>,
+    /// Low byte written before the high one.
+    low: u8,
+    /// Whether the next byte written or read is the high one.
+    write_high: bool,
+    read_high: bool,
+    counting: bool,
+    /// Whether a one shot mode is yet to reach zero.
+    armed: bool,
+}
+
+impl Counter {
+    fn control(&mut self, value: u8) {
+        let access = value >> 4 & 0b11;
+
+        if access == access::LATCH {
+            self.latch = self.latch.or(Some(self.count));
+            return;
+        }
+
+        // Modes 6 and 7 are 2 and 3
+        let mode = value >> 1 & 0b111;
+
+        *self = Self {
+            mode: if mode > 5 { mode & 0b11 } else { mode },
+            access,
+            ..Self::default()
+        };
+    }
+
+    fn write(&mut self, value: u8) {
+        let reload = match self.access {
+            access::LOW => value as u16,
+            access::HIGH => (value as u16) << 8,
+            access::LOW_HIGH if !self.write_high => {
+                self.low = value;
+                self.write_high = true;
+                return;
+            }
+            _ => {
+                self.write_high = false;
+                u16::from_le_bytes([self.low, value])
+            }
+        };
+
+        self.reload = reload;
+        self.count = reload;
+        // The gates of modes 1 and 5 are not emulated, so they never start
+        self.counting = !matches!(self.mode, 1 | 5);
+        self.armed = true;
+    }
+
+    fn read(&mut self) -> u8 {
+        let [low, high] = self.latch.unwrap_or(self.count).to_le_bytes();
+        let (byte, done) = match self.access {
+            access::LOW => (low, true),
+            access::HIGH => (high, true),
+            access::LOW_HIGH if !self.read_high => (low, false),
+            _ => (high, true),
+        };
+
+        self.read_high = !done;
+
+        if done {
+            self.latch = None;
+        }
+
+        byte
+    }
+
+    /// Counts down once, returning whether the output raises an interrupt.
+    fn tick(&mut self) -> bool {
+        if !self.counting {
+            return false;
+        }
+
+        self.count = self.count.wrapping_sub(1);
+
+        if self.count != 0 {
+            return false;
+        }
+
+        match self.mode {
+            2 | 3 => {
+                self.count = self.reload;
+                true
+            }
+            _ => std::mem::replace(&mut self.armed, false),
+        }
+    }
+}
+
+/// An 8253 timer counting once per instruction, whose counter 0 raises
+/// IRQ 0. Modes 2 and 3 raise it every period and modes 0 and 4 once, while
+/// modes 1 and 5 wait for a gate that is not emulated. BCD counting is not
+/// supported either.
+#[derive(Debug, Default)]
+pub struct Pit {
+    counters: [Counter; 3],
+}
+
+impl Pit {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl IoBus for Pit {
+    fn read_byte(&mut self, port: u16) -> u8 {
+        match self.counters.get_mut(port as usize & 0b11) {
+            Some(counter) => counter.read(),
+            // The 8253 can not read the control word
+            None => OPEN_BUS,
+        }
+    }
+
+    fn write_byte(&mut self, port: u16, value: u8) {
+        match self.counters.get_mut(port as usize & 0b11) {
+            Some(counter) => counter.write(value),
+            None => {
+                // The 8254 read back command is not supported
+                if let Some(counter) = self.counters.get_mut(value as usize >> 6) {
+                    counter.control(value);
+                }
+            }
+        }
+    }
+
+    fn tick(&mut self) -> u8 {
+        let mut lines = 0;
+
+        for (index, counter) in self.counters.iter_mut().enumerate() {
+            if counter.tick() && index == 0 {
+                lines |= IRQ;
+            }
+        }
+
+        lines
+    }
+}
+
+#[test]
+fn test_pit() {
+    let mut pit = Pit::new();
+
+    // Counter 0 in mode 0 with a count of 3, then latched and read
+    pit.write_byte(0x43, 0b0011_0000);
+    pit.write_byte(0x40, 3);
+    pit.write_byte(0x40, 0);
+
+    assert_eq!(pit.tick(), 0);
+
+    pit.write_byte(0x43, 0b0000_0000);
+
+    assert_eq!(pit.tick(), 0);
+    assert_eq!(pit.read_byte(0x40), 2);
+    assert_eq!(pit.read_byte(0x40), 0);
+    assert_eq!(pit.read_byte(0x40), 1);
+    assert_eq!(pit.tick(), IRQ);
+
+    // Mode 0 fires once, and the count wraps around
+    assert_eq!(pit.tick(), 0);
+    assert_eq!(pit.read_byte(0x40), 0xff);
+    assert_eq!(pit.read_byte(0x40), 0xff);
+
+    // Counter 0 in mode 2 with a low byte count of 2 fires every period
+    pit.write_byte(0x43, 0b0001_0100);
+    pit.write_byte(0x40, 2);
+
+    let lines: Vec<u8> = (0..6).map(|_| pit.tick()).collect();
+
+    assert_eq!(lines, [0, IRQ, 0, IRQ, 0, IRQ]);
+
+    // Counter 2 counts without raising an interrupt
+    pit.write_byte(0x43, 0b1001_0110);
+    pit.write_byte(0x42, 1);
+
+    assert_eq!(pit.tick(), 0);
+    assert_eq!(pit.read_byte(0x42), 1);
+    assert_eq!(pit.read_byte(0x43), OPEN_BUS);
+}
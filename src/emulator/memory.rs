@@ -0,0 +1,236 @@
+use std::io::{self, Write};
+use std::ops::Range;
+
+/// Size of the 20-bit address space.
+pub const MEMORY_SIZE: usize = 1 << 20;
+
+/// Bytes above 1 MiB that segments reach with the A20 line enabled, the
+/// high memory area.
+const HIGH_MEMORY_SIZE: usize = 0x10000 - 0x10;
+
+/// Returns the 20-bit physical address of a segment and offset, wrapping
+/// around at 1 MiB like the 8086.
+pub fn physical(segment: u16, offset: u16) -> u32 {
+    (((segment as u32) << 4) + offset as u32) & (MEMORY_SIZE as u32 - 1)
+}
+
+/// A device mapped into memory, which is called with offsets into its
+/// region. Reads take `&self`, so a device that changes when read keeps
+/// that state in a `Cell`.
+pub trait Mmio: Send {
+    fn read(&self, offset: u32) -> u8;
+
+    fn write(&mut self, offset: u32, value: u8);
+}
+
+/// What a range of memory is mapped as.
+pub enum Region {
+    Ram,
+    /// Memory that ignores writes, though `Memory::load` still fills it.
+    Rom,
+    Mmio(Box<dyn Mmio>),
+}
+
+/// The 1 MiB address space of the 8086, all RAM until regions are mapped.
+pub struct Memory {
+    bytes: Vec<u8>,
+    /// Mapped regions, where the ones mapped later are in front.
+    regions: Vec<(Range<u32>, Region)>,
+    a20: bool,
+}
+
+impl Default for Memory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Memory {
+    pub fn new() -> Self {
+        Self {
+            bytes: vec![0; MEMORY_SIZE + HIGH_MEMORY_SIZE],
+            regions: Vec::new(),
+            a20: false,
+        }
+    }
+
+    /// Maps the range of physical addresses, over the regions mapped there
+    /// before.
+    pub fn map(&mut self, range: Range<u32>, region: Region) {
+        self.regions.push((range, region));
+    }
+
+    /// Lets addresses past 1 MiB reach the high memory area instead of
+    /// wrapping around, like the A20 line of the AT.
+    pub fn set_a20(&mut self, enabled: bool) {
+        self.a20 = enabled;
+    }
+
+    pub fn a20(&self) -> bool {
+        self.a20
+    }
+
+    /// Returns the physical address of a segment and offset, which is past
+    /// 1 MiB only with the A20 line enabled.
+    pub fn address(&self, segment: u16, offset: u16) -> u32 {
+        match self.a20 {
+            true => ((segment as u32) << 4) + offset as u32,
+            false => physical(segment, offset),
+        }
+    }
+
+    /// Returns the index of the byte at the address, wrapping it around.
+    fn index(&self, address: u32) -> usize {
+        match self.a20 {
+            true => address as usize % self.bytes.len(),
+            false => address as usize % MEMORY_SIZE,
+        }
+    }
+
+    fn region(&self, address: u32) -> Option<(u32, &Region)> {
+        self.regions
+            .iter()
+            .rev()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, region)| (range.start, region))
+    }
+
+    pub fn read_byte(&self, address: u32) -> u8 {
+        let index = self.index(address);
+
+        match self.region(index as u32) {
+            Some((start, Region::Mmio(device))) => device.read(index as u32 - start),
+            _ => self.bytes[index],
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) {
+        let index = self.index(address);
+        let region = self
+            .regions
+            .iter_mut()
+            .rev()
+            .find(|(range, _)| range.contains(&(index as u32)));
+
+        match region {
+            Some((range, Region::Mmio(device))) => device.write(index as u32 - range.start, value),
+            Some((_, Region::Rom)) => {}
+            _ => self.bytes[index] = value,
+        }
+    }
+
+    pub fn read_word(&self, address: u32) -> u16 {
+        u16::from_le_bytes([self.read_byte(address), self.read_byte(address + 1)])
+    }
+
+    pub fn write_word(&mut self, address: u32, value: u16) {
+        let [low, high] = value.to_le_bytes();
+
+        self.write_byte(address, low);
+        self.write_byte(address + 1, high);
+    }
+
+    /// Copies the bytes to memory from the address on, including ROM.
+    pub fn load(&mut self, address: u32, bytes: &[u8]) {
+        for (index, &byte) in bytes.iter().enumerate() {
+            let index = self.index(address + index as u32);
+
+            self.bytes[index] = byte;
+        }
+    }
+
+    /// Writes the memory as the CPU reads it, with the high memory area
+    /// after the first 1 MiB if the A20 line is enabled.
+    pub fn dump(&self, out: &mut impl Write) -> io::Result<()> {
+        let length = match self.a20 {
+            true => self.bytes.len(),
+            false => MEMORY_SIZE,
+        };
+
+        match self.regions.is_empty() {
+            true => out.write_all(&self.bytes[..length]),
+            false => {
+                let bytes: Vec<u8> = (0..length as u32)
+                    .map(|address| self.read_byte(address))
+                    .collect();
+
+                out.write_all(&bytes)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_memory() {
+    let mut memory = Memory::new();
+
+    assert_eq!(physical(0xffff, 0x0010), 0);
+    assert_eq!(physical(0x1234, 0x0010), 0x12350);
+
+    memory.write_word(0xfffff, 0xabcd);
+
+    assert_eq!(memory.read_byte(0xfffff), 0xcd);
+    assert_eq!(memory.read_byte(0), 0xab);
+    assert_eq!(memory.read_word(0xfffff), 0xabcd);
+
+    // With A20 enabled, FFFF:0010 is in the high memory area
+    memory.set_a20(true);
+
+    assert_eq!(memory.address(0xffff, 0x0010), 0x100000);
+
+    memory.write_byte(0x100000, 0x12);
+
+    assert_eq!(memory.read_byte(0), 0xab);
+
+    memory.set_a20(false);
+
+    assert_eq!(memory.address(0xffff, 0x0010), 0);
+    assert_eq!(memory.read_byte(0x100000), 0xab);
+}
+
+#[test]
+fn test_memory_regions() {
+    use std::sync::atomic::{AtomicU8, Ordering};
+
+    /// Reads back the last byte written plus the offset.
+    #[derive(Default)]
+    struct Latch(AtomicU8);
+
+    impl Mmio for Latch {
+        fn read(&self, offset: u32) -> u8 {
+            self.0.load(Ordering::Relaxed) + offset as u8
+        }
+
+        fn write(&mut self, _offset: u32, value: u8) {
+            self.0.store(value, Ordering::Relaxed);
+        }
+    }
+
+    let mut memory = Memory::new();
+
+    memory.map(0xf0000..0x100000, Region::Rom);
+    memory.load(0xffff0, &[0xea, 0x00, 0x00]);
+    memory.write_word(0xffff0, 0x9090);
+
+    assert_eq!(memory.read_word(0xffff0), 0x00ea);
+
+    // A RAM region over part of the ROM makes it writable again
+    memory.map(0xf8000..0xf8010, Region::Ram);
+    memory.write_byte(0xf8000, 1);
+
+    assert_eq!(memory.read_byte(0xf8000), 1);
+
+    memory.map(0xb0000..0xb0004, Region::Mmio(Box::<Latch>::default()));
+    memory.write_byte(0xb0002, 0x40);
+
+    assert_eq!(memory.read_word(0xb0000), 0x4140);
+    assert_eq!(memory.read_byte(0xb0004), 0);
+
+    let mut dump = Vec::new();
+
+    memory.dump(&mut dump).unwrap();
+
+    assert_eq!(dump.len(), MEMORY_SIZE);
+    assert_eq!(dump[0xb0003], 0x43);
+    assert_eq!(&dump[0xffff0..0xffff3], &[0xea, 0x00, 0x00]);
+}
@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::fmt;
+
+/// Bytes in the fixed part of the header.
+const HEADER_LENGTH: usize = 0x1c;
+
+const PAGE_LENGTH: usize = 512;
+
+#[derive(Debug)]
+pub struct MzError {
+    pub message: String,
+}
+
+impl Error for MzError {}
+
+impl fmt::Display for MzError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid MZ executable: {}", self.message)
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, MzError> {
+    Err(MzError {
+        message: message.into(),
+    })
+}
+
+/// The header of a DOS `.exe` program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MzHeader {
+    /// Offset of the load module in the file.
+    pub header_length: usize,
+    /// Length of the load module.
+    pub image_length: usize,
+    /// Paragraphs needed after the load module.
+    pub min_alloc: u16,
+    /// Paragraphs wanted after the load module.
+    pub max_alloc: u16,
+    /// Initial SS and CS relative to the load segment.
+    pub ss: u16,
+    pub sp: u16,
+    pub cs: u16,
+    pub ip: u16,
+    /// Offsets and segments of the words to add the load segment to.
+    pub relocations: Vec<(u16, u16)>,
+}
+
+/// Returns whether the bytes start with the MZ signature.
+pub fn is_mz(bytes: &[u8]) -> bool {
+    matches!(bytes, [b'M', b'Z', ..] | [b'Z', b'M', ..])
+}
+
+impl MzHeader {
+    pub fn parse(bytes: &[u8]) -> Result<Self, MzError> {
+        if !is_mz(bytes) {
+            return error("the MZ signature is missing");
+        }
+
+        if bytes.len() < HEADER_LENGTH {
+            return error("the header is cut off");
+        }
+
+        let word = |offset: usize| u16::from_le_bytes([bytes[offset], bytes[offset + 1]]);
+        let last_page_length = word(0x02) as usize;
+        let pages = word(0x04) as usize;
+        let relocation_count = word(0x06) as usize;
+        let header_length = word(0x08) as usize * 16;
+        let relocation_offset = word(0x18) as usize;
+
+        let mut file_length = pages * PAGE_LENGTH;
+
+        if last_page_length != 0 {
+            file_length = file_length.saturating_sub(PAGE_LENGTH - last_page_length);
+        }
+
+        // Some linkers round the length up, so the file may end before it
+        let file_length = file_length.min(bytes.len());
+
+        if header_length > file_length {
+            return error("the header is longer than the file");
+        }
+
+        let relocations_end = relocation_offset + relocation_count * 4;
+
+        if relocations_end > bytes.len() {
+            return error("the relocation table is cut off");
+        }
+
+        let relocations = (relocation_offset..relocations_end)
+            .step_by(4)
+            .map(|offset| (word(offset), word(offset + 2)))
+            .collect();
+
+        Ok(Self {
+            header_length,
+            image_length: file_length - header_length,
+            min_alloc: word(0x0a),
+            max_alloc: word(0x0c),
+            ss: word(0x0e),
+            sp: word(0x10),
+            ip: word(0x14),
+            cs: word(0x16),
+            relocations,
+        })
+    }
+
+    /// Returns the load module.
+    pub fn image<'a>(&self, bytes: &'a [u8]) -> &'a [u8] {
+        &bytes[self.header_length..self.header_length + self.image_length]
+    }
+}
+
+#[test]
+fn test_mz_header() {
+    // A header of 2 paragraphs with one relocation, and a 4 byte module
+    let mut bytes = vec![0; 0x20];
+
+    bytes[..0x1c].copy_from_slice(&[
+        b'M', b'Z', 0x24, 0x00, 0x01, 0x00, 0x01, 0x00, 0x02, 0x00, 0x10, 0x00, 0xff, 0xff, 0x01,
+        0x00, 0x00, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x1c, 0x00, 0x00, 0x00,
+    ]);
+    bytes[0x1c..0x20].copy_from_slice(&[0x01, 0x00, 0x00, 0x00]);
+    bytes.extend([0xb8, 0x00, 0x00, 0xf4]);
+
+    let header = MzHeader::parse(&bytes).unwrap();
+
+    assert_eq!(
+        header,
+        MzHeader {
+            header_length: 0x20,
+            image_length: 4,
+            min_alloc: 0x10,
+            max_alloc: 0xffff,
+            ss: 1,
+            sp: 0x100,
+            cs: 0,
+            ip: 2,
+            relocations: vec![(1, 0)],
+        }
+    );
+    assert_eq!(header.image(&bytes), &[0xb8, 0x00, 0x00, 0xf4]);
+    assert_eq!(
+        MzHeader::parse(b"MZ").unwrap_err().to_string(),
+        "Invalid MZ executable: the header is cut off"
+    );
+}
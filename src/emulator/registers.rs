@@ -0,0 +1,206 @@
+use std::fmt;
+
+use crate::instructions::common::{register, segment_register};
+
+/// Bits of the FLAGS register.
+pub mod flags {
+    pub const CF: u16 = 1 << 0;
+    pub const PF: u16 = 1 << 2;
+    pub const AF: u16 = 1 << 4;
+    pub const ZF: u16 = 1 << 6;
+    pub const SF: u16 = 1 << 7;
+    pub const TF: u16 = 1 << 8;
+    pub const IF: u16 = 1 << 9;
+    pub const DF: u16 = 1 << 10;
+    pub const OF: u16 = 1 << 11;
+
+    /// Bits that read as set on the 8086 whatever is written to them.
+    pub const RESERVED: u16 = 0xf002;
+    /// Bits that can be written.
+    pub const WRITABLE: u16 = CF | PF | AF | ZF | SF | TF | IF | DF | OF;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Registers {
+    pub ax: u16,
+    pub bx: u16,
+    pub cx: u16,
+    pub dx: u16,
+    pub sp: u16,
+    pub bp: u16,
+    pub si: u16,
+    pub di: u16,
+    pub cs: u16,
+    pub ds: u16,
+    pub es: u16,
+    pub ss: u16,
+    pub ip: u16,
+    pub flags: u16,
+}
+
+impl Default for Registers {
+    fn default() -> Self {
+        Self {
+            ax: 0,
+            bx: 0,
+            cx: 0,
+            dx: 0,
+            sp: 0,
+            bp: 0,
+            si: 0,
+            di: 0,
+            cs: 0,
+            ds: 0,
+            es: 0,
+            ss: 0,
+            ip: 0,
+            flags: flags::RESERVED,
+        }
+    }
+}
+
+impl Registers {
+    /// Returns the 16-bit register, numbered like `common::register`.
+    pub fn word(&self, number: u8) -> u16 {
+        match number & 0b111 {
+            register::AX => self.ax,
+            register::CX => self.cx,
+            register::DX => self.dx,
+            register::BX => self.bx,
+            register::SP => self.sp,
+            register::BP => self.bp,
+            register::SI => self.si,
+            _ => self.di,
+        }
+    }
+
+    pub fn set_word(&mut self, number: u8, value: u16) {
+        let register = match number & 0b111 {
+            register::AX => &mut self.ax,
+            register::CX => &mut self.cx,
+            register::DX => &mut self.dx,
+            register::BX => &mut self.bx,
+            register::SP => &mut self.sp,
+            register::BP => &mut self.bp,
+            register::SI => &mut self.si,
+            _ => &mut self.di,
+        };
+
+        *register = value;
+    }
+
+    /// Returns the 8-bit register, numbered AL, CL, DL, BL, AH, CH, DH, BH.
+    pub fn byte(&self, number: u8) -> u8 {
+        let word = self.word(number & 0b11);
+
+        match number & 0b100 {
+            0 => word as u8,
+            _ => (word >> 8) as u8,
+        }
+    }
+
+    pub fn set_byte(&mut self, number: u8, value: u8) {
+        let word = self.word(number & 0b11);
+        let word = match number & 0b100 {
+            0 => word & 0xff00 | value as u16,
+            _ => word & 0x00ff | (value as u16) << 8,
+        };
+
+        self.set_word(number & 0b11, word);
+    }
+
+    /// Returns the segment register, numbered like `common::segment_register`.
+    pub fn segment(&self, number: u8) -> u16 {
+        match number & 0b11 {
+            segment_register::ES => self.es,
+            segment_register::CS => self.cs,
+            segment_register::SS => self.ss,
+            _ => self.ds,
+        }
+    }
+
+    pub fn set_segment(&mut self, number: u8, value: u16) {
+        let register = match number & 0b11 {
+            segment_register::ES => &mut self.es,
+            segment_register::CS => &mut self.cs,
+            segment_register::SS => &mut self.ss,
+            _ => &mut self.ds,
+        };
+
+        *register = value;
+    }
+
+    pub fn flag(&self, flag: u16) -> bool {
+        self.flags & flag != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u16, value: bool) {
+        match value {
+            true => self.flags |= flag,
+            false => self.flags &= !flag,
+        }
+    }
+
+    /// Sets the writable flags, keeping the reserved bits.
+    pub fn set_flags(&mut self, value: u16) {
+        self.flags = value & flags::WRITABLE | flags::RESERVED;
+    }
+}
+
+/// Names of the set flags, e.g. `ZP` for zero and parity.
+pub fn flag_names(value: u16) -> String {
+    const NAMES: [(u16, char); 9] = [
+        (flags::OF, 'O'),
+        (flags::DF, 'D'),
+        (flags::IF, 'I'),
+        (flags::TF, 'T'),
+        (flags::SF, 'S'),
+        (flags::ZF, 'Z'),
+        (flags::AF, 'A'),
+        (flags::PF, 'P'),
+        (flags::CF, 'C'),
+    ];
+
+    NAMES
+        .iter()
+        .filter(|(flag, _)| value & flag != 0)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "ax={:04x} bx={:04x} cx={:04x} dx={:04x} sp={:04x} bp={:04x} si={:04x} di={:04x}",
+            self.ax, self.bx, self.cx, self.dx, self.sp, self.bp, self.si, self.di
+        )?;
+        write!(
+            f,
+            "cs={:04x} ds={:04x} es={:04x} ss={:04x} ip={:04x} flags={:04x}",
+            self.cs, self.ds, self.es, self.ss, self.ip, self.flags
+        )?;
+
+        match flag_names(self.flags).as_str() {
+            "" => Ok(()),
+            names => write!(f, " {}", names),
+        }
+    }
+}
+
+#[test]
+fn test_registers() {
+    let mut registers = Registers::default();
+
+    registers.set_word(register::BX, 0x1234);
+    registers.set_byte(0b111, 0xab);
+    registers.set_byte(register::BX, 0xcd);
+
+    assert_eq!(registers.bx, 0xabcd);
+    assert_eq!(registers.byte(0b111), 0xab);
+
+    registers.set_flags(0xffff);
+
+    assert_eq!(registers.flags, 0xffd7);
+    assert_eq!(flag_names(registers.flags), "ODITSZAPC");
+}
@@ -0,0 +1,119 @@
+use std::error::Error;
+use std::io::Write;
+
+use super::{
+    memory::physical,
+    registers::{flag_names, Registers},
+    Machine, Stop, MAX_PREFIXES,
+};
+use crate::listing::Listing;
+
+/// Returns the registers and flags that differ between the states, like
+/// `cx:0x0000->0x1234 ip:0x0100->0x0102 flags:->PZ`.
+pub fn changes(before: &Registers, after: &Registers) -> String {
+    let registers = [
+        ("ax", before.ax, after.ax),
+        ("bx", before.bx, after.bx),
+        ("cx", before.cx, after.cx),
+        ("dx", before.dx, after.dx),
+        ("sp", before.sp, after.sp),
+        ("bp", before.bp, after.bp),
+        ("si", before.si, after.si),
+        ("di", before.di, after.di),
+        ("cs", before.cs, after.cs),
+        ("ds", before.ds, after.ds),
+        ("es", before.es, after.es),
+        ("ss", before.ss, after.ss),
+        ("ip", before.ip, after.ip),
+    ];
+    let mut changes: Vec<String> = registers
+        .iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(name, before, after)| format!("{}:{:#06x}->{:#06x}", name, before, after))
+        .collect();
+
+    if before.flags != after.flags {
+        changes.push(format!(
+            "flags:{}->{}",
+            flag_names(before.flags),
+            flag_names(after.flags)
+        ));
+    }
+
+    changes.join(" ")
+}
+
+/// Writes the instruction at CS:IP with its prefixes, with jump targets as
+/// offsets in the code segment.
+pub fn instruction_text(machine: &Machine) -> Option<String> {
+    let registers = &machine.registers;
+    let bytes: Vec<u8> = (0..MAX_PREFIXES + 6)
+        .map(|index| {
+            machine
+                .memory
+                .read_byte(physical(registers.cs, registers.ip.wrapping_add(index)))
+        })
+        .collect();
+    let listing = Listing::new(&bytes).with_origin(registers.ip as usize);
+    let listing = match machine.undocumented {
+        true => listing.with_undocumented(),
+        false => listing,
+    };
+
+    listing.line_at(0).map(|(_, text)| text)
+}
+
+/// Runs the machine like `Machine::run`, writing each executed instruction
+/// with the registers and flags it changed, like
+/// `mov cx, bx ; cx:0x0000->0x1234 ip:0x0100->0x0102`.
+pub fn run(
+    machine: &mut Machine,
+    max_steps: u64,
+    out: &mut impl Write,
+) -> Result<Stop, Box<dyn Error>> {
+    for _ in 0..max_steps {
+        let before = machine.registers;
+        let steps = machine.steps;
+        let text = instruction_text(machine);
+        let stop = machine.step()?;
+
+        if machine.steps > steps {
+            writeln!(
+                out,
+                "{} ; {}",
+                text.unwrap_or_default(),
+                changes(&before, &machine.registers)
+            )?;
+        }
+
+        if let Some(stop) = stop {
+            return Ok(stop);
+        }
+    }
+
+    Ok(Stop::StepLimit)
+}
+
+#[test]
+fn test_trace() {
+    use super::LOAD_SEGMENT;
+
+    // mov cx, 0x1234; mov ax, cx; sub ax, ax; jmp to the next line; hlt
+    let bytes = [0xb9, 0x34, 0x12, 0x89, 0xc8, 0x29, 0xc0, 0xeb, 0x00, 0xf4];
+    let mut machine = Machine::new();
+    let mut output = Vec::new();
+
+    machine.load(&bytes, LOAD_SEGMENT, 0x100);
+
+    assert_eq!(run(&mut machine, 10, &mut output).unwrap(), Stop::Halted);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\
+mov cx, 0x1234 ; cx:0x0000->0x1234 ip:0x0100->0x0103
+mov ax, cx ; ax:0x0000->0x1234 ip:0x0103->0x0105
+sub ax, ax ; ax:0x1234->0x0000 ip:0x0105->0x0107 flags:->ZP
+jmp 0x0109 ; ip:0x0107->0x0109
+hlt ; ip:0x0109->0x010a
+"
+    );
+}
@@ -0,0 +1,367 @@
+use super::{
+    memory::{physical, Memory},
+    registers::Registers,
+    Machine,
+};
+
+pub const COLUMNS: u16 = 80;
+pub const ROWS: u16 = 25;
+
+/// Interrupt of the BIOS video services.
+pub const VIDEO_INTERRUPT: u8 = 0x10;
+
+/// BIOS data area entries of the video mode, the number of columns and the
+/// cursor position of page 0.
+const MODE: u32 = 0x449;
+const COLUMN_COUNT: u32 = 0x44a;
+const CURSOR: u32 = 0x450;
+
+/// Attribute of the cells cleared by the BIOS, light gray on black.
+const BLANK_ATTRIBUTE: u8 = 0x07;
+
+/// Characters 0x00 to 0x1f and 0x7f to 0xff of code page 437.
+const CP437_LOW: &str = " ☺☻♥♦♣♠•◘○◙♂♀♪♫☼►◄↕‼¶§▬↨↑↓→←∟↔▲▼";
+const CP437_HIGH: &str = "⌂ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+/// ANSI color numbers of the CGA colors, whose red and blue bits are the
+/// other way around.
+const ANSI_COLORS: [u8; 8] = [0, 4, 2, 6, 1, 5, 3, 7];
+
+/// Returns the character of a byte in code page 437.
+pub fn cp437(byte: u8) -> char {
+    match byte {
+        0x20..=0x7e => byte as char,
+        0x00..=0x1f => CP437_LOW.chars().nth(byte as usize).unwrap_or(' '),
+        _ => CP437_HIGH.chars().nth(byte as usize - 0x7f).unwrap_or(' '),
+    }
+}
+
+/// The display adapter whose 80x25 text mode video RAM is read.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Adapter {
+    /// Color text at B800:0000, in mode 3.
+    Cga,
+    /// Monochrome text at B000:0000, in mode 7.
+    Mda,
+}
+
+impl Adapter {
+    pub fn segment(self) -> u16 {
+        match self {
+            Adapter::Cga => 0xb800,
+            Adapter::Mda => 0xb000,
+        }
+    }
+
+    fn mode(self) -> u8 {
+        match self {
+            Adapter::Cga => 3,
+            Adapter::Mda => 7,
+        }
+    }
+
+    /// Returns the character and attribute of a cell.
+    fn cell(self, memory: &Memory, row: u16, column: u16) -> (u8, u8) {
+        let address = physical(self.segment(), (row * COLUMNS + column) * 2);
+
+        (memory.read_byte(address), memory.read_byte(address + 1))
+    }
+
+    fn set_cell(self, memory: &mut Memory, row: u16, column: u16, character: u8, attribute: u8) {
+        let address = physical(self.segment(), (row * COLUMNS + column) * 2);
+
+        memory.write_byte(address, character);
+        memory.write_byte(address + 1, attribute);
+    }
+
+    /// Fills the screen with blanks and moves the cursor home, like setting
+    /// the video mode.
+    fn clear(self, memory: &mut Memory) {
+        for row in 0..ROWS {
+            for column in 0..COLUMNS {
+                self.set_cell(memory, row, column, b' ', BLANK_ATTRIBUTE);
+            }
+        }
+
+        memory.write_byte(MODE, self.mode());
+        memory.write_word(COLUMN_COUNT, COLUMNS);
+        memory.write_word(CURSOR, 0);
+    }
+
+    /// Moves the rows up by one, blanking the last.
+    fn scroll(self, memory: &mut Memory) {
+        for row in 1..ROWS {
+            for column in 0..COLUMNS {
+                let (character, attribute) = self.cell(memory, row, column);
+
+                self.set_cell(memory, row - 1, column, character, attribute);
+            }
+        }
+
+        for column in 0..COLUMNS {
+            self.set_cell(memory, ROWS - 1, column, b' ', BLANK_ATTRIBUTE);
+        }
+    }
+
+    /// Writes a character like the teletype output of the BIOS, moving the
+    /// cursor and scrolling at the bottom.
+    pub fn teletype(self, memory: &mut Memory, character: u8) {
+        let [mut column, mut row] = memory.read_word(CURSOR).to_le_bytes().map(u16::from);
+
+        match character {
+            0x07 => {}
+            0x08 => column = column.saturating_sub(1),
+            b'\n' => row += 1,
+            b'\r' => column = 0,
+            _ => {
+                let (_, attribute) = self.cell(memory, row, column);
+
+                self.set_cell(memory, row, column, character, attribute);
+                column += 1;
+
+                if column == COLUMNS {
+                    column = 0;
+                    row += 1;
+                }
+            }
+        }
+
+        if row == ROWS {
+            self.scroll(memory);
+            row = ROWS - 1;
+        }
+
+        memory.write_word(CURSOR, u16::from_le_bytes([column as u8, row as u8]));
+    }
+
+    /// Services INT 10h.
+    fn int10(self, registers: &mut Registers, memory: &mut Memory) {
+        let function = (registers.ax >> 8) as u8;
+        let cursor = memory.read_word(CURSOR);
+
+        match function {
+            0x00 => self.clear(memory),
+            0x02 => {
+                let [column, row] = registers.dx.to_le_bytes();
+
+                memory.write_word(
+                    CURSOR,
+                    u16::from_le_bytes([column.min(COLUMNS as u8 - 1), row.min(ROWS as u8 - 1)]),
+                );
+            }
+            0x03 => {
+                registers.dx = cursor;
+                registers.cx = 0x0607;
+            }
+            0x09 | 0x0a => {
+                // Writes CX times without moving the cursor
+                let [column, row] = cursor.to_le_bytes().map(u16::from);
+                let start = row * COLUMNS + column;
+                let end = start.saturating_add(registers.cx).min(ROWS * COLUMNS);
+
+                for cell in start..end {
+                    let (_, attribute) = self.cell(memory, cell / COLUMNS, cell % COLUMNS);
+                    let attribute = match function {
+                        0x09 => registers.bx as u8,
+                        _ => attribute,
+                    };
+
+                    self.set_cell(
+                        memory,
+                        cell / COLUMNS,
+                        cell % COLUMNS,
+                        registers.ax as u8,
+                        attribute,
+                    );
+                }
+            }
+            0x0e => self.teletype(memory, registers.ax as u8),
+            0x0f => {
+                registers.ax = u16::from_le_bytes([self.mode(), COLUMNS as u8]);
+                registers.bx &= 0x00ff;
+            }
+            _ => {}
+        }
+    }
+
+    /// Clears the screen and hooks INT 10h with the services to set the
+    /// mode (00h), set and get the cursor (02h, 03h), write characters
+    /// (09h, 0Ah, 0Eh) and get the mode (0Fh). Other functions do nothing.
+    pub fn hook(self, machine: &mut Machine) {
+        self.clear(&mut machine.memory);
+        machine.hook(VIDEO_INTERRUPT, move |machine| {
+            self.int10(&mut machine.registers, &mut machine.memory);
+            None
+        });
+    }
+
+    /// Returns the screen as lines of text, without trailing blanks.
+    pub fn text(self, memory: &Memory) -> String {
+        let mut lines: Vec<String> = (0..ROWS)
+            .map(|row| {
+                let line: String = (0..COLUMNS)
+                    .map(|column| cp437(self.cell(memory, row, column).0))
+                    .collect();
+
+                line.trim_end().to_string()
+            })
+            .collect();
+
+        while lines.last().is_some_and(|line| line.is_empty()) {
+            lines.pop();
+        }
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Returns the escape sequence that sets the attribute on a terminal.
+    fn sgr(self, attribute: u8) -> String {
+        let foreground = attribute & 0b111;
+        let background = attribute >> 4 & 0b111;
+        let bright = attribute & 0b1000 != 0;
+
+        let codes = match self {
+            Adapter::Cga => {
+                let foreground = ANSI_COLORS[foreground as usize];
+
+                format!(
+                    "{};{}",
+                    match bright {
+                        true => 90 + foreground,
+                        false => 30 + foreground,
+                    },
+                    40 + ANSI_COLORS[background as usize]
+                )
+            }
+            // The MDA only tells apart underlined, reverse, hidden and
+            // intense text
+            Adapter::Mda => {
+                let mut codes = vec!["0"];
+
+                if bright {
+                    codes.push("1");
+                }
+
+                match (background, foreground) {
+                    (0, 0) => codes.push("8"),
+                    (0b111, 0) => codes.push("7"),
+                    (_, 1) => codes.push("4"),
+                    _ => {}
+                }
+
+                codes.join(";")
+            }
+        };
+
+        format!("\x1b[{}m", codes)
+    }
+
+    /// Returns the screen with the attributes as ANSI escape sequences, each
+    /// line resetting them at its end.
+    pub fn ansi(self, memory: &Memory) -> String {
+        let mut out = String::new();
+
+        for row in 0..ROWS {
+            let mut current = None;
+
+            for column in 0..COLUMNS {
+                let (character, attribute) = self.cell(memory, row, column);
+
+                if current != Some(attribute) {
+                    out.push_str(&self.sgr(attribute));
+                    current = Some(attribute);
+                }
+
+                out.push(cp437(character));
+            }
+
+            out.push_str("\x1b[0m\n");
+        }
+
+        out
+    }
+}
+
+#[test]
+fn test_video() {
+    use super::{Stop, LOAD_SEGMENT};
+    use crate::assembler::assemble;
+
+    assert_eq!(
+        CP437_LOW.chars().count() + CP437_HIGH.chars().count(),
+        0x20 + 0x81
+    );
+    assert_eq!(cp437(0xdb), '█');
+
+    // Writes to video RAM directly and through the BIOS
+    let program = assemble(
+        "\
+bits 16
+org 0
+mov ax, 0xb800
+mov es, ax
+mov word [es:160], 0x1f41
+mov ah, 0x02
+mov dx, 0x0002
+int 0x10
+mov ax, 0x0e48
+int 0x10
+mov al, 0x69
+int 0x10
+mov al, 0x0d
+int 0x10
+mov al, 0x0a
+int 0x10
+mov al, 0x0a
+int 0x10
+mov ah, 0x03
+int 0x10
+hlt
+",
+    )
+    .unwrap();
+    let mut machine = Machine::new();
+
+    Adapter::Cga.hook(&mut machine);
+    machine.load(&program, LOAD_SEGMENT, 0);
+
+    assert_eq!(machine.run(100).unwrap(), Stop::Halted);
+    assert_eq!(machine.registers.dx, 0x0200);
+    assert_eq!(Adapter::Cga.text(&machine.memory), "  Hi\nA\n");
+
+    let ansi = Adapter::Cga.ansi(&machine.memory);
+
+    assert!(ansi.starts_with("\x1b[37;40m  Hi "));
+    assert!(ansi.contains("\x1b[0m\n\x1b[97;44mA\x1b[37;40m "));
+    assert_eq!(ansi.lines().count(), ROWS as usize);
+
+    // Teletype output scrolls at the bottom
+    for _ in 0..ROWS {
+        Adapter::Cga.teletype(&mut machine.memory, b'\n');
+    }
+
+    assert_eq!(Adapter::Cga.text(&machine.memory), "");
+
+    // A count past the end of the screen stops there
+    let mut registers = Registers {
+        ax: 0x0200,
+        dx: 0x0001,
+        ..Registers::default()
+    };
+
+    Adapter::Cga.int10(&mut registers, &mut machine.memory);
+    registers.ax = 0x0958;
+    registers.bx = 0x0007;
+    registers.cx = 0xffff;
+    Adapter::Cga.int10(&mut registers, &mut machine.memory);
+
+    assert_eq!(
+        Adapter::Cga.cell(&machine.memory, ROWS - 1, COLUMNS - 1),
+        (b'X', 0x07)
+    );
+
+    // The MDA underlines and reverses text
+    assert_eq!(Adapter::Mda.sgr(0x01), "\x1b[0;4m");
+    assert_eq!(Adapter::Mda.sgr(0x70), "\x1b[0;7m");
+    assert_eq!(Adapter::Mda.sgr(0x0f), "\x1b[0;1m");
+}
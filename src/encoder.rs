@@ -0,0 +1,665 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{
+    instructions::{
+        arithmetic::ARITHMETIC_OPCODES,
+        common::{effective, register},
+        opcode::Opcode,
+        operand::{Memory, Operand, Operation, Size},
+    },
+    Instruction,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncodeError {
+    pub operation: Operation,
+    pub message: String,
+}
+
+impl Error for EncodeError {}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Can not encode {}: {}",
+            self.operation.opcode.get_mnemonic(),
+            self.message
+        )
+    }
+}
+
+type EncodeResult = Result<Vec<u8>, String>;
+
+fn fits_i8(value: i16) -> bool {
+    value as i8 as i16 == value
+}
+
+/// Returns whether the operand is 16 bits wide, or `None` if it has no size.
+fn operand_word(operand: &Operand) -> Result<Option<bool>, String> {
+    match operand {
+        Operand::Byte(_) => Ok(Some(false)),
+        Operand::Word(_) | Operand::Segment(_) => Ok(Some(true)),
+        Operand::Memory(Memory {
+            size: Size::Far, ..
+        }) => Err("far memory operand".to_string()),
+        Operand::Memory(memory) => Ok(Some(memory.size == Size::Word)),
+        _ => Ok(None),
+    }
+}
+
+/// Returns whether the operands are 16 bits wide, checking that they agree.
+fn operands_word(operands: &[Operand]) -> Result<bool, String> {
+    let mut word = None;
+
+    for operand in operands {
+        match (word, operand_word(operand)?) {
+            (Some(a), Some(b)) if a != b => return Err("operand sizes differ".to_string()),
+            (None, size) => word = size,
+            _ => {}
+        }
+    }
+
+    word.ok_or_else(|| "operand size is unknown".to_string())
+}
+
+fn immediate(value: u16, word: bool) -> EncodeResult {
+    if word {
+        Ok(value.to_le_bytes().to_vec())
+    } else if value <= 0xff || value >= 0xff80 {
+        Ok(vec![value as u8])
+    } else {
+        Err(format!("immediate {:#x} does not fit in a byte", value))
+    }
+}
+
+/// Encodes the ModRM byte and displacement for `rm` with `reg` in the
+/// register field.
+fn modrm(reg: u8, rm: &Operand) -> EncodeResult {
+    let reg = reg << 3;
+
+    match rm {
+        Operand::Byte(register) | Operand::Word(register) => {
+            Ok(vec![0b11_000_000 | reg | register])
+        }
+        Operand::Memory(Memory {
+            base: None,
+            displacement,
+            ..
+        }) => {
+            let [low, high] = displacement.to_le_bytes();
+            Ok(vec![reg | effective::BP_OR_DIRECT_ADDRESS, low, high])
+        }
+        Operand::Memory(Memory {
+            base: Some(base),
+            displacement,
+            ..
+        }) => {
+            if *displacement == 0 && *base != effective::BP_OR_DIRECT_ADDRESS {
+                Ok(vec![reg | base])
+            } else if fits_i8(*displacement) {
+                Ok(vec![0b01_000_000 | reg | base, *displacement as u8])
+            } else {
+                let [low, high] = displacement.to_le_bytes();
+                Ok(vec![0b10_000_000 | reg | base, low, high])
+            }
+        }
+        operand => Err(format!("{:?} is not a register or memory operand", operand)),
+    }
+}
+
+fn with_modrm(opcode: u8, reg: u8, rm: &Operand) -> EncodeResult {
+    let mut bytes = vec![opcode];
+    bytes.extend(modrm(reg, rm)?);
+
+    Ok(bytes)
+}
+
+fn is_register(operand: &Operand) -> bool {
+    matches!(operand, Operand::Byte(_) | Operand::Word(_))
+}
+
+fn is_rm(operand: &Operand) -> bool {
+    matches!(
+        operand,
+        Operand::Byte(_) | Operand::Word(_) | Operand::Memory(_)
+    )
+}
+
+fn is_accumulator(operand: &Operand) -> bool {
+    matches!(
+        operand,
+        Operand::Byte(register::AX) | Operand::Word(register::AX)
+    )
+}
+
+fn register_number(operand: &Operand) -> u8 {
+    match operand {
+        Operand::Byte(register) | Operand::Word(register) | Operand::Segment(register) => *register,
+        _ => unreachable!("Operand is not a register: {:?}", operand),
+    }
+}
+
+/// Encodes ADD, OR, ADC, SBB, AND, SUB, XOR and CMP.
+fn encode_arithmetic(index: u8, operands: &[Operand]) -> EncodeResult {
+    let word = operands_word(operands)?;
+    let w = word as u8;
+
+    match operands {
+        [destination, source] if is_rm(destination) && is_register(source) => {
+            with_modrm(index << 3 | w, register_number(source), destination)
+        }
+        [destination, source @ Operand::Memory(_)] if is_register(destination) => {
+            with_modrm(index << 3 | 0b10 | w, register_number(destination), source)
+        }
+        [destination, Operand::Immediate(value)] if is_rm(destination) => {
+            if word && fits_i8(*value as i16) {
+                let mut bytes = with_modrm(0x83, index, destination)?;
+                bytes.push(*value as u8);
+                Ok(bytes)
+            } else if is_accumulator(destination) {
+                let mut bytes = vec![index << 3 | 0b100 | w];
+                bytes.extend(immediate(*value, word)?);
+                Ok(bytes)
+            } else {
+                let mut bytes = with_modrm(0x80 | w, index, destination)?;
+                bytes.extend(immediate(*value, word)?);
+                Ok(bytes)
+            }
+        }
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+fn encode_mov(operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [Operand::Segment(segment), source] if is_rm(source) => {
+            if operand_word(source)? != Some(true) {
+                return Err("segment registers are 16 bits wide".to_string());
+            }
+            with_modrm(0x8e, *segment, source)
+        }
+        [destination, Operand::Segment(segment)] if is_rm(destination) => {
+            if operand_word(destination)? != Some(true) {
+                return Err("segment registers are 16 bits wide".to_string());
+            }
+            with_modrm(0x8c, *segment, destination)
+        }
+        [accumulator, Operand::Memory(Memory {
+            base: None,
+            displacement,
+            ..
+        })]
+        | [Operand::Memory(Memory {
+            base: None,
+            displacement,
+            ..
+        }), accumulator]
+            if is_accumulator(accumulator) =>
+        {
+            let word = operands_word(operands)?;
+            let to_memory = matches!(operands[0], Operand::Memory(_));
+            let mut bytes = vec![0xa0 | (to_memory as u8) << 1 | word as u8];
+            bytes.extend(displacement.to_le_bytes());
+            Ok(bytes)
+        }
+        [destination, Operand::Immediate(value)] if is_register(destination) => {
+            let word = operands_word(operands)?;
+            let mut bytes = vec![0xb0 | (word as u8) << 3 | register_number(destination)];
+            bytes.extend(immediate(*value, word)?);
+            Ok(bytes)
+        }
+        [destination @ Operand::Memory(_), Operand::Immediate(value)] => {
+            let word = operands_word(operands)?;
+            let mut bytes = with_modrm(0xc6 | word as u8, 0, destination)?;
+            bytes.extend(immediate(*value, word)?);
+            Ok(bytes)
+        }
+        [destination, source] if is_rm(destination) && is_register(source) => {
+            let word = operands_word(operands)?;
+            with_modrm(0x88 | word as u8, register_number(source), destination)
+        }
+        [destination, source @ Operand::Memory(_)] if is_register(destination) => {
+            let word = operands_word(operands)?;
+            with_modrm(0x8a | word as u8, register_number(destination), source)
+        }
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+fn encode_test(operands: &[Operand]) -> EncodeResult {
+    let word = operands_word(operands)?;
+    let w = word as u8;
+
+    match operands {
+        [accumulator, Operand::Immediate(value)] if is_accumulator(accumulator) => {
+            let mut bytes = vec![0xa8 | w];
+            bytes.extend(immediate(*value, word)?);
+            Ok(bytes)
+        }
+        [destination, Operand::Immediate(value)] if is_rm(destination) => {
+            let mut bytes = with_modrm(0xf6 | w, 0, destination)?;
+            bytes.extend(immediate(*value, word)?);
+            Ok(bytes)
+        }
+        [destination, source] if is_rm(destination) && is_register(source) => {
+            with_modrm(0x84 | w, register_number(source), destination)
+        }
+        [destination, source @ Operand::Memory(_)] if is_register(destination) => {
+            with_modrm(0x84 | w, register_number(destination), source)
+        }
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+fn encode_xchg(operands: &[Operand]) -> EncodeResult {
+    let word = operands_word(operands)?;
+
+    match operands {
+        // 0x90 is NOP, so `xchg ax, ax` uses the long form
+        [Operand::Word(register), Operand::Word(register::AX)] if *register != register::AX => {
+            Ok(vec![0x90 | register])
+        }
+        [destination, source] if is_register(destination) && is_rm(source) => {
+            with_modrm(0x86 | word as u8, register_number(destination), source)
+        }
+        [destination @ Operand::Memory(_), source] if is_register(source) => {
+            with_modrm(0x86 | word as u8, register_number(source), destination)
+        }
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+/// Encodes an instruction with a single register or memory operand and an
+/// opcode extension in the register field, e.g. NOT or INC.
+fn encode_group(opcode: u8, extension: u8, operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [operand] if is_rm(operand) => {
+            let word = operands_word(operands)?;
+            with_modrm(opcode | word as u8, extension, operand)
+        }
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+fn encode_shift(extension: u8, operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [operand, Operand::Immediate(1)] if is_rm(operand) => {
+            encode_group(0xd0, extension, &operands[..1])
+        }
+        [operand, Operand::Byte(register::CX)] if is_rm(operand) => {
+            encode_group(0xd2, extension, &operands[..1])
+        }
+        _ => Err("count must be 1 or cl".to_string()),
+    }
+}
+
+fn encode_push_pop(push: bool, operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [Operand::Word(register)] => Ok(vec![if push { 0x50 } else { 0x58 } | register]),
+        [Operand::Segment(segment)] => Ok(vec![segment << 3 | 0b110 | !push as u8]),
+        [operand @ Operand::Memory(Memory {
+            size: Size::Word, ..
+        })] => {
+            if push {
+                with_modrm(0xff, 0b110, operand)
+            } else {
+                with_modrm(0x8f, 0b000, operand)
+            }
+        }
+        _ => Err("operand must be a word register or word memory".to_string()),
+    }
+}
+
+fn encode_jump_or_call(call: bool, operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [Operand::Relative(relative)] => {
+            let short = relative.wrapping_sub(2);
+
+            if !call && fits_i8(short) {
+                return Ok(vec![0xeb, short as u8]);
+            }
+
+            let mut bytes = vec![if call { 0xe8 } else { 0xe9 }];
+            bytes.extend(relative.wrapping_sub(3).to_le_bytes());
+            Ok(bytes)
+        }
+        [Operand::Far { segment, offset }] => {
+            let mut bytes = vec![if call { 0x9a } else { 0xea }];
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(segment.to_le_bytes());
+            Ok(bytes)
+        }
+        [operand @ Operand::Memory(Memory {
+            size: Size::Far, ..
+        })] => with_modrm(0xff, if call { 0b011 } else { 0b101 }, operand),
+        [operand @ (Operand::Word(_)
+        | Operand::Memory(Memory {
+            size: Size::Word, ..
+        }))] => with_modrm(0xff, if call { 0b010 } else { 0b100 }, operand),
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+fn encode_short_jump(opcode: u8, operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [Operand::Relative(relative)] => {
+            let displacement = relative.wrapping_sub(2);
+
+            if fits_i8(displacement) {
+                Ok(vec![opcode, displacement as u8])
+            } else {
+                Err(format!("jump of {} bytes is out of range", relative))
+            }
+        }
+        _ => Err("operand must be a relative target".to_string()),
+    }
+}
+
+fn encode_in_out(opcode: Opcode, operands: &[Operand]) -> EncodeResult {
+    let (port, accumulator) = match (opcode, operands) {
+        (Opcode::IN, [accumulator, port]) | (Opcode::OUT, [port, accumulator]) => {
+            (port, accumulator)
+        }
+        _ => return Err("invalid operands".to_string()),
+    };
+    let out = (opcode == Opcode::OUT) as u8;
+
+    if !is_accumulator(accumulator) {
+        return Err("operand must be al or ax".to_string());
+    }
+
+    let w = operands_word(&[*accumulator])? as u8;
+
+    match port {
+        Operand::Immediate(port) if *port <= 0xff => Ok(vec![0xe4 | out << 1 | w, *port as u8]),
+        Operand::Word(register::DX) => Ok(vec![0xec | out << 1 | w]),
+        _ => Err("port must be an 8-bit immediate or dx".to_string()),
+    }
+}
+
+fn encode_load_address(opcode: u8, operands: &[Operand]) -> EncodeResult {
+    match operands {
+        [Operand::Word(register), source] if is_rm(source) => with_modrm(opcode, *register, source),
+        _ => Err("invalid operands".to_string()),
+    }
+}
+
+fn encode_bare(byte: u8, operands: &[Operand]) -> EncodeResult {
+    if operands.is_empty() {
+        Ok(vec![byte])
+    } else {
+        Err("no operands expected".to_string())
+    }
+}
+
+fn encode_operation(operation: &Operation) -> EncodeResult {
+    let operands = operation.operands.as_slice();
+    let opcode = operation.opcode;
+
+    if let Some(index) = ARITHMETIC_OPCODES.iter().position(|&op| op == opcode) {
+        return encode_arithmetic(index as u8, operands);
+    }
+
+    match opcode {
+        Opcode::MOV => encode_mov(operands),
+        Opcode::TEST => encode_test(operands),
+        Opcode::XCHG => encode_xchg(operands),
+        Opcode::INC | Opcode::DEC => match operands {
+            [Operand::Word(register)] => Ok(vec![
+                if opcode == Opcode::INC { 0x40 } else { 0x48 } | register,
+            ]),
+            _ => encode_group(0xfe, (opcode == Opcode::DEC) as u8, operands),
+        },
+        Opcode::NOT => encode_group(0xf6, 0b010, operands),
+        Opcode::NEG => encode_group(0xf6, 0b011, operands),
+        Opcode::MUL => encode_group(0xf6, 0b100, operands),
+        Opcode::IMUL => encode_group(0xf6, 0b101, operands),
+        Opcode::DIV => encode_group(0xf6, 0b110, operands),
+        Opcode::IDIV => encode_group(0xf6, 0b111, operands),
+        Opcode::ROL => encode_shift(0b000, operands),
+        Opcode::ROR => encode_shift(0b001, operands),
+        Opcode::RCL => encode_shift(0b010, operands),
+        Opcode::RCR => encode_shift(0b011, operands),
+        Opcode::SAL => encode_shift(0b100, operands),
+        Opcode::SHR => encode_shift(0b101, operands),
+        Opcode::SAR => encode_shift(0b111, operands),
+        Opcode::SETMO => encode_shift(0b110, operands),
+        Opcode::PUSH => encode_push_pop(true, operands),
+        Opcode::POP => encode_push_pop(false, operands),
+        Opcode::LEA => encode_load_address(0x8d, operands),
+        Opcode::LDS => encode_load_address(0xc5, operands),
+        Opcode::LES => encode_load_address(0xc4, operands),
+        Opcode::IN | Opcode::OUT => encode_in_out(opcode, operands),
+        Opcode::JMP => encode_jump_or_call(false, operands),
+        Opcode::CALL => encode_jump_or_call(true, operands),
+        Opcode::JO => encode_short_jump(0x70, operands),
+        Opcode::JNO => encode_short_jump(0x71, operands),
+        Opcode::JB | Opcode::JC => encode_short_jump(0x72, operands),
+        Opcode::JAE | Opcode::JNC => encode_short_jump(0x73, operands),
+        Opcode::JE => encode_short_jump(0x74, operands),
+        Opcode::JNE => encode_short_jump(0x75, operands),
+        Opcode::JBE => encode_short_jump(0x76, operands),
+        Opcode::JA => encode_short_jump(0x77, operands),
+        Opcode::JS => encode_short_jump(0x78, operands),
+        Opcode::JNS => encode_short_jump(0x79, operands),
+        Opcode::JP => encode_short_jump(0x7a, operands),
+        Opcode::JNP => encode_short_jump(0x7b, operands),
+        Opcode::JL => encode_short_jump(0x7c, operands),
+        Opcode::JGE => encode_short_jump(0x7d, operands),
+        Opcode::JLE => encode_short_jump(0x7e, operands),
+        Opcode::JG => encode_short_jump(0x7f, operands),
+        Opcode::LOOPNE => encode_short_jump(0xe0, operands),
+        Opcode::LOOPE => encode_short_jump(0xe1, operands),
+        Opcode::LOOP => encode_short_jump(0xe2, operands),
+        Opcode::JCXZ => encode_short_jump(0xe3, operands),
+        Opcode::RET | Opcode::RETF => {
+            let far = (opcode == Opcode::RETF) as u8;
+
+            match operands {
+                [] => Ok(vec![0xc3 | far << 3]),
+                [Operand::Immediate(value)] => {
+                    let mut bytes = vec![0xc2 | far << 3];
+                    bytes.extend(value.to_le_bytes());
+                    Ok(bytes)
+                }
+                _ => Err("invalid operands".to_string()),
+            }
+        }
+        Opcode::INT => match operands {
+            [Operand::Immediate(vector)] if *vector <= 0xff => Ok(vec![0xcd, *vector as u8]),
+            _ => Err("operand must be an 8-bit immediate".to_string()),
+        },
+        Opcode::AAM | Opcode::AAD => {
+            let byte = if opcode == Opcode::AAM { 0xd4 } else { 0xd5 };
+
+            match operands {
+                [] => Ok(vec![byte, 10]),
+                [Operand::Immediate(base)] if *base <= 0xff => Ok(vec![byte, *base as u8]),
+                _ => Err("operand must be an 8-bit immediate".to_string()),
+            }
+        }
+        Opcode::SEGMENT => match operands {
+            [Operand::Segment(segment)] => Ok(vec![0x26 | segment << 3]),
+            _ => Err("operand must be a segment register".to_string()),
+        },
+        Opcode::REP | Opcode::REPE => encode_bare(0xf3, operands),
+        Opcode::REPNE => encode_bare(0xf2, operands),
+        Opcode::LOCK => encode_bare(0xf0, operands),
+        Opcode::INT3 => encode_bare(0xcc, operands),
+        Opcode::INTO => encode_bare(0xce, operands),
+        Opcode::IRET => encode_bare(0xcf, operands),
+        Opcode::NOP => encode_bare(0x90, operands),
+        Opcode::DAA => encode_bare(0x27, operands),
+        Opcode::SALC => encode_bare(0xd6, operands),
+        Opcode::DAS => encode_bare(0x2f, operands),
+        Opcode::AAA => encode_bare(0x37, operands),
+        Opcode::AAS => encode_bare(0x3f, operands),
+        Opcode::CBW => encode_bare(0x98, operands),
+        Opcode::CWD => encode_bare(0x99, operands),
+        Opcode::WAIT => encode_bare(0x9b, operands),
+        Opcode::PUSHF => encode_bare(0x9c, operands),
+        Opcode::POPF => encode_bare(0x9d, operands),
+        Opcode::SAHF => encode_bare(0x9e, operands),
+        Opcode::LAHF => encode_bare(0x9f, operands),
+        Opcode::MOVSB => encode_bare(0xa4, operands),
+        Opcode::MOVSW => encode_bare(0xa5, operands),
+        Opcode::CMPSB => encode_bare(0xa6, operands),
+        Opcode::CMPSW => encode_bare(0xa7, operands),
+        Opcode::STOSB => encode_bare(0xaa, operands),
+        Opcode::STOSW => encode_bare(0xab, operands),
+        Opcode::LODSB => encode_bare(0xac, operands),
+        Opcode::LODSW => encode_bare(0xad, operands),
+        Opcode::SCASB => encode_bare(0xae, operands),
+        Opcode::SCASW => encode_bare(0xaf, operands),
+        Opcode::XLAT => encode_bare(0xd7, operands),
+        Opcode::HLT => encode_bare(0xf4, operands),
+        Opcode::CMC => encode_bare(0xf5, operands),
+        Opcode::CLC => encode_bare(0xf8, operands),
+        Opcode::STC => encode_bare(0xf9, operands),
+        Opcode::CLI => encode_bare(0xfa, operands),
+        Opcode::STI => encode_bare(0xfb, operands),
+        Opcode::CLD => encode_bare(0xfc, operands),
+        Opcode::STD => encode_bare(0xfd, operands),
+        _ => Err("unknown instruction".to_string()),
+    }
+}
+
+/// Encodes an operation as machine code, choosing the shortest encoding
+/// like NASM does: `0x83` for word immediates that fit in a signed byte, the
+/// accumulator forms, `inc r16` and short jumps where possible.
+pub fn encode(operation: &Operation) -> Result<Vec<u8>, EncodeError> {
+    encode_operation(operation).map_err(|message| EncodeError {
+        operation: operation.clone(),
+        message,
+    })
+}
+
+/// Encodes a decoded instruction. The result decodes to the same operation,
+/// but may be shorter than the original bytes.
+pub fn encode_instruction(instruction: &Instruction) -> Result<Vec<u8>, EncodeError> {
+    match instruction.operation() {
+        Some(operation) => encode(&operation),
+        None => Err(EncodeError {
+            operation: Operation::new(instruction.opcode, &[]),
+            message: "instruction has no operand form".to_string(),
+        }),
+    }
+}
+
+/// Encodes a near `jmp` to a target relative to the start of the
+/// instruction, for jumps that `encode` would make short.
+pub fn encode_near_jump(relative: i16) -> Vec<u8> {
+    let mut bytes = vec![0xe9];
+    bytes.extend(relative.wrapping_sub(3).to_le_bytes());
+    bytes
+}
+
+#[test]
+fn test_encode() {
+    let word = |base, displacement| {
+        Operand::Memory(Memory {
+            base,
+            displacement,
+            size: Size::Word,
+        })
+    };
+    let cases = [
+        (
+            Operation::new(
+                Opcode::MOV,
+                &[Operand::Word(register::AX), Operand::Word(register::BX)],
+            ),
+            vec![0x89, 0xd8],
+        ),
+        (
+            Operation::new(
+                Opcode::ADD,
+                &[
+                    word(Some(effective::BP_OR_DIRECT_ADDRESS), 0),
+                    Operand::Immediate(0xfffe),
+                ],
+            ),
+            vec![0x83, 0x46, 0x00, 0xfe],
+        ),
+        (
+            Operation::new(
+                Opcode::ADD,
+                &[Operand::Word(register::AX), Operand::Immediate(0x1234)],
+            ),
+            vec![0x05, 0x34, 0x12],
+        ),
+        (
+            Operation::new(
+                Opcode::MOV,
+                &[word(None, 0x1234), Operand::Word(register::AX)],
+            ),
+            vec![0xa3, 0x34, 0x12],
+        ),
+        (
+            Operation::new(Opcode::MOV, &[Operand::Byte(4), Operand::Immediate(9)]),
+            vec![0xb4, 0x09],
+        ),
+        (
+            Operation::new(Opcode::JMP, &[Operand::Relative(0x82)]),
+            vec![0xe9, 0x7f, 0x00],
+        ),
+        (
+            Operation::new(Opcode::JMP, &[Operand::Relative(-0x7e)]),
+            vec![0xeb, 0x80],
+        ),
+        (
+            Operation::new(Opcode::INC, &[Operand::Word(register::SI)]),
+            vec![0x46],
+        ),
+    ];
+
+    for (operation, bytes) in cases {
+        assert_eq!(encode(&operation).unwrap(), bytes, "{:?}", operation);
+    }
+
+    let error = encode(&Operation::new(Opcode::JE, &[Operand::Relative(0x100)])).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Can not encode je: jump of 256 bytes is out of range"
+    );
+}
+
+/// Encodes every instruction the decoder recognises and checks that the
+/// result decodes to the same operation, and is never longer.
+#[test]
+fn test_encode_round_trip() {
+    let tails: [[u8; 4]; 3] = [
+        [0x00; 4],
+        [0x34, 0x12, 0x78, 0x56],
+        [0x80, 0xff, 0x7f, 0xfe],
+    ];
+
+    for first in 0..=0xffu8 {
+        for second in 0..=0xffu8 {
+            for tail in tails {
+                let bytes = [first, second, tail[0], tail[1], tail[2], tail[3]];
+                let Some(instruction) = Instruction::parse(&bytes) else {
+                    continue;
+                };
+                let Some(operation) = instruction.operation() else {
+                    // Far jumps and calls through a register
+                    assert!(first == 0xff && second >> 6 == 0b11);
+                    continue;
+                };
+                let encoded =
+                    encode(&operation).unwrap_or_else(|error| panic!("{:02x?}: {}", &bytes, error));
+                let decoded = Instruction::parse(&encoded).unwrap();
+
+                assert_eq!(decoded.length as usize, encoded.len(), "{:02x?}", &bytes);
+                assert_eq!(decoded.operation(), Some(operation), "{:02x?}", &bytes);
+                assert!(
+                    encoded.len() <= instruction.length as usize,
+                    "{:02x?}",
+                    &bytes
+                );
+            }
+        }
+    }
+}
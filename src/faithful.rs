@@ -0,0 +1,119 @@
+use std::ops::Range;
+
+use crate::{
+    assembler::assemble,
+    instructions::opcode::Opcode,
+    parser::Item,
+    writer::{Writer, WriterOptions},
+    Instruction,
+};
+
+fn is_prefix(instruction: &Instruction) -> bool {
+    matches!(
+        instruction.opcode,
+        Opcode::LOCK | Opcode::REP | Opcode::SEGMENT
+    )
+}
+
+/// Writes the instructions in the range on their own, with jump targets
+/// named `loc_0`.
+fn write_alone(bytes: &[u8], range: Range<usize>) -> String {
+    let mut writer = Writer::new(WriterOptions {
+        verbose: false,
+        faithful: true,
+        undocumented: false,
+    });
+    let mut index = range.start;
+
+    writer.set_position(range.start);
+
+    while let Some(instruction) = Instruction::parse_undocumented(&bytes[index..range.end]) {
+        instruction.write(&mut writer);
+        index += instruction.length as usize;
+    }
+
+    String::from_utf8_lossy(writer.as_slice())
+        .trim()
+        .to_string()
+}
+
+/// Whether the written instructions reassemble to the bytes in the range.
+fn reassembles(bytes: &[u8], range: Range<usize>, text: &str, target: Option<isize>) -> bool {
+    let mut source = String::new();
+
+    // Targets before the instructions are not written as labels
+    if let (Some(target), false) = (target, text.contains("loc_0:")) {
+        source.push_str(&format!("loc_0 equ {}\n", target - range.start as isize));
+    }
+
+    source.push_str(text);
+
+    assemble(&source).is_ok_and(|assembled| assembled == bytes[range])
+}
+
+/// Finds the instructions whose written form would be reassembled to other
+/// bytes, together with the prefixes before them. Returns the range of each
+/// and its written form, which is empty for prefixes that apply to nothing.
+pub fn find_inexact(bytes: &[u8], items: &[(usize, Item)]) -> Vec<(Range<usize>, String)> {
+    let mut inexact = Vec::new();
+    let mut prefixes: Option<Range<usize>> = None;
+
+    for &(offset, item) in items {
+        let Item::Instruction(instruction) = item else {
+            inexact.extend(prefixes.take().map(|range| (range, String::new())));
+            continue;
+        };
+        let end = offset + instruction.length as usize;
+        let start = prefixes.as_ref().map_or(offset, |range| range.start);
+
+        if is_prefix(&instruction) {
+            prefixes = Some(start..end);
+            continue;
+        }
+
+        prefixes = None;
+
+        let text = write_alone(bytes, start..end);
+        let target = instruction.branch_target(offset);
+
+        if !reassembles(bytes, start..end, &text, target) {
+            // Labels inside the range are defined with `equ` on the lines
+            // after the instruction, which the comment leaves out
+            let text = text.lines().next().unwrap_or_default().to_string();
+            // The label is not written, so the comment names the offset
+            let text = match target {
+                Some(target) => text.replace("loc_0", &format!("{:#06x}", target)),
+                None => text,
+            };
+
+            inexact.push((start..end, text));
+        }
+    }
+
+    inexact.extend(prefixes.map(|range| (range, String::new())));
+    inexact
+}
+
+#[test]
+fn test_find_inexact() {
+    let items = |bytes: &[u8]| crate::parser::parse_with_data(bytes, &[], false);
+    // add ax, 1 (long); jmp near $+3; mov bx, ax; es daa; jne $
+    let bytes = [
+        0x05, 0x01, 0x00, 0xe9, 0x00, 0x00, 0x89, 0xc3, 0x26, 0x27, 0x75, 0xfe,
+    ];
+
+    assert_eq!(
+        find_inexact(&bytes, &items(&bytes)),
+        vec![(0..3, "add ax, 1".to_string()), (8..10, "daa".to_string())]
+    );
+    // A lone lock before the end of the input
+    assert_eq!(
+        find_inexact(&[0x90, 0xf0], &items(&[0x90, 0xf0])),
+        vec![(1..2, String::new())]
+    );
+    // je to its own second byte
+    assert_eq!(
+        find_inexact(&[0x74, 0xff], &items(&[0x74, 0xff])),
+        vec![(0..2, "je 0x0001".to_string())]
+    );
+}
@@ -0,0 +1,138 @@
+use std::io::{self, Write};
+
+use crate::{analysis::functions::CallGraph, layout, parser::Item, symbols::SymbolMap};
+
+/// A summary of what the input decodes to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Info {
+    pub size: usize,
+    /// Address the input is loaded at.
+    pub origin: usize,
+    pub instructions: usize,
+    /// Bytes written as data, including strings.
+    pub data_bytes: usize,
+    pub strings: usize,
+    pub functions: usize,
+    /// Bytes at the end that are neither instructions nor data.
+    pub undecoded_bytes: usize,
+}
+
+impl Info {
+    pub fn collect(bytes: &[u8], symbols: &SymbolMap, undocumented: bool, origin: usize) -> Self {
+        let (items, strings) = layout(bytes, symbols, undocumented, origin);
+        let mut info = Info {
+            size: bytes.len(),
+            origin,
+            instructions: 0,
+            data_bytes: 0,
+            strings: strings.len(),
+            functions: 0,
+            undecoded_bytes: bytes.len(),
+        };
+        let mut instructions = Vec::new();
+
+        for &(offset, item) in &items {
+            let length = match item {
+                Item::Instruction(instruction) => {
+                    instructions.push((offset, instruction));
+                    instruction.length as usize
+                }
+                Item::Data(length) => {
+                    info.data_bytes += length;
+                    length
+                }
+            };
+
+            info.undecoded_bytes = bytes.len() - (offset + length);
+        }
+
+        info.instructions = instructions.len();
+        info.functions = CallGraph::from_instructions(instructions).functions.len();
+
+        info
+    }
+
+    fn fields(&self) -> [(&'static str, usize); 7] {
+        [
+            ("size", self.size),
+            ("origin", self.origin),
+            ("instructions", self.instructions),
+            ("data_bytes", self.data_bytes),
+            ("strings", self.strings),
+            ("functions", self.functions),
+            ("undecoded_bytes", self.undecoded_bytes),
+        ]
+    }
+
+    pub fn write_text(&self, out: &mut impl Write, file_name: &str) -> io::Result<()> {
+        writeln!(out, "{:<16}{}", "file", file_name)?;
+
+        for (name, value) in self.fields() {
+            let name = name.replace('_', " ");
+
+            match name.as_str() {
+                "origin" => writeln!(out, "{:<16}{:#06x}", name, value)?,
+                _ => writeln!(out, "{:<16}{}", name, value)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write_json(&self, out: &mut impl Write, file_name: &str) -> io::Result<()> {
+        write!(out, "{{\"file\": \"{}\"", escape_json(file_name))?;
+
+        for (name, value) in self.fields() {
+            write!(out, ", \"{}\": {}", name, value)?;
+        }
+
+        writeln!(out, "}}")
+    }
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\u{0}'..='\u{1f}' => escaped.push_str(&format!("\\u{:04x}", character as u32)),
+            _ => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+#[test]
+fn test_info() {
+    // call 0x000a; hlt; 'Hello$'; ret; an undecodable byte
+    let mut bytes = vec![0xe8, 0x07, 0x00, 0xf4];
+    bytes.extend(b"Hello$");
+    bytes.extend([0xc3, 0xd6]);
+
+    let info = Info::collect(&bytes, &SymbolMap::new(), false, 0x100);
+    let mut output = Vec::new();
+
+    assert_eq!(
+        info,
+        Info {
+            size: 12,
+            origin: 0x100,
+            instructions: 3,
+            data_bytes: 6,
+            strings: 1,
+            functions: 2,
+            undecoded_bytes: 1,
+        }
+    );
+
+    info.write_json(&mut output, "a \"b\"").unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "{\"file\": \"a \\\"b\\\"\", \"size\": 12, \"origin\": 256, \"instructions\": 3, \
+         \"data_bytes\": 6, \"strings\": 1, \"functions\": 2, \"undecoded_bytes\": 1}\n"
+    );
+}
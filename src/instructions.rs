@@ -8,6 +8,8 @@ pub mod instruction;
 pub mod logic;
 pub mod mov;
 pub mod opcode;
+pub mod operand;
 pub mod processor_control;
 pub mod push_pop;
 pub mod strings;
+pub mod undocumented;
@@ -13,10 +13,17 @@ fn parse_direct_within_segment(bytes: &[u8], inst: &mut Instruction) {
 }
 
 fn write_direct_within_segment(writer: &mut Writer, instruction: &Instruction) {
-    writer
-        .start_instruction(instruction)
-        .write_jump_displacement(instruction.disp)
-        .end_line();
+    writer.start_instruction(instruction);
+
+    // A near jump to a close target would be reassembled as a short one
+    if writer.options().faithful
+        && instruction.input[0] == 0b11101001
+        && i8::try_from(instruction.disp.wrapping_add(1)).is_ok()
+    {
+        writer.write_str("near ");
+    }
+
+    writer.write_jump_displacement(instruction.disp).end_line();
 }
 
 fn write_direct_intersegment(writer: &mut Writer, instruction: &Instruction) {
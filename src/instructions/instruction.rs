@@ -6,9 +6,31 @@ use super::{
     },
     descriptions::{Description, UNIMPLEMENTED},
     opcode::Opcode,
+    operand::Operation,
     resolve,
+    undocumented::resolve_undocumented,
 };
 
+/// How an instruction hands over control once it has executed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ControlFlow {
+    /// Execution continues with the next instruction.
+    Next,
+    /// Conditional jump or loop with a relative displacement. Falls through
+    /// to the next instruction when not taken.
+    Branch(i16),
+    /// Unconditional jump with a relative displacement.
+    Jump(i16),
+    /// Call with a relative displacement.
+    Call(i16),
+    /// Jump through a register, memory or a far address.
+    IndirectJump,
+    /// Call through a register, memory or a far address.
+    IndirectCall,
+    /// RET, RETF or IRET.
+    Return,
+}
+
 #[derive(Debug, Copy, Clone)]
 pub struct Instruction {
     pub opcode: Opcode,
@@ -36,21 +58,51 @@ impl Instruction {
     };
 
     pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let padded = Self::pad(bytes)?;
+
+        Self::parse_with(bytes.len(), &padded, resolve(&padded))
+    }
+
+    /// Parses like `parse`, but also decodes the opcodes the 8086 executes
+    /// without them being documented.
+    pub fn parse_undocumented(bytes: &[u8]) -> Option<Self> {
+        let padded = Self::pad(bytes)?;
+
+        Self::parse(bytes)
+            .or_else(|| Self::parse_with(bytes.len(), &padded, resolve_undocumented(&padded)))
+    }
+
+    /// Copies the start of the bytes into a zero-padded buffer, so that an
+    /// instruction cut off by the end of the input is not read past it.
+    fn pad(bytes: &[u8]) -> Option<[u8; 6]> {
         if bytes.is_empty() {
             return None;
         }
 
+        let mut padded = [0; 6];
+        let length = bytes.len().min(padded.len());
+
+        padded[..length].copy_from_slice(&bytes[..length]);
+
+        Some(padded)
+    }
+
+    /// Parses the padded bytes, of which `available` are input.
+    fn parse_with(
+        available: usize,
+        bytes: &[u8; 6],
+        description: &'static Description,
+    ) -> Option<Self> {
         let mut instruction = Instruction::EMPTY;
 
-        let description = resolve(bytes);
         description.parse(bytes, &mut instruction);
 
-        if instruction.length == 0 {
+        let length = instruction.length as usize;
+
+        if length == 0 || length > available {
             return None;
         }
 
-        let length = instruction.length as usize;
-
         instruction.input[..length].copy_from_slice(&bytes[..length]);
 
         instruction.description = description;
@@ -62,6 +114,60 @@ impl Instruction {
         (self.description.write_fn)(writer, self);
     }
 
+    pub fn control_flow(&self) -> ControlFlow {
+        match self.opcode {
+            Opcode::JMP => match self.input[0] {
+                0b11101001 | 0b11101011 => ControlFlow::Jump(self.disp),
+                _ => ControlFlow::IndirectJump,
+            },
+            Opcode::CALL => match self.input[0] {
+                0b11101000 => ControlFlow::Call(self.disp),
+                _ => ControlFlow::IndirectCall,
+            },
+            Opcode::JA
+            | Opcode::JAE
+            | Opcode::JB
+            | Opcode::JBE
+            | Opcode::JC
+            | Opcode::JCXZ
+            | Opcode::JE
+            | Opcode::JG
+            | Opcode::JGE
+            | Opcode::JL
+            | Opcode::JLE
+            | Opcode::JNC
+            | Opcode::JNE
+            | Opcode::JNO
+            | Opcode::JNP
+            | Opcode::JNS
+            | Opcode::JO
+            | Opcode::JP
+            | Opcode::JS
+            | Opcode::LOOP
+            | Opcode::LOOPE
+            | Opcode::LOOPNE => ControlFlow::Branch(self.disp),
+            Opcode::RET | Opcode::RETF | Opcode::IRET => ControlFlow::Return,
+            _ => ControlFlow::Next,
+        }
+    }
+
+    /// Returns the byte offset a relative jump, branch or call at `offset`
+    /// transfers control to. The result may lie outside the parsed bytes.
+    pub fn branch_target(&self, offset: usize) -> Option<isize> {
+        match self.control_flow() {
+            ControlFlow::Branch(disp) | ControlFlow::Jump(disp) | ControlFlow::Call(disp) => {
+                Some(offset as isize + self.length as isize + disp as isize)
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the mnemonic and operands of the instruction, see
+    /// `Operation::from_instruction`.
+    pub fn operation(&self) -> Option<Operation> {
+        Operation::from_instruction(self)
+    }
+
     pub fn get_source(&self) -> RM {
         if has_direction_flag(self.flags) {
             self.data_fields.rm
@@ -87,11 +87,15 @@ pub enum Opcode {
     ROR,
     SAHF,
     SAL,
+    /// Undocumented: sets AL to 0xff if the carry flag is set, 0 otherwise.
+    SALC,
     SAR,
     SBB,
     SCASB,
     SCASW,
     SEGMENT,
+    /// Undocumented /6 of the shift group: sets the operand to all ones.
+    SETMO,
     SHR,
     STC,
     STD,
@@ -107,7 +111,7 @@ pub enum Opcode {
 }
 
 impl Opcode {
-    const MNEMONICS: [&str; 102] = [
+    const MNEMONICS: [&str; 104] = [
         "<unknown>",
         "aaa",
         "aad",
@@ -193,11 +197,13 @@ impl Opcode {
         "ror",
         "sahf",
         "sal",
+        "salc",
         "sar",
         "sbb",
         "scasb",
         "scasw",
         "<segment>",
+        "setmo",
         "shr",
         "stc",
         "std",
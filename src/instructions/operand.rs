@@ -0,0 +1,239 @@
+use super::{
+    common::{
+        effective,
+        instruction_flags::{has_sign_flag, has_word_flag},
+        mode, register, InstRegister, RM,
+    },
+    instruction::Instruction,
+    opcode::Opcode,
+};
+
+/// Size of a memory operand.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Size {
+    Byte,
+    Word,
+    /// 32-bit segment and offset pair read by far jumps and calls.
+    Far,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Memory {
+    /// Effective address as encoded in the R/M field, e.g.
+    /// `effective::BX_PLUS_SI`, or `None` for a direct address.
+    pub base: Option<u8>,
+    pub displacement: i16,
+    pub size: Size,
+}
+
+impl Memory {
+    pub fn direct(address: u16, size: Size) -> Self {
+        Self {
+            base: None,
+            displacement: address as i16,
+            size,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operand {
+    /// 8-bit register, numbered AL, CL, DL, BL, AH, CH, DH, BH.
+    Byte(u8),
+    /// 16-bit register, see `common::register`.
+    Word(u8),
+    /// Segment register, see `common::segment_register`.
+    Segment(u8),
+    Memory(Memory),
+    /// Immediate value. 8-bit immediates that are sign-extended by the
+    /// instruction are stored extended.
+    Immediate(u16),
+    /// Jump or call target relative to the start of the instruction.
+    Relative(i16),
+    /// Far jump or call target.
+    Far {
+        segment: u16,
+        offset: u16,
+    },
+}
+
+/// An instruction as a mnemonic and operands, without the details of how it
+/// is encoded. Operands are in the order they are written in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub opcode: Opcode,
+    pub operands: Vec<Operand>,
+}
+
+impl Operation {
+    pub fn new(opcode: Opcode, operands: &[Operand]) -> Self {
+        Self {
+            opcode,
+            operands: operands.to_vec(),
+        }
+    }
+
+    /// Returns the typed form of a decoded instruction, or `None` if it has
+    /// none, like a far jump through a register.
+    pub fn from_instruction(instruction: &Instruction) -> Option<Self> {
+        let word = has_word_flag(instruction.flags);
+        let size = if word { Size::Word } else { Size::Byte };
+        let register = |register: InstRegister| match register {
+            InstRegister::Reg(register) if word => Operand::Word(register),
+            InstRegister::Reg(register) => Operand::Byte(register),
+            InstRegister::SegReg(register) => Operand::Segment(register),
+        };
+        let rm = |rm: RM, size: Size| match rm {
+            RM::Reg(InstRegister::Reg(register)) if size == Size::Word => Operand::Word(register),
+            RM::Reg(reg) => register(reg),
+            RM::Eff(effective::BP_OR_DIRECT_ADDRESS)
+                if instruction.data_fields.mode == mode::MEMORY_MODE =>
+            {
+                Operand::Memory(Memory::direct(instruction.disp as u16, size))
+            }
+            RM::Eff(base) => Operand::Memory(Memory {
+                base: Some(base),
+                displacement: instruction.disp,
+                size,
+            }),
+        };
+        let accumulator = register(InstRegister::Reg(register::AX));
+        let destination = rm(instruction.get_destination(), size);
+        let source = rm(instruction.get_source(), size);
+        // Group opcodes use the register field as an extension, regardless
+        // of the direction bit
+        let operand = rm(instruction.data_fields.rm, size);
+        let data = Operand::Immediate(instruction.data);
+        let relative = Operand::Relative(instruction.disp.wrapping_add(instruction.length as i16));
+        let input = instruction.input;
+
+        let (opcode, operands) = match input[0] {
+            0x00..=0x3f if input[0] & 0b110 == 0b100 => {
+                (instruction.opcode, vec![accumulator, data])
+            }
+            0xa8 | 0xa9 => (instruction.opcode, vec![accumulator, data]),
+            0xb0..=0xbf => (
+                instruction.opcode,
+                vec![register(instruction.register), data],
+            ),
+            0x83 if has_sign_flag(instruction.flags) => {
+                let data = instruction.data as u8 as i8 as i16 as u16;
+                (instruction.opcode, vec![operand, Operand::Immediate(data)])
+            }
+            0x80..=0x83 | 0xc6 | 0xc7 => (instruction.opcode, vec![operand, data]),
+            0xf6 | 0xf7 if instruction.opcode == Opcode::TEST => {
+                (instruction.opcode, vec![operand, data])
+            }
+            0xf6 | 0xf7 | 0xfe => (instruction.opcode, vec![operand]),
+            0xff if matches!(input[1] >> 3 & 0b111, 0b011 | 0b101) => {
+                match instruction.data_fields.rm {
+                    RM::Eff(_) => (
+                        instruction.opcode,
+                        vec![rm(instruction.data_fields.rm, Size::Far)],
+                    ),
+                    RM::Reg(_) => return None,
+                }
+            }
+            0xff | 0x8f => (
+                instruction.opcode,
+                vec![rm(instruction.data_fields.rm, Size::Word)],
+            ),
+            0x40..=0x5f => (
+                instruction.opcode,
+                vec![Operand::Word(instruction.register.into())],
+            ),
+            0x06 | 0x07 | 0x0e | 0x0f | 0x16 | 0x17 | 0x1e | 0x1f => {
+                (instruction.opcode, vec![register(instruction.register)])
+            }
+            0x91..=0x97 => (
+                instruction.opcode,
+                vec![
+                    Operand::Word(instruction.register.into()),
+                    Operand::Word(register::AX),
+                ],
+            ),
+            0xd0 | 0xd1 => (instruction.opcode, vec![operand, Operand::Immediate(1)]),
+            0xd2 | 0xd3 => (
+                instruction.opcode,
+                vec![operand, Operand::Byte(register::CX)],
+            ),
+            0xe4 | 0xe5 => (instruction.opcode, vec![accumulator, data]),
+            0xe6 | 0xe7 => (instruction.opcode, vec![data, accumulator]),
+            0xec | 0xed => (
+                instruction.opcode,
+                vec![accumulator, Operand::Word(register::DX)],
+            ),
+            0xee | 0xef => (
+                instruction.opcode,
+                vec![Operand::Word(register::DX), accumulator],
+            ),
+            0x60..=0x7f | 0xe0..=0xe3 | 0xe8 | 0xe9 | 0xeb => (instruction.opcode, vec![relative]),
+            0x9a | 0xea => (
+                instruction.opcode,
+                vec![Operand::Far {
+                    segment: u16::from_le_bytes([input[3], input[4]]),
+                    offset: u16::from_le_bytes([input[1], input[2]]),
+                }],
+            ),
+            0xc0 | 0xc2 | 0xc8 | 0xca => (
+                instruction.opcode,
+                vec![Operand::Immediate(instruction.disp as u16)],
+            ),
+            0xcd => (instruction.opcode, vec![data]),
+            0xd4 | 0xd5 if input[1] != 10 => (
+                instruction.opcode,
+                vec![Operand::Immediate(input[1] as u16)],
+            ),
+            0xf2 => (Opcode::REPNE, vec![]),
+            0x26 | 0x2e | 0x36 | 0x3e => (instruction.opcode, vec![register(instruction.register)]),
+            0x00..=0x3f if input[0] & 0b100 == 0 => (instruction.opcode, vec![destination, source]),
+            0x84..=0x8e | 0xa0..=0xa3 | 0xc4 | 0xc5 => {
+                (instruction.opcode, vec![destination, source])
+            }
+            _ => (instruction.opcode, vec![]),
+        };
+
+        Some(Self { opcode, operands })
+    }
+}
+
+#[test]
+fn test_operation_from_instruction() {
+    let operation = |bytes: &[u8]| Instruction::parse(bytes).unwrap().operation().unwrap();
+
+    // add word [bp+si-2], -3
+    assert_eq!(
+        operation(&[0x83, 0x42, 0xfe, 0xfd]),
+        Operation::new(
+            Opcode::ADD,
+            &[
+                Operand::Memory(Memory {
+                    base: Some(effective::BP_PLUS_SI),
+                    displacement: -2,
+                    size: Size::Word,
+                }),
+                Operand::Immediate(0xfffd),
+            ]
+        )
+    );
+    // mov al, [0x1234]
+    assert_eq!(
+        operation(&[0xa0, 0x34, 0x12]),
+        Operation::new(
+            Opcode::MOV,
+            &[
+                Operand::Byte(register::AX),
+                Operand::Memory(Memory::direct(0x1234, Size::Byte)),
+            ]
+        )
+    );
+    // jne $-2
+    assert_eq!(
+        operation(&[0x75, 0xfc]),
+        Operation::new(Opcode::JNE, &[Operand::Relative(-2)])
+    );
+    assert!(Instruction::parse(&[0xff, 0xd8])
+        .unwrap()
+        .operation()
+        .is_none());
+}
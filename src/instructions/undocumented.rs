@@ -0,0 +1,123 @@
+use super::{
+    common::{
+        parse_bare_instruction, parse_instruction_flags, parse_typical_instruction,
+        write_bare_instruction, write_memory_or_register_instruction,
+    },
+    control_transfer,
+    descriptions::UNIMPLEMENTED,
+    instruction::Instruction,
+    logic::write_logic_instruction,
+    opcode::Opcode,
+    processor_control,
+    push_pop::parse_push_pop_register_or_memory,
+    Description,
+};
+
+pub const CONDITIONAL_JUMP_ALIAS: Description = Description {
+    parse_fn: |bytes, inst| {
+        (control_transfer::CONDITIONAL_JUMP.parse_fn)(&[bytes[0] | 0b00010000, bytes[1]], inst)
+    },
+    write_fn: control_transfer::CONDITIONAL_JUMP.write_fn,
+};
+
+pub const SALC: Description = Description {
+    parse_fn: |_, inst| parse_bare_instruction(inst, Opcode::SALC),
+    write_fn: write_bare_instruction,
+};
+
+pub const SETMO: Description = Description {
+    parse_fn: |bytes, inst| parse_typical_instruction(inst, Opcode::SETMO, bytes),
+    write_fn: write_logic_instruction,
+};
+
+pub const PUSH_ALIAS: Description = Description {
+    parse_fn: |bytes, inst| {
+        parse_push_pop_register_or_memory(bytes, inst);
+        inst.opcode = Opcode::PUSH;
+        inst.flags = parse_instruction_flags(bytes[0]);
+    },
+    write_fn: write_memory_or_register_instruction,
+};
+
+/// Resolves the opcodes that are not documented for the 8086, as the
+/// hardware decodes them.
+pub fn resolve_undocumented(bytes: &[u8]) -> &'static Description {
+    let extension = bytes.get(1).map(|byte| byte >> 3 & 0b111);
+
+    match (bytes[0], extension) {
+        (0b01100000..=0b01101111, _) => &CONDITIONAL_JUMP_ALIAS,
+        (0b11000000 | 0b11001000, _) => &control_transfer::RETURN_WITH_VALUE,
+        (0b11000001 | 0b11001001, _) => &control_transfer::RETURN_NO_VALUE,
+        (0b11010110, _) => &SALC,
+        (0b11110001, _) => &processor_control::LOCK,
+        (0b11010000..=0b11010011, Some(0b110)) => &SETMO,
+        (0b11111110, Some(0b010 | 0b100)) => &control_transfer::INDIRECT_WITHIN_SEGMENT,
+        (0b11111110, Some(0b011 | 0b101)) => &control_transfer::INDIRECT_INTERSEGMENT,
+        (0b11111110, Some(0b110 | 0b111)) | (0b11111111, Some(0b111)) => &PUSH_ALIAS,
+        _ => &UNIMPLEMENTED,
+    }
+}
+
+/// Describes how the instruction differs from the documented 8086, if it
+/// does.
+pub fn annotation(instruction: &Instruction) -> Option<String> {
+    let opcode = instruction.input[0];
+    let extension = instruction.input[1] >> 3 & 0b111;
+    let alias = |documented: u8| Some(format!("undocumented alias of {:#04x}", documented));
+
+    match opcode {
+        0b01100000..=0b01101111 => alias(opcode | 0b00010000),
+        0b11000000 | 0b11000001 | 0b11001000 | 0b11001001 => alias(opcode | 0b10),
+        0b11110001 => alias(0b11110000),
+        0b10000010 => alias(0b10000000),
+        _ => Some(
+            match opcode {
+                0b11010110 => "undocumented salc: sets al to 0xff if carry is set",
+                0b00001111 => "undocumented pop cs",
+                0b10001110 if extension == 0b001 => "undocumented mov to cs",
+                0b10001100 | 0b10001110 if extension >= 0b100 => {
+                    "undocumented segment register 4-7, same as 0-3"
+                }
+                0b11111111 if extension == 0b111 => "undocumented alias of push",
+                0b11010000..=0b11010011 if extension == 0b110 => {
+                    "undocumented setmo: sets all bits"
+                }
+                0b11111110 if extension >= 0b010 => "undocumented: 0xff with a byte operand",
+                _ => return None,
+            }
+            .to_string(),
+        ),
+    }
+}
+
+#[test]
+fn test_resolve_undocumented() {
+    let parse = |bytes: &[u8]| {
+        let mut instruction = Instruction::EMPTY;
+        resolve_undocumented(bytes).parse(bytes, &mut instruction);
+        instruction
+    };
+
+    let jump = parse(&[0x65, 0xfe]);
+    assert_eq!((jump.opcode, jump.length, jump.disp), (Opcode::JNE, 2, -2));
+
+    let ret = parse(&[0xc8, 0x04, 0x00]);
+    assert_eq!((ret.opcode, ret.length, ret.disp), (Opcode::RETF, 3, 4));
+
+    assert_eq!(parse(&[0xd6]).opcode, Opcode::SALC);
+    assert_eq!(parse(&[0xf1]).opcode, Opcode::LOCK);
+    assert_eq!(parse(&[0xd2, 0xf0]).opcode, Opcode::SETMO);
+    assert_eq!(parse(&[0xfe, 0x17]).opcode, Opcode::CALL);
+    assert_eq!(parse(&[0xfe, 0x27]).opcode, Opcode::JMP);
+    assert_eq!(parse(&[0xff, 0x37 | 0b1000]).opcode, Opcode::PUSH);
+    assert_eq!(parse(&[0xd0, 0xc0]).length, 0);
+
+    assert_eq!(
+        annotation(&Instruction::parse_undocumented(&[0x65, 0xfe]).unwrap()),
+        Some("undocumented alias of 0x75".to_string())
+    );
+    assert_eq!(
+        annotation(&Instruction::parse(&[0x75, 0xfe]).unwrap()),
+        None
+    );
+}
@@ -1,66 +1,420 @@
+pub mod analysis;
+pub mod assembler;
+pub mod debugger;
+pub mod emulator;
+pub mod encoder;
+pub mod faithful;
+pub mod info;
 pub mod instructions;
 pub use crate::instructions::instruction::Instruction;
+pub mod listing;
 pub mod parser;
+pub mod shell;
+pub mod symbols;
+pub mod verify;
 pub mod writer;
 
+use analysis::functions::CallGraph;
+use instructions::undocumented;
+use parser::{Item, StreamParser};
 use std::io::{stdout, Read, Write};
+use std::ops::Range;
+use symbols::SymbolMap;
 use writer::{Writer, WriterOptions};
 
-fn read_file(filename: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
-    let mut file = std::fs::File::open(filename)?;
+pub fn run(
+    file_name: &str,
+    bytes: &[u8],
+    writer_options: WriterOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    run_with_symbols(file_name, bytes, writer_options, &SymbolMap::new())
+}
 
-    let mut buffer: Vec<u8> = Vec::new();
+pub fn run_with_symbols(
+    file_name: &str,
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let output = disassemble_with_symbols(file_name, bytes, writer_options, symbols)?;
 
-    file.read_to_end(&mut buffer)?;
+    stdout().write_all(&output)?;
 
-    Ok(buffer)
+    Ok(())
 }
 
-pub fn run(
+/// Splits the input loaded at the given address into instructions and data,
+/// returning the items along with the ranges of the data that are strings.
+/// Without an address, strings are found as in a `.com` program.
+pub(crate) fn layout(
+    bytes: &[u8],
+    symbols: &SymbolMap,
+    undocumented: bool,
+    origin: usize,
+) -> (Vec<(usize, Item)>, Vec<Range<usize>>) {
+    layout_with(bytes, symbols, undocumented, origin, false)
+}
+
+/// Splits the input like `layout`, keeping the bytes that can not be decoded
+/// as data instead of stopping at the first one.
+pub(crate) fn layout_to_end(
+    bytes: &[u8],
+    symbols: &SymbolMap,
+    undocumented: bool,
+    origin: usize,
+) -> (Vec<(usize, Item)>, Vec<Range<usize>>) {
+    layout_with(bytes, symbols, undocumented, origin, true)
+}
+
+fn layout_with(
+    bytes: &[u8],
+    symbols: &SymbolMap,
+    undocumented: bool,
+    origin: usize,
+    to_end: bool,
+) -> (Vec<(usize, Item)>, Vec<Range<usize>>) {
+    let origin = match origin {
+        0 => analysis::strings::COM_ORIGIN,
+        origin => origin,
+    };
+    let mut data_ranges = symbols.data_ranges();
+    let strings = analysis::strings::find_string_regions(bytes, &data_ranges, undocumented, origin);
+
+    data_ranges.extend(strings.iter().cloned());
+
+    let items = match to_end {
+        true => parser::parse_to_end(bytes, &data_ranges, undocumented),
+        false => parser::parse_with_data(bytes, &data_ranges, undocumented),
+    };
+
+    (items, strings)
+}
+
+fn call_graph_of(items: &[(usize, Item)], symbols: &SymbolMap, origin: usize) -> CallGraph {
+    let instructions = items
+        .iter()
+        .filter_map(|&(offset, item)| match item {
+            Item::Instruction(instruction) => Some((offset, instruction)),
+            Item::Data(_) => None,
+        })
+        .collect();
+    let mut call_graph = CallGraph::from_instructions_at(instructions, origin);
+
+    call_graph.apply_symbols(symbols);
+    call_graph
+}
+
+/// Builds the call graph of the instructions the disassembly is made of, as
+/// loaded at the given address and with the names of the symbols.
+pub fn call_graph(
+    bytes: &[u8],
+    symbols: &SymbolMap,
+    undocumented: bool,
+    origin: usize,
+) -> CallGraph {
+    let (items, _) = layout(bytes, symbols, undocumented, origin);
+
+    call_graph_of(&items, symbols, origin)
+}
+
+/// Returns how many bytes from the start of the input loaded at the given
+/// address are decoded into instructions and data. Decoding stops at the
+/// first byte that is neither.
+pub fn decoded_length(
+    bytes: &[u8],
+    symbols: &SymbolMap,
+    undocumented: bool,
+    origin: usize,
+) -> usize {
+    let (items, _) = layout(bytes, symbols, undocumented, origin);
+
+    items.last().map_or(0, |&(offset, item)| match item {
+        Item::Instruction(instruction) => offset + instruction.length as usize,
+        Item::Data(length) => offset + length,
+    })
+}
+
+/// Disassembles the input into NASM source without writing it anywhere.
+pub fn disassemble(
     file_name: &str,
     bytes: &[u8],
     writer_options: WriterOptions,
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    disassemble_with_symbols(file_name, bytes, writer_options, &SymbolMap::new())
+}
+
+pub fn disassemble_with_symbols(
+    file_name: &str,
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    disassemble_with_origin(file_name, bytes, writer_options, symbols, 0)
+}
+
+/// Disassembles the input as loaded at the given address, which is written
+/// as an `org` directive when it is not zero.
+pub fn disassemble_with_origin(
+    file_name: &str,
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+    origin: usize,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    disassemble_with_hits(file_name, bytes, writer_options, symbols, origin, None)
+}
+
+/// Disassembles the input like `disassemble_with_origin`, with how many
+/// times each instruction was executed according to the hits of each byte
+/// offset, followed by a summary of the code that never was.
+pub fn disassemble_with_coverage(
+    file_name: &str,
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+    origin: usize,
+    hits: &[u64],
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    disassemble_with_hits(
+        file_name,
+        bytes,
+        writer_options,
+        symbols,
+        origin,
+        Some(hits),
+    )
+}
+
+fn disassemble_with_hits(
+    file_name: &str,
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+    origin: usize,
+    hits: Option<&[u64]>,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    if bytes.is_empty() {
+        return Err(parser::ParserInitError.into());
+    }
+
     let mut writer = Writer::new(writer_options.clone());
-    let parser = parser::Parser::build(bytes)?;
+    // Faithful mode keeps every byte, writing the ones that can not be
+    // decoded as data
+    let (items, strings) = match writer_options.faithful {
+        true => layout_to_end(bytes, symbols, writer_options.undocumented, origin),
+        false => layout(bytes, symbols, writer_options.undocumented, origin),
+    };
     let mut index: usize = 0;
 
+    let call_graph = call_graph_of(&items, symbols, origin);
+
+    call_graph.annotate(&mut writer);
+    analysis::interrupts::annotate(&call_graph.cfg, &mut writer);
+    analysis::overlap::annotate(
+        bytes,
+        &items,
+        &mut writer,
+        writer_options.undocumented,
+        origin,
+    );
+    symbols.annotate(&mut writer);
+
     writer.write_comment(file_name);
     writer.end_line();
     writer.write(b"bits 16");
     writer.end_line();
 
-    for instruction in parser {
-        instruction.write(&mut writer);
-        index += instruction.length as usize;
+    if origin != 0 {
+        writer.write_str(&format!("org {:#06x}", origin));
+        writer.end_line();
+        writer.set_origin(origin);
+    }
+
+    let inexact = match writer_options.faithful {
+        true => faithful::find_inexact(bytes, &items),
+        false => Vec::new(),
+    };
+
+    // Instructions written as data jump nowhere
+    analysis::labels::annotate(
+        items.iter().filter_map(|&(offset, item)| match item {
+            Item::Instruction(instruction)
+                if !inexact.iter().any(|(range, _)| range.contains(&offset)) =>
+            {
+                Some((offset, instruction))
+            }
+            _ => None,
+        }),
+        &mut writer,
+    );
+
+    for (address, name) in symbols.variables_before() {
+        writer.write_str(&format!("{} equ {:#06x}", name, address));
+        writer.end_line();
+    }
+
+    for (symbol, _) in symbols.symbols() {
+        let is_item_start = items
+            .binary_search_by_key(&symbol, |&(offset, _)| offset)
+            .is_ok();
+
+        if !is_item_start {
+            writer.write_label_value(symbol);
+        }
+    }
+
+    for (range, text) in inexact.iter().filter(|(_, text)| !text.is_empty()) {
+        writer.append_comment(range.start, text);
+    }
+
+    if writer_options.undocumented {
+        for &(offset, item) in &items {
+            let Item::Instruction(instruction) = item else {
+                continue;
+            };
+            let Some(annotation) = undocumented::annotation(&instruction) else {
+                continue;
+            };
+            // Instructions written as data are annotated on the `db` line
+            let line = inexact
+                .iter()
+                .find(|(range, _)| range.contains(&offset))
+                .map_or(offset, |(range, _)| range.start);
+
+            writer.append_comment(line, &annotation);
+        }
     }
 
-    stdout().write_all(writer.as_slice())?;
+    // Last, so that the hits end the comments
+    let coverage = hits.map(|hits| analysis::coverage::lines(&items, hits));
+
+    if let Some(lines) = &coverage {
+        analysis::coverage::annotate(lines, &mut writer);
+    }
+
+    let mut inexact = inexact.into_iter().peekable();
+
+    for (offset, item) in items {
+        // Instructions in the middle of one written as data
+        if offset < index {
+            continue;
+        }
+
+        if let Some((range, _)) = inexact.next_if(|(range, _)| range.start == offset) {
+            writer.write_data(&bytes[range.clone()]);
+            index = range.end;
+            continue;
+        }
+
+        let length = match item {
+            Item::Instruction(instruction) => {
+                instruction.write(&mut writer);
+                instruction.length as usize
+            }
+            Item::Data(length) => {
+                let data = &bytes[offset..offset + length];
+
+                if strings
+                    .binary_search_by_key(&offset, |range| range.start)
+                    .is_ok()
+                {
+                    writer.write_string(data);
+                } else {
+                    writer.write_data(data);
+                }
+
+                length
+            }
+        };
+
+        index = offset + length;
+    }
+
+    writer.define_unwritten_labels();
+
+    let mut output = writer.as_slice().to_vec();
 
     if writer_options.verbose && index < bytes.len() {
-        print!(
+        write!(
+            output,
             "; Warning: {} bytes were not parsed. Bytes:",
             bytes.len() - index,
-        );
+        )?;
 
         for &byte in &bytes[index..] {
-            print!(" {:08b}", byte);
+            write!(output, " {:08b}", byte)?;
         }
 
-        println!();
+        writeln!(output)?;
     }
 
-    Ok(())
+    if let Some(lines) = &coverage {
+        output.extend(analysis::coverage::summary(lines, origin).bytes());
+    }
+
+    Ok(output)
 }
 
-pub fn run_from_file(
+/// Instructions read ahead when streaming, so that jumps back within them
+/// can be written with labels.
+const STREAM_CHUNK_LENGTH: usize = 4096;
+
+/// Disassembles the input as it is read, writing the output to the sink as
+/// it goes, so that memory use does not grow with the size of the input.
+/// The analyses and faithful mode need the whole input and are not applied.
+/// Returns how many bytes at the end could not be decoded.
+pub fn run_streaming(
     file_name: &str,
+    reader: impl Read,
+    sink: impl Write + 'static,
     writer_options: WriterOptions,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let bytes = read_file(file_name)?;
-    let base_name = std::path::Path::new(file_name)
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("");
-    run(base_name, bytes.as_slice(), writer_options)
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let mut writer = Writer::new(writer_options.clone());
+    let mut parser = StreamParser::new(reader);
+
+    if writer_options.undocumented {
+        parser = parser.with_undocumented();
+    }
+
+    writer.set_sink(Box::new(sink));
+    writer.write_comment(file_name);
+    writer.end_line();
+    writer.write(b"bits 16");
+    writer.end_line();
+
+    let mut chunk = Vec::with_capacity(STREAM_CHUNK_LENGTH);
+
+    loop {
+        chunk.clear();
+
+        for result in parser.by_ref().take(STREAM_CHUNK_LENGTH) {
+            chunk.push(result?);
+        }
+
+        if chunk.is_empty() {
+            break;
+        }
+
+        analysis::labels::annotate(chunk.iter().copied(), &mut writer);
+
+        for &(offset, instruction) in &chunk {
+            if writer_options.undocumented {
+                if let Some(annotation) = undocumented::annotation(&instruction) {
+                    writer.append_comment(offset, &annotation);
+                }
+            }
+
+            instruction.write(&mut writer);
+        }
+    }
+
+    let remaining = parser.remaining()?;
+
+    if writer_options.verbose && remaining > 0 {
+        writer.write_comment(&format!("Warning: {} bytes were not parsed.", remaining));
+    }
+
+    writer.finish()?;
+
+    Ok(remaining)
 }
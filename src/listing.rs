@@ -0,0 +1,286 @@
+use std::io::{self, Write};
+
+use crate::{
+    symbols::SymbolMap,
+    writer::{Writer, WriterOptions},
+    Instruction,
+};
+
+/// Instructions and bytes written with their addresses, for reading instead
+/// of reassembling.
+pub struct Listing<'a> {
+    bytes: &'a [u8],
+    symbols: Option<&'a SymbolMap>,
+    /// Address of the first byte.
+    origin: usize,
+    undocumented: bool,
+}
+
+impl<'a> Listing<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self {
+            bytes,
+            symbols: None,
+            origin: 0,
+            undocumented: false,
+        }
+    }
+
+    /// Writes the names and comments of the symbols, and leaves their data
+    /// ranges undecoded.
+    pub fn with_symbols(mut self, symbols: &'a SymbolMap) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Adds the address the bytes are loaded at to the written addresses.
+    pub fn with_origin(mut self, origin: usize) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Also decodes the opcodes the 8086 executes without them being
+    /// documented, see `Instruction::parse_undocumented`.
+    pub fn with_undocumented(mut self) -> Self {
+        self.undocumented = true;
+        self
+    }
+
+    fn parse(&self, offset: usize) -> Option<Instruction> {
+        let bytes = &self.bytes[offset..];
+
+        match self.undocumented {
+            true => Instruction::parse_undocumented(bytes),
+            false => Instruction::parse(bytes),
+        }
+    }
+
+    fn writer(&self) -> Writer {
+        let mut writer = Writer::new(WriterOptions {
+            verbose: false,
+            faithful: false,
+            undocumented: self.undocumented,
+        });
+
+        if let Some(symbols) = self.symbols {
+            symbols.annotate(&mut writer);
+        }
+
+        writer
+    }
+
+    fn add_target_label(&self, writer: &mut Writer, offset: usize, instruction: &Instruction) {
+        let Some(target) = instruction
+            .branch_target(offset)
+            .and_then(|target| usize::try_from(target).ok())
+        else {
+            return;
+        };
+
+        // Targets named by the symbols keep their names
+        if self
+            .symbols
+            .and_then(|symbols| symbols.name(target))
+            .is_none()
+        {
+            writer.add_label(target, &format!("{:#06x}", self.origin + target));
+        }
+    }
+
+    /// Returns the line of the instruction, without the labels and comments
+    /// written around it.
+    fn line(writer: &Writer) -> String {
+        let text = String::from_utf8_lossy(writer.as_slice());
+
+        text.lines()
+            .find(|line| !line.ends_with(':') && !line.starts_with(';') && !line.contains(" equ "))
+            .unwrap_or("")
+            .trim()
+            .to_string()
+    }
+
+    /// Writes an instruction on its own, with the names of the symbols and
+    /// jump targets as addresses.
+    pub fn instruction_text(&self, offset: usize, instruction: &Instruction) -> String {
+        let mut writer = self.writer();
+
+        self.add_target_label(&mut writer, offset, instruction);
+        writer.set_position(offset);
+        instruction.write(&mut writer);
+
+        Self::line(&writer)
+    }
+
+    /// Decodes the instruction at the offset along with the prefixes before
+    /// it, returning their length and text.
+    pub fn line_at(&self, offset: usize) -> Option<(usize, String)> {
+        let mut writer = self.writer();
+        let mut index = offset;
+
+        writer.set_position(offset);
+
+        // Prefixes are written with the instruction after them
+        while writer.as_slice().is_empty() {
+            let instruction = self.parse(index)?;
+
+            self.add_target_label(&mut writer, index, &instruction);
+            instruction.write(&mut writer);
+            index += instruction.length as usize;
+        }
+
+        Some((index - offset, Self::line(&writer)))
+    }
+
+    fn write_line(
+        &self,
+        out: &mut impl Write,
+        offset: usize,
+        length: usize,
+        text: &str,
+    ) -> io::Result<()> {
+        let hex: Vec<String> = self.bytes[offset..offset + length]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+
+        writeln!(
+            out,
+            "{:04x}  {:<18}{}",
+            self.origin + offset,
+            hex.join(" "),
+            text
+        )
+    }
+
+    /// Writes the instruction at the offset with its address and bytes.
+    pub fn write_instruction(
+        &self,
+        out: &mut impl Write,
+        offset: usize,
+        instruction: &Instruction,
+    ) -> io::Result<()> {
+        let text = self.instruction_text(offset, instruction);
+
+        self.write_line(out, offset, instruction.length as usize, &text)
+    }
+
+    /// Writes up to `count` instructions from the offset, returning the
+    /// offset after them. Data and bytes that can not be decoded are written
+    /// as `db`.
+    pub fn write_instructions(
+        &self,
+        out: &mut impl Write,
+        start: usize,
+        count: usize,
+    ) -> io::Result<usize> {
+        let mut offset = start;
+
+        for _ in 0..count {
+            if offset >= self.bytes.len() {
+                break;
+            }
+
+            if let Some(name) = self.symbols.and_then(|symbols| symbols.name(offset)) {
+                writeln!(out, "{}:", name)?;
+            }
+
+            let data = self.symbols.and_then(|symbols| {
+                symbols
+                    .data_ranges()
+                    .into_iter()
+                    .find(|range| range.contains(&offset))
+            });
+            let line = self.line_at(offset).filter(|_| data.is_none());
+
+            let Some((length, text)) = line else {
+                let end = data.map_or(offset + 1, |range| range.end.min(offset + 6));
+                let end = end.min(self.bytes.len());
+                let bytes: Vec<String> = self.bytes[offset..end]
+                    .iter()
+                    .map(|byte| format!("{:#04x}", byte))
+                    .collect();
+
+                self.write_line(
+                    out,
+                    offset,
+                    end - offset,
+                    &format!("db {}", bytes.join(", ")),
+                )?;
+                offset = end;
+                continue;
+            };
+
+            self.write_line(out, offset, length, &text)?;
+            offset += length;
+        }
+
+        Ok(offset)
+    }
+
+    /// Writes the bytes in [start, end) as rows of 16 in hex and ASCII.
+    pub fn write_dump(&self, out: &mut impl Write, start: usize, end: usize) -> io::Result<()> {
+        let end = end.min(self.bytes.len());
+
+        for offset in (start..end).step_by(16) {
+            let row = &self.bytes[offset..(offset + 16).min(end)];
+            let hex: Vec<String> = row.iter().map(|byte| format!("{:02x}", byte)).collect();
+            let text: String = row
+                .iter()
+                .map(|&byte| match byte {
+                    0x20..=0x7e => byte as char,
+                    _ => '.',
+                })
+                .collect();
+
+            writeln!(
+                out,
+                "{:04x}  {:<48}{}",
+                self.origin + offset,
+                hex.join(" "),
+                text
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_listing() {
+    // jmp to itself; mov ax, 0x1234; an undecodable byte
+    let bytes = [0xeb, 0xfe, 0xb8, 0x34, 0x12, 0xd6, b'A'];
+    let listing = Listing::new(&bytes).with_origin(0x100);
+    let mut output = Vec::new();
+
+    assert_eq!(listing.write_instructions(&mut output, 0, 3).unwrap(), 6);
+
+    // rep movsb; mov ax, es:[bx]; jmp into itself
+    let prefixed = [0xf3, 0xa4, 0x26, 0x8b, 0x07, 0xeb, 0xff];
+    let lines: Vec<_> = [0, 2, 5]
+        .iter()
+        .map(|&offset| Listing::new(&prefixed).line_at(offset).unwrap())
+        .collect();
+
+    assert_eq!(
+        lines,
+        vec![
+            (2, "rep movsb".to_string()),
+            (3, "mov ax, es:[bx]".to_string()),
+            (2, "jmp 0x0006".to_string())
+        ]
+    );
+    listing.write_dump(&mut output, 0, usize::MAX).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        format!(
+            "\
+0100  eb fe             jmp 0x0100
+0102  b8 34 12          mov ax, 0x1234
+0105  d6                db 0xd6
+0100  eb fe b8 34 12 d6 41{}...4..A
+",
+            " ".repeat(28)
+        )
+    );
+}
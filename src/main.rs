@@ -1,15 +1,21 @@
-use de8086::{run_from_file, writer::WriterOptions};
-use std::env;
+mod cli;
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let args: Vec<String> = env::args().collect();
+use std::{env, process::exit};
 
-    if args.len() < 2 {
-        println!("Usage: {} <file>", args[0]);
-        return Ok(());
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.is_empty() {
+        eprintln!("{}", cli::USAGE);
+        exit(cli::EXIT_USAGE);
     }
 
-    let verbose = args.len() > 2 && (args[2] == "-v" || args[2] == "--verbose");
+    if let Err(error) = cli::run(&args) {
+        // Programs run with `exec --dos` exit with their own codes
+        if !error.message.is_empty() {
+            eprintln!("{}", error);
+        }
 
-    run_from_file(&args[1], WriterOptions { verbose })
+        exit(error.code);
+    }
 }
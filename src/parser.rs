@@ -1,12 +1,15 @@
 use crate::instructions::instruction::Instruction;
 use std::error::Error;
 use std::fmt;
+use std::io::{self, Read};
+use std::ops::Range;
 
 #[derive(Debug, Copy, Clone)]
 pub struct Parser<'a> {
     bytes: &'a [u8],
     current_index: usize,
     instruction: Instruction,
+    undocumented: bool,
 }
 
 #[derive(Debug)]
@@ -21,7 +24,7 @@ impl fmt::Display for ParserInitError {
 }
 
 impl<'a> Parser<'a> {
-    pub fn build(bytes: &'a [u8]) -> Result<Parser, ParserInitError> {
+    pub fn build(bytes: &'a [u8]) -> Result<Parser<'a>, ParserInitError> {
         if bytes.is_empty() {
             return Err(ParserInitError);
         }
@@ -30,8 +33,210 @@ impl<'a> Parser<'a> {
             bytes,
             current_index: 0,
             instruction: Instruction::EMPTY,
+            undocumented: false,
         })
     }
+
+    /// Also decodes the opcodes the 8086 executes without them being
+    /// documented, see `Instruction::parse_undocumented`.
+    pub fn with_undocumented(mut self) -> Self {
+        self.undocumented = true;
+        self
+    }
+
+    /// Pairs every parsed instruction with its byte offset in the input.
+    pub fn with_offsets(mut self) -> impl Iterator<Item = (usize, Instruction)> + 'a {
+        std::iter::from_fn(move || {
+            let offset = self.current_index;
+            self.next().map(|instruction| (offset, instruction))
+        })
+    }
+
+    /// Byte offset of the next instruction to parse.
+    pub fn position(&self) -> usize {
+        self.current_index
+    }
+
+    /// Continues parsing at the given byte offset.
+    pub fn seek(&mut self, index: usize) {
+        self.current_index = index;
+    }
+}
+
+/// The most bytes an instruction takes, not counting prefixes.
+const MAX_INSTRUCTION_LENGTH: usize = 6;
+
+/// Parses instructions from a reader, keeping only the bytes of the next
+/// instruction in memory. Prefixes are parsed as instructions of their own.
+pub struct StreamParser<R: Read> {
+    reader: R,
+    buffer: [u8; MAX_INSTRUCTION_LENGTH],
+    length: usize,
+    offset: usize,
+    undocumented: bool,
+    done: bool,
+}
+
+impl<R: Read> StreamParser<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            buffer: [0; MAX_INSTRUCTION_LENGTH],
+            length: 0,
+            offset: 0,
+            undocumented: false,
+            done: false,
+        }
+    }
+
+    /// Also decodes the opcodes the 8086 executes without them being
+    /// documented, see `Instruction::parse_undocumented`.
+    pub fn with_undocumented(mut self) -> Self {
+        self.undocumented = true;
+        self
+    }
+
+    /// Byte offset of the next instruction to parse.
+    pub fn position(&self) -> usize {
+        self.offset
+    }
+
+    /// Reads the rest of the input, returning how many bytes were not
+    /// parsed.
+    pub fn remaining(mut self) -> io::Result<usize> {
+        let rest = io::copy(&mut self.reader, &mut io::sink())?;
+
+        Ok(self.length + rest as usize)
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        while self.length < MAX_INSTRUCTION_LENGTH {
+            match self.reader.read(&mut self.buffer[self.length..]) {
+                Ok(0) => break,
+                Ok(read) => self.length += read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Iterator for StreamParser<R> {
+    type Item = io::Result<(usize, Instruction)>;
+
+    /// Parses the next instruction, paired with its byte offset.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if let Err(error) = self.fill() {
+            self.done = true;
+            return Some(Err(error));
+        }
+
+        let Some(instruction) = parse_instruction(&self.buffer[..self.length], self.undocumented)
+        else {
+            self.done = true;
+            return None;
+        };
+        let offset = self.offset;
+        let length = instruction.length as usize;
+
+        self.buffer.copy_within(length..self.length, 0);
+        self.length -= length;
+        self.offset += length;
+
+        Some(Ok((offset, instruction)))
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Item {
+    Instruction(Instruction),
+    /// Bytes that are not decoded, with their length.
+    Data(usize),
+}
+
+fn parse_instruction(bytes: &[u8], undocumented: bool) -> Option<Instruction> {
+    match undocumented {
+        true => Instruction::parse_undocumented(bytes),
+        false => Instruction::parse(bytes),
+    }
+}
+
+/// Parses the bytes like `Parser`, but keeps the given byte ranges as data.
+/// An instruction that would overlap the start of a data range is kept as
+/// data as well. Parsing stops at the first byte that can not be decoded.
+pub fn parse_with_data(
+    bytes: &[u8],
+    data: &[Range<usize>],
+    undocumented: bool,
+) -> Vec<(usize, Item)> {
+    parse_items(bytes, data, undocumented, false)
+}
+
+/// Parses the bytes like `parse_with_data`, but keeps each byte that can not
+/// be decoded as data of its own and goes on after it.
+pub fn parse_to_end(bytes: &[u8], data: &[Range<usize>], undocumented: bool) -> Vec<(usize, Item)> {
+    parse_items(bytes, data, undocumented, true)
+}
+
+fn parse_items(
+    bytes: &[u8],
+    data: &[Range<usize>],
+    undocumented: bool,
+    to_end: bool,
+) -> Vec<(usize, Item)> {
+    let mut data: Vec<Range<usize>> = data
+        .iter()
+        .filter(|range| range.start < range.end && range.start < bytes.len())
+        .cloned()
+        .collect();
+    data.sort_by_key(|range| range.start);
+
+    let mut items = Vec::new();
+    let mut ranges = data.iter().peekable();
+    let mut index = 0;
+
+    while index < bytes.len() {
+        while ranges.next_if(|range| range.end <= index).is_some() {}
+
+        let next_data = ranges.peek().map(|range| range.start.max(index));
+
+        if let Some(range) = ranges.peek().filter(|range| range.start <= index) {
+            let end = range.end.min(bytes.len());
+            items.push((index, Item::Data(end - index)));
+            index = end;
+            continue;
+        }
+
+        let Some(instruction) = parse_instruction(&bytes[index..], undocumented) else {
+            if !to_end {
+                break;
+            }
+
+            items.push((index, Item::Data(1)));
+            index += 1;
+            continue;
+        };
+        let end = index + instruction.length as usize;
+
+        match next_data {
+            Some(start) if end > start => {
+                items.push((index, Item::Data(start - index)));
+                index = start;
+            }
+            _ => {
+                items.push((index, Item::Instruction(instruction)));
+                index = end;
+            }
+        }
+    }
+
+    items
 }
 
 impl Iterator for Parser<'_> {
@@ -47,10 +252,106 @@ impl Iterator for Parser<'_> {
 
         let remaining_bytes_slice = &self.bytes[self.current_index..];
 
-        self.instruction = Instruction::parse(remaining_bytes_slice)?;
+        self.instruction = parse_instruction(remaining_bytes_slice, self.undocumented)?;
 
         self.current_index += self.instruction.length as usize;
 
         Some(self.instruction)
     }
 }
+
+#[test]
+fn test_parse_with_data() {
+    // mov ax, bx; db 0x00, 0x01; add ax, bx; 'sub ax, bx' cut by data
+    let bytes = [
+        0b1000_1001,
+        0b1101_1000,
+        0x00,
+        0x01,
+        0b0000_0001,
+        0b1101_1000,
+        0b0010_1001,
+        0b1101_1000,
+    ];
+    let items = parse_with_data(&bytes, &[2..4, 7..8], false);
+    let items: Vec<(usize, Option<usize>)> = items
+        .iter()
+        .map(|(offset, item)| match item {
+            Item::Instruction(_) => (*offset, None),
+            Item::Data(length) => (*offset, Some(*length)),
+        })
+        .collect();
+
+    assert_eq!(
+        items,
+        vec![
+            (0, None),
+            (2, Some(2)),
+            (4, None),
+            (6, Some(1)),
+            (7, Some(1))
+        ]
+    );
+}
+
+#[test]
+fn test_parse_to_end() {
+    // mov ax, bx; two bytes that do not decode; add ax, bx cut off
+    let bytes = [0b1000_1001, 0b1101_1000, 0xd6, 0xd6, 0b0000_0001];
+    let items: Vec<(usize, Option<usize>)> = parse_to_end(&bytes, &[], false)
+        .iter()
+        .map(|(offset, item)| match item {
+            Item::Instruction(_) => (*offset, None),
+            Item::Data(length) => (*offset, Some(*length)),
+        })
+        .collect();
+
+    assert_eq!(
+        items,
+        vec![(0, None), (2, Some(1)), (3, Some(1)), (4, Some(1))]
+    );
+    assert_eq!(parse_with_data(&bytes, &[], false).len(), 1);
+}
+
+#[test]
+fn test_parse_cut_off_instruction() {
+    // Instructions missing bytes at the end of the input, starting with
+    // group opcodes that are resolved by their second byte
+    let inputs: [&[u8]; 5] = [
+        &[0xff],
+        &[0xd0],
+        &[0xb8, 0x01],
+        &[0x8b, 0x87, 0x01],
+        &[0xeb],
+    ];
+
+    for bytes in inputs {
+        assert!(Instruction::parse(bytes).is_none(), "{:02x?}", bytes);
+        assert!(Instruction::parse_undocumented(bytes).is_none());
+        assert_eq!(Parser::build(bytes).unwrap().count(), 0);
+    }
+}
+
+#[test]
+fn test_stream_parser() {
+    // mov ax, bx; add ax, bx; then a byte that does not decode
+    let bytes: &[u8] = &[
+        0b1000_1001,
+        0b1101_1000,
+        0b0000_0001,
+        0b1101_1000,
+        0xd6,
+        0x00,
+    ];
+    let mut parser = StreamParser::new(bytes);
+    let offsets: Vec<usize> = parser.by_ref().map(|result| result.unwrap().0).collect();
+
+    assert_eq!(offsets, vec![0, 2]);
+    assert_eq!(parser.position(), 4);
+    assert_eq!(parser.remaining().unwrap(), 2);
+
+    // An instruction cut off by the end of the input
+    let mut parser = StreamParser::new(&[0xb8, 0x34][..]);
+
+    assert!(parser.next().is_none());
+}
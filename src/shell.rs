@@ -0,0 +1,335 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    instructions::operand::{Memory, Operand},
+    layout,
+    listing::Listing,
+    parser::Item,
+    symbols::{parse_address, SymbolMap},
+};
+
+/// Instructions written by `u` without a count.
+const DEFAULT_UNASSEMBLE_COUNT: usize = 16;
+
+/// Bytes dumped by `d` without a length.
+const DEFAULT_DUMP_LENGTH: usize = 128;
+
+const HELP: &str = "\
+Addresses are hexadecimal or label names, counts are decimal.
+  u [addr] [n]        unassemble n instructions
+  d [addr] [len]      dump len bytes
+  goto <addr>         continue u and d from the address
+  label <addr> <name> name an address
+  comment <addr> <text>
+                      comment an address
+  data <from> <to>    mark [from, to) as data
+  xrefs <addr>        list the jumps, calls and direct accesses to an address
+  save <file>         write the labels, comments and data to a symbol file
+  help                show this text
+  quit                leave the shell";
+
+#[derive(Debug)]
+pub struct ShellError {
+    pub message: String,
+}
+
+impl Error for ShellError {}
+
+impl fmt::Display for ShellError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Error: {}", self.message)
+    }
+}
+
+fn error<T>(message: impl Into<String>) -> Result<T, ShellError> {
+    Err(ShellError {
+        message: message.into(),
+    })
+}
+
+/// Text commands for exploring a binary that is loaded once.
+pub struct Shell {
+    bytes: Vec<u8>,
+    symbols: SymbolMap,
+    /// Where `u` and `d` continue from.
+    position: usize,
+    undocumented: bool,
+    prompt: bool,
+}
+
+impl Shell {
+    pub fn new(bytes: Vec<u8>, symbols: SymbolMap) -> Self {
+        Self {
+            bytes,
+            symbols,
+            position: 0,
+            undocumented: false,
+            prompt: false,
+        }
+    }
+
+    /// Also decodes the opcodes the 8086 executes without them being
+    /// documented, see `Instruction::parse_undocumented`.
+    pub fn with_undocumented(mut self) -> Self {
+        self.undocumented = true;
+        self
+    }
+
+    /// Writes a prompt before reading each command, for interactive use.
+    pub fn with_prompt(mut self) -> Self {
+        self.prompt = true;
+        self
+    }
+
+    /// Runs the commands read from the input until it ends or a `quit`.
+    /// Errors in commands are written to the output.
+    pub fn run(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        let mut lines = input.lines();
+
+        loop {
+            if self.prompt {
+                write!(out, "> ")?;
+                out.flush()?;
+            }
+
+            let Some(line) = lines.next() else {
+                return Ok(());
+            };
+
+            match self.execute(&line?, out) {
+                Ok(true) => {}
+                Ok(false) => return Ok(()),
+                Err(error) => writeln!(out, "{}", error)?,
+            }
+        }
+    }
+
+    /// Runs a command, returning whether to read another one.
+    pub fn execute(&mut self, line: &str, out: &mut impl Write) -> Result<bool, ShellError> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let arguments: Vec<&str> = words.collect();
+
+        let result = match command {
+            "u" => self.unassemble(&arguments, out),
+            "d" => self.dump(&arguments, out),
+            "goto" => self.goto(&arguments),
+            "label" => self.label(&arguments),
+            "comment" => self.comment(line, &arguments),
+            "data" => self.data(&arguments),
+            "xrefs" => self.xrefs(&arguments, out),
+            "save" => self.save(&arguments),
+            "help" => writeln!(out, "{}", HELP).map_err(Into::into),
+            "quit" | "q" => return Ok(false),
+            _ => error(format!("unknown command {}", command)),
+        };
+
+        result.map(|_| true)
+    }
+
+    fn address(&self, argument: Option<&&str>) -> Result<usize, ShellError> {
+        let Some(argument) = argument else {
+            return error("missing address");
+        };
+        let address = self
+            .symbols
+            .symbols()
+            .find(|(_, symbol)| symbol.name.as_deref() == Some(*argument))
+            .map(|(address, _)| address)
+            .or_else(|| parse_address(argument));
+
+        match address {
+            Some(address) if address <= self.bytes.len() => Ok(address),
+            Some(address) => error(format!("{:#06x} is past the end of the file", address)),
+            None => error(format!("invalid address {}", argument)),
+        }
+    }
+
+    fn count(argument: Option<&&str>, default: usize) -> Result<usize, ShellError> {
+        match argument {
+            Some(argument) => argument
+                .parse()
+                .or_else(|_| error(format!("invalid count {}", argument))),
+            None => Ok(default),
+        }
+    }
+
+    fn start(&self, argument: Option<&&str>) -> Result<usize, ShellError> {
+        match argument {
+            Some(_) => self.address(argument),
+            None => Ok(self.position),
+        }
+    }
+
+    fn listing(&self) -> Listing<'_> {
+        let listing = Listing::new(&self.bytes).with_symbols(&self.symbols);
+
+        match self.undocumented {
+            true => listing.with_undocumented(),
+            false => listing,
+        }
+    }
+
+    fn unassemble(&mut self, arguments: &[&str], out: &mut impl Write) -> Result<(), ShellError> {
+        let start = self.start(arguments.first())?;
+        let count = Self::count(arguments.get(1), DEFAULT_UNASSEMBLE_COUNT)?;
+        let offset = self.listing().write_instructions(out, start, count)?;
+
+        self.position = offset;
+
+        Ok(())
+    }
+
+    fn dump(&mut self, arguments: &[&str], out: &mut impl Write) -> Result<(), ShellError> {
+        let start = self.start(arguments.first())?;
+        let length = Self::count(arguments.get(1), DEFAULT_DUMP_LENGTH)?;
+        let end = start.saturating_add(length).min(self.bytes.len());
+
+        self.listing().write_dump(out, start, end)?;
+        self.position = end;
+
+        Ok(())
+    }
+
+    fn goto(&mut self, arguments: &[&str]) -> Result<(), ShellError> {
+        self.position = self.address(arguments.first())?;
+
+        Ok(())
+    }
+
+    fn label(&mut self, arguments: &[&str]) -> Result<(), ShellError> {
+        let address = self.address(arguments.first())?;
+
+        if arguments.len() != 2 {
+            return error("usage: label <addr> <name>");
+        }
+
+        // Names are looked up before addresses, so a hex name would hide one
+        if parse_address(arguments[1]).is_some() {
+            return error(format!("{} looks like an address", arguments[1]));
+        }
+
+        self.symbols.set_name(address, arguments[1]);
+
+        Ok(())
+    }
+
+    fn comment(&mut self, line: &str, arguments: &[&str]) -> Result<(), ShellError> {
+        let address = self.address(arguments.first())?;
+        let text = line
+            .trim()
+            .splitn(3, char::is_whitespace)
+            .nth(2)
+            .map(str::trim)
+            .unwrap_or("");
+
+        if text.is_empty() {
+            return error("usage: comment <addr> <text>");
+        }
+
+        self.symbols.set_comment(address, text);
+
+        Ok(())
+    }
+
+    fn data(&mut self, arguments: &[&str]) -> Result<(), ShellError> {
+        let from = self.address(arguments.first())?;
+        let to = self.address(arguments.get(1))?;
+
+        if from >= to {
+            return error("the data range is empty");
+        }
+
+        self.symbols.add_data_range(from..to);
+
+        Ok(())
+    }
+
+    fn xrefs(&self, arguments: &[&str], out: &mut impl Write) -> Result<(), ShellError> {
+        let address = self.address(arguments.first())?;
+        let (items, _) = layout(&self.bytes, &self.symbols, self.undocumented, 0);
+
+        for (offset, item) in items {
+            let Item::Instruction(instruction) = item else {
+                continue;
+            };
+            let jumps = instruction.branch_target(offset) == Some(address as isize);
+            let accesses = instruction.operation().is_some_and(|operation| {
+                operation.operands.iter().any(|operand| {
+                    matches!(
+                        operand,
+                        Operand::Memory(Memory { base: None, displacement, .. })
+                            if *displacement as u16 as usize == address
+                    )
+                })
+            });
+
+            if jumps || accesses {
+                self.listing()
+                    .write_instruction(out, offset, &instruction)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn save(&self, arguments: &[&str]) -> Result<(), ShellError> {
+        let [file_name] = arguments else {
+            return error("usage: save <file>");
+        };
+        let mut file = std::fs::File::create(file_name)?;
+
+        self.symbols.write(&mut file)?;
+
+        Ok(())
+    }
+}
+
+impl From<io::Error> for ShellError {
+    fn from(error: io::Error) -> Self {
+        Self {
+            message: error.to_string(),
+        }
+    }
+}
+
+#[test]
+fn test_shell() {
+    // start: mov ax, 0x1234; mov [0x0010], ax; jmp start; db 'Hi'
+    let bytes = vec![
+        0xb8, 0x34, 0x12, 0xa3, 0x10, 0x00, 0xeb, 0xf8, b'H', b'i', 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00,
+    ];
+    let mut shell = Shell::new(bytes, SymbolMap::new());
+    let mut output = Vec::new();
+    let input = "\
+label 0 start
+label 10 counter
+u 0 3
+xrefs counter
+xrefs start
+d 8 2
+bogus
+quit
+u 0";
+
+    shell.run(input.as_bytes(), &mut output).unwrap();
+
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "\
+start:
+0000  b8 34 12          mov ax, 0x1234
+0003  a3 10 00          mov [counter], ax
+0006  eb f8             jmp start
+0003  a3 10 00          mov [counter], ax
+0006  eb f8             jmp start
+0008  48 69                                           Hi
+Error: unknown command bogus
+"
+    );
+}
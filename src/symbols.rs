@@ -0,0 +1,323 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Write};
+use std::ops::Range;
+
+use crate::writer::Writer;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Symbol {
+    pub name: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// Names, comments and data ranges assigned to addresses by the user.
+///
+/// Addresses are where the input is loaded, so that with `--org 0x100` the
+/// first byte is at 0100. The map is looked up by byte offsets into the
+/// input, which `with_origin` converts them to.
+///
+/// Symbol files are line based. Addresses are hexadecimal, with an optional
+/// `0x` prefix, and everything after `#` is ignored:
+///
+/// ```text
+/// # address name [comment]
+/// 0010 print_message Prints the string at DS:DX
+/// 1234 g_counter
+/// # address - comment
+/// 0012 - Loop over the message
+/// # start..end name [comment], marks [start, end) as data
+/// 0100..0110 message
+/// 0110..0120 -
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SymbolMap {
+    symbols: BTreeMap<usize, Symbol>,
+    data_ranges: Vec<Range<usize>>,
+    /// Address of the first byte of the input.
+    origin: usize,
+}
+
+#[derive(Debug)]
+pub struct SymbolParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Error for SymbolParseError {}
+
+impl fmt::Display for SymbolParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid symbol file on line {}: {}",
+            self.line, self.message
+        )
+    }
+}
+
+pub(crate) fn parse_address(string: &str) -> Option<usize> {
+    let digits = string
+        .strip_prefix("0x")
+        .or_else(|| string.strip_prefix("0X"))
+        .unwrap_or(string);
+
+    usize::from_str_radix(digits, 16).ok()
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '@' | '$'))
+}
+
+impl SymbolMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(text: &str) -> Result<Self, SymbolParseError> {
+        let mut map = Self::new();
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: &str| SymbolParseError {
+                line: number + 1,
+                message: message.to_string(),
+            };
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                continue;
+            }
+
+            let (address, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let (name, comment) = rest
+                .trim()
+                .split_once(char::is_whitespace)
+                .unwrap_or((rest.trim(), ""));
+
+            if name.is_empty() {
+                return Err(error("missing name"));
+            }
+
+            let name = match name {
+                "-" => None,
+                name if is_valid_name(name) => Some(name),
+                name => return Err(error(&format!("invalid name '{}'", name))),
+            };
+            let comment = Some(comment.trim()).filter(|comment| !comment.is_empty());
+
+            let start = match address.split_once("..") {
+                Some((start, end)) => {
+                    let start = parse_address(start).ok_or_else(|| error("invalid address"))?;
+                    let end = parse_address(end).ok_or_else(|| error("invalid address"))?;
+
+                    if end <= start {
+                        return Err(error("data range ends before it starts"));
+                    }
+
+                    map.add_data_range(start..end);
+                    start
+                }
+                None => parse_address(address).ok_or_else(|| error("invalid address"))?,
+            };
+
+            if let Some(name) = name {
+                map.set_name(start, name);
+            }
+
+            if let Some(comment) = comment {
+                map.set_comment(start, comment);
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn load(file_name: &str) -> Result<Self, Box<dyn Error>> {
+        let text = std::fs::read_to_string(file_name)?;
+
+        Ok(Self::parse(&text)?)
+    }
+
+    /// Sets the address the input is loaded at, which is subtracted from
+    /// the addresses of the symbols to get their byte offsets.
+    pub fn with_origin(mut self, origin: usize) -> Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Returns the byte offset of an address, if it is not before the input.
+    fn offset(&self, address: usize) -> Option<usize> {
+        address.checked_sub(self.origin)
+    }
+
+    pub fn set_name(&mut self, address: usize, name: &str) {
+        self.symbols.entry(address).or_default().name = Some(name.to_string());
+    }
+
+    pub fn set_comment(&mut self, address: usize, comment: &str) {
+        self.symbols.entry(address).or_default().comment = Some(comment.to_string());
+    }
+
+    pub fn add_data_range(&mut self, range: Range<usize>) {
+        self.data_ranges.push(range);
+        self.data_ranges.sort_by_key(|range| range.start);
+    }
+
+    /// Returns the name at the byte offset.
+    pub fn name(&self, offset: usize) -> Option<&str> {
+        self.symbols.get(&(self.origin + offset))?.name.as_deref()
+    }
+
+    /// Returns the comment at the byte offset.
+    pub fn comment(&self, offset: usize) -> Option<&str> {
+        self.symbols
+            .get(&(self.origin + offset))?
+            .comment
+            .as_deref()
+    }
+
+    /// Returns the symbols with their byte offsets, leaving out the ones
+    /// before the input.
+    pub fn symbols(&self) -> impl Iterator<Item = (usize, &Symbol)> {
+        self.symbols
+            .iter()
+            .filter_map(|(&address, symbol)| Some((self.offset(address)?, symbol)))
+    }
+
+    /// Returns the names before the input with their addresses, which are
+    /// only referred to as memory operands.
+    pub fn variables_before(&self) -> impl Iterator<Item = (usize, &str)> {
+        self.symbols
+            .range(..self.origin)
+            .filter_map(|(&address, symbol)| Some((address, symbol.name.as_deref()?)))
+    }
+
+    /// Returns the data ranges as byte offsets, cut at the start of the input.
+    pub fn data_ranges(&self) -> Vec<Range<usize>> {
+        self.data_ranges
+            .iter()
+            .filter(|range| range.end > self.origin)
+            .map(|range| range.start.saturating_sub(self.origin)..range.end - self.origin)
+            .collect()
+    }
+
+    /// Names labels, direct addresses and jump targets and adds the comments
+    /// to the written instructions.
+    pub fn annotate(&self, writer: &mut Writer) {
+        for (&address, symbol) in &self.symbols {
+            if let Some(name) = &symbol.name {
+                writer.add_variable(address, name);
+            }
+
+            let Some(offset) = self.offset(address) else {
+                continue;
+            };
+
+            if let Some(name) = &symbol.name {
+                writer.add_label(offset, name);
+            }
+
+            if let Some(comment) = &symbol.comment {
+                writer.append_comment(offset, comment);
+            }
+        }
+    }
+
+    /// Writes the map in the symbol file format.
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut ranges = self.data_ranges.iter().peekable();
+
+        for (&address, symbol) in &self.symbols {
+            while let Some(range) = ranges.next_if(|range| range.start < address) {
+                writeln!(out, "{:04x}..{:04x} -", range.start, range.end)?;
+            }
+
+            match ranges.next_if(|range| range.start == address) {
+                Some(range) => write!(out, "{:04x}..{:04x} ", range.start, range.end)?,
+                None => write!(out, "{:04x} ", address)?,
+            }
+
+            write!(out, "{}", symbol.name.as_deref().unwrap_or("-"))?;
+
+            match &symbol.comment {
+                Some(comment) => writeln!(out, " {}", comment)?,
+                None => writeln!(out)?,
+            }
+        }
+
+        for range in ranges {
+            writeln!(out, "{:04x}..{:04x} -", range.start, range.end)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_symbol_map_parse_and_write() {
+    let text = "# symbols
+0010 print_message Prints the string at DS:DX
+0x1234 g_counter
+0012 - Loop over the message  # trailing comment
+0100..0110 message
+0110..0120 -
+";
+    let map = SymbolMap::parse(text).unwrap();
+
+    assert_eq!(map.name(0x10), Some("print_message"));
+    assert_eq!(map.comment(0x10), Some("Prints the string at DS:DX"));
+    assert_eq!(map.name(0x1234), Some("g_counter"));
+    assert_eq!(map.name(0x12), None);
+    assert_eq!(map.comment(0x12), Some("Loop over the message"));
+    assert_eq!(map.data_ranges(), &[0x100..0x110, 0x110..0x120]);
+
+    let mut written = Vec::new();
+    map.write(&mut written).unwrap();
+
+    assert_eq!(
+        String::from_utf8(written).unwrap(),
+        "0010 print_message Prints the string at DS:DX
+0012 - Loop over the message
+0100..0110 message
+0110..0120 -
+1234 g_counter
+"
+    );
+}
+
+#[test]
+fn test_symbol_map_parse_errors() {
+    let error = SymbolMap::parse("0010 main\nzz name\n").unwrap_err();
+    assert_eq!(error.line, 2);
+
+    assert!(SymbolMap::parse("0010").is_err());
+    assert!(SymbolMap::parse("0010 1abc").is_err());
+    assert!(SymbolMap::parse("0020..0010 -").is_err());
+}
+
+#[test]
+fn test_symbol_map_annotate() {
+    use crate::{disassemble_with_symbols, writer::WriterOptions};
+
+    // mov ah, 0x09; int 0x21, whose generated comment is kept
+    let map = SymbolMap::parse("0002 - Say hello").unwrap();
+    let output = disassemble_with_symbols(
+        "",
+        &[0xb4, 0x09, 0xcd, 0x21],
+        WriterOptions {
+            verbose: false,
+            faithful: false,
+            undocumented: false,
+        },
+        &map,
+    )
+    .unwrap();
+
+    assert!(String::from_utf8(output)
+        .unwrap()
+        .contains("; DOS: print string (DS:DX), Say hello\n"));
+}
@@ -0,0 +1,198 @@
+use std::{error::Error, fmt};
+
+use crate::{
+    assembler::assemble,
+    disassemble_with_origin, layout, layout_to_end,
+    parser::Item,
+    symbols::SymbolMap,
+    writer::{Writer, WriterOptions},
+    Instruction,
+};
+
+/// The first difference between a program and its reassembled disassembly.
+#[derive(Debug)]
+pub struct Mismatch {
+    pub offset: usize,
+    /// The instruction or data at the offset in the input, or `None` if the
+    /// reassembled program is longer.
+    pub original: Option<String>,
+    /// The instruction or data at the offset in the reassembled program, or
+    /// `None` if it is shorter.
+    pub regenerated: Option<String>,
+}
+
+impl Error for Mismatch {}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let describe = |item: &Option<String>| match item {
+            Some(item) => item.clone(),
+            None => "the end of the file".to_string(),
+        };
+
+        write!(
+            f,
+            "Reassembly differs at offset {:#06x}: {} became {}",
+            self.offset,
+            describe(&self.original),
+            describe(&self.regenerated)
+        )
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Writes the instruction at the given offset, along with the prefixes
+/// before it, followed by its bytes.
+fn describe_instruction(bytes: &[u8], offset: usize) -> Option<String> {
+    let mut writer = Writer::new(WriterOptions {
+        verbose: false,
+        faithful: false,
+        undocumented: false,
+    });
+    let mut index = offset;
+
+    writer.set_position(offset);
+
+    while writer.as_slice().is_empty() {
+        let instruction = Instruction::parse_undocumented(bytes.get(index..)?)?;
+
+        instruction.write(&mut writer);
+        index += instruction.length as usize;
+    }
+
+    let text = String::from_utf8_lossy(writer.as_slice());
+
+    Some(format!(
+        "`{}` ({})",
+        text.trim(),
+        hex(&bytes[offset..index])
+    ))
+}
+
+fn describe_data(bytes: &[u8], offset: usize, length: usize) -> Option<String> {
+    let data = bytes.get(offset..)?;
+
+    Some(format!("data ({})", hex(&data[..length.min(data.len())])))
+}
+
+/// Disassembles the input, reassembles the result and checks that it is
+/// the same as the input.
+pub fn verify(bytes: &[u8], writer_options: WriterOptions) -> Result<(), Box<dyn Error>> {
+    verify_with_symbols(bytes, writer_options, &SymbolMap::new())
+}
+
+pub fn verify_with_symbols(
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+) -> Result<(), Box<dyn Error>> {
+    verify_with_origin(bytes, writer_options, symbols, 0)
+}
+
+/// Verifies the input as loaded at the given address, which the addresses
+/// of the symbols are relative to.
+pub fn verify_with_origin(
+    bytes: &[u8],
+    writer_options: WriterOptions,
+    symbols: &SymbolMap,
+    origin: usize,
+) -> Result<(), Box<dyn Error>> {
+    let undocumented = writer_options.undocumented;
+    let faithful = writer_options.faithful;
+    let source = disassemble_with_origin("", bytes, writer_options, symbols, origin)?;
+    let regenerated = assemble(&String::from_utf8_lossy(&source))?;
+
+    let Some(offset) = (0..bytes.len().max(regenerated.len()))
+        .find(|&index| bytes.get(index) != regenerated.get(index))
+    else {
+        return Ok(());
+    };
+
+    let (items, _) = match faithful {
+        true => layout_to_end(bytes, symbols, undocumented, origin),
+        false => layout(bytes, symbols, undocumented, origin),
+    };
+    // The item the offset is in, which is none past the decoded ones
+    let item = items
+        .iter()
+        .take_while(|&&(start, _)| start <= offset)
+        .last()
+        .filter(|&&(start, item)| match item {
+            Item::Instruction(instruction) => offset < start + instruction.length as usize,
+            Item::Data(length) => offset < start + length,
+        });
+    // Describes the item in either program, or the bytes at the offset if
+    // they are not one
+    let describe = |bytes: &[u8]| {
+        if offset >= bytes.len() {
+            return None;
+        }
+
+        match item {
+            Some(&(start, Item::Data(length))) => describe_data(bytes, start, length),
+            Some(&(start, Item::Instruction(_))) => describe_instruction(bytes, start),
+            None => None,
+        }
+        .or_else(|| describe_data(bytes, offset, 16))
+    };
+
+    let mismatch = Mismatch {
+        offset,
+        original: describe(bytes),
+        regenerated: describe(&regenerated),
+    };
+
+    Err(mismatch.into())
+}
+
+#[test]
+fn test_verify() {
+    let options = |faithful| WriterOptions {
+        verbose: false,
+        faithful,
+        undocumented: false,
+    };
+
+    // mov ax, 0x1234; mov bx, ax; ret
+    assert!(verify(&[0xb8, 0x34, 0x12, 0x89, 0xc3, 0xc3], options(false)).is_ok());
+
+    // The assembler picks the sign-extended form of add ax, 1
+    let bytes = [0x05, 0x01, 0x00, 0xc3];
+    let error = verify(&bytes, options(false)).unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "Reassembly differs at offset 0x0000: `add ax, 1` (05 01 00) became `add ax, word 0x0001` (83 c0 01)"
+    );
+    assert!(verify(&bytes, options(true)).is_ok());
+
+    // The byte that can not be decoded is left out
+    let error = verify(&[0x4b, 0xd6], options(false)).unwrap_err();
+
+    assert_eq!(
+        error.to_string(),
+        "Reassembly differs at offset 0x0001: data (d6) became the end of the file"
+    );
+
+    // je to its own second byte, whose label is defined after it
+    let bytes = [
+        0xb0, 0x73, 0xe5, 0x24, 0xd0, 0x09, 0x3c, 0x98, 0xaf, 0x36, 0x5a, 0x74, 0xff, 0x79, 0x67,
+        0x2a, 0x01, 0x99, 0xf7, 0xbd, 0xb1, 0xfe, 0x2f, 0xa6,
+    ];
+
+    assert!(verify(&bytes, options(true)).is_ok());
+
+    // mov ax, [0x0104]; mov bx, 0x1234, naming the byte in the middle of
+    // the second instruction at its load address
+    let symbols = SymbolMap::parse("0104 g_high").unwrap().with_origin(0x100);
+    let bytes = [0xa1, 0x04, 0x01, 0xbb, 0x34, 0x12];
+
+    assert!(verify_with_origin(&bytes, options(false), &symbols, 0x100).is_ok());
+}
@@ -1,5 +1,5 @@
 use std::collections::HashMap;
-use std::io::Write;
+use std::io::{self, Write};
 
 use crate::{
     instructions::common::{
@@ -12,19 +12,24 @@ use crate::{
 #[derive(Debug, Copy, Clone)]
 struct WrittenInstruction {
     start_instruction_index: usize,
-    start_file_index: usize,
     length: u8,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 struct Label {
-    number: usize,
+    name: String,
     pub inserted: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct WriterOptions {
     pub verbose: bool,
+    /// Whether to write instructions so that they reassemble to the same
+    /// bytes, even when the assembler would pick another encoding.
+    pub faithful: bool,
+    /// Whether to decode the opcodes the 8086 executes without them being
+    /// documented, and point them out in comments.
+    pub undocumented: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -34,21 +39,32 @@ pub struct WriterContext {
     segment: u8,
 }
 
+/// Bytes of output kept in memory before they are written to a sink.
+const SINK_BUFFER_SIZE: usize = 1 << 16;
+
 pub struct Writer {
     file_buffer: Vec<u8>,
     next_instruction_byte_index: usize,
     current_instruction_byte_index: usize,
-    instruction_buffer: Vec<WrittenInstruction>,
+    /// Byte offset of the first prefix of the line being written.
+    line_start_byte_index: Option<usize>,
     label_map: HashMap<usize, Label>,
+    header_map: HashMap<usize, Vec<String>>,
+    comment_map: HashMap<usize, String>,
+    variable_map: HashMap<usize, String>,
+    next_label_number: usize,
     current_instruction: Option<WrittenInstruction>,
     options: WriterOptions,
     context: WriterContext,
+    sink: Option<Box<dyn Write>>,
+    sink_error: Option<io::Error>,
+    /// Address the input is loaded at.
+    origin: usize,
 }
 
 impl Writer {
     pub fn new(options: WriterOptions) -> Self {
         let buffer = Vec::new();
-        let instruction_buffer = Vec::new();
         let mut label_map = HashMap::new();
 
         label_map.reserve(16);
@@ -57,8 +73,12 @@ impl Writer {
             file_buffer: buffer,
             next_instruction_byte_index: 0,
             current_instruction_byte_index: 0,
-            instruction_buffer,
+            line_start_byte_index: None,
             label_map,
+            header_map: HashMap::new(),
+            comment_map: HashMap::new(),
+            variable_map: HashMap::new(),
+            next_label_number: 0,
             current_instruction: None,
             options,
             context: WriterContext {
@@ -67,15 +87,252 @@ impl Writer {
                 // TODO(joni): Change default values to 0 across the board
                 segment: 0xff,
             },
+            sink: None,
+            sink_error: None,
+            origin: 0,
+        }
+    }
+
+    /// Writes completed lines to the sink as they are written instead of
+    /// keeping all of the output in memory. Labels for jumps back to lines
+    /// that have already been written are defined where the output is
+    /// written, like `loc_3 equ $-300`.
+    pub fn set_sink(&mut self, sink: Box<dyn Write>) -> &mut Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// Writes the rest of the output to the sink, returning the first error
+    /// writing to it.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.define_unwritten_labels();
+        self.flush_sink();
+
+        match (self.sink_error.take(), self.sink.as_mut()) {
+            (Some(error), _) => Err(error),
+            (None, Some(sink)) => sink.flush(),
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn flush_sink(&mut self) {
+        if let (Some(sink), None) = (self.sink.as_mut(), &self.sink_error) {
+            if let Err(error) = sink.write_all(&self.file_buffer) {
+                self.sink_error = Some(error);
+            }
+        }
+
+        self.file_buffer.clear();
+    }
+
+    /// Defines the labels that have not been written, like jump targets
+    /// outside the written instructions, relative to the end of the output.
+    pub fn define_unwritten_labels(&mut self) -> &mut Self {
+        self.define_passed_labels(usize::MAX);
+        self
+    }
+
+    /// Defines the labels before the given byte offset that have not been
+    /// written, relative to the end of the output, and forgets them.
+    fn define_passed_labels(&mut self, before: usize) {
+        let here = self.current_instruction_byte_index as isize;
+        let mut passed: Vec<(usize, String)> = self
+            .label_map
+            .iter()
+            .filter(|(&index, label)| index < before && !label.inserted)
+            .map(|(&index, label)| (index, label.name.clone()))
+            .collect();
+
+        passed.sort();
+
+        for (index, name) in passed {
+            writeln!(
+                self.file_buffer,
+                "{} equ ${:+}",
+                name,
+                index as isize - here
+            )
+            .unwrap();
+        }
+
+        self.label_map.retain(|&index, _| index >= before);
+    }
+
+    pub fn options(&self) -> &WriterOptions {
+        &self.options
+    }
+
+    /// Sets the byte offset of the next written instruction. Used when the
+    /// written instructions do not start at the beginning of the input.
+    pub fn set_position(&mut self, index: usize) -> &mut Self {
+        self.current_instruction_byte_index = index;
+        self.next_instruction_byte_index = index;
+
+        self
+    }
+
+    /// Gives the label at the given byte offset a name. The label is written
+    /// once an instruction starting at the offset is written.
+    pub fn add_label(&mut self, index: usize, name: &str) -> &mut Self {
+        self.label_map.insert(
+            index,
+            Label {
+                name: name.to_string(),
+                inserted: false,
+            },
+        );
+
+        self
+    }
+
+    /// Names the target of a jump, branch or call with the next `loc_N`,
+    /// unless it has a name already. Targets are added before the
+    /// instructions are written, so that labels can be written before the
+    /// jumps back to them.
+    pub fn add_jump_target(&mut self, index: usize) -> &mut Self {
+        self.jump_label(index);
+        self
+    }
+
+    fn jump_label(&mut self, index: usize) -> Label {
+        let next_label_number = &mut self.next_label_number;
+
+        self.label_map
+            .entry(index)
+            .or_insert_with(|| {
+                let number = *next_label_number;
+                *next_label_number += 1;
+                Label {
+                    name: format!("loc_{}", number),
+                    inserted: false,
+                }
+            })
+            .clone()
+    }
+
+    /// Sets the address the input is loaded at, which is added to the values
+    /// of labels written with `write_label_value`.
+    pub fn set_origin(&mut self, origin: usize) -> &mut Self {
+        self.origin = origin;
+        self
+    }
+
+    /// Writes the label at the given byte offset as an `equ` of its address on
+    /// its own line, for labels that are not at the start of a line.
+    pub fn write_label_value(&mut self, index: usize) -> &mut Self {
+        if let Some(label) = self
+            .label_map
+            .get_mut(&index)
+            .filter(|label| !label.inserted)
+        {
+            label.inserted = true;
+            writeln!(
+                self.file_buffer,
+                "{} equ {:#06x}",
+                label.name,
+                self.origin + index
+            )
+            .unwrap();
+        }
+
+        self
+    }
+
+    /// Adds a comment line to the header written before the label at the
+    /// given byte offset. Headers are separated from the previous instruction
+    /// by an empty line.
+    pub fn add_header_comment(&mut self, index: usize, comment: &str) -> &mut Self {
+        self.header_map
+            .entry(index)
+            .or_default()
+            .push(comment.to_string());
+
+        self
+    }
+
+    /// Adds a comment to the end of the line of the instruction at the given
+    /// byte offset.
+    pub fn add_comment(&mut self, index: usize, comment: &str) -> &mut Self {
+        self.comment_map.insert(index, comment.to_string());
+
+        self
+    }
+
+    /// Adds a comment to the line of the instruction at the given byte
+    /// offset, after the comment it already has.
+    pub fn append_comment(&mut self, index: usize, comment: &str) -> &mut Self {
+        self.comment_map
+            .entry(index)
+            .and_modify(|existing| *existing = format!("{}, {}", existing, comment))
+            .or_insert_with(|| comment.to_string());
+
+        self
+    }
+
+    /// Names a memory address. Direct address operands referring to it are
+    /// written with the name instead of the number.
+    pub fn add_variable(&mut self, address: usize, name: &str) -> &mut Self {
+        self.variable_map.insert(address, name.to_string());
+
+        self
+    }
+
+    fn write_header_and_label(&mut self, index: usize) {
+        if let Some(header) = self.header_map.remove(&index) {
+            self.write_byte(b'\n');
+
+            for comment in header {
+                self.write_comment(&comment);
+            }
+        }
+
+        // A label after a prefix is defined after the line instead
+        if self.line_start_byte_index.is_some() {
+            return;
+        }
+
+        let mut label_str = None;
+
+        if let Some(label) = self.label_map.get_mut(&index) {
+            if !label.inserted {
+                label_str = Some(format!("{}:\n", label.name));
+                label.inserted = true;
+            }
+        }
+
+        if let Some(label_str) = label_str {
+            self.write_str(&label_str);
+        }
+    }
+
+    /// Defines the labels inside the line that was just written relative to
+    /// its end, like `loc_2 equ $-1`.
+    fn write_inner_labels(&mut self, start: usize, end: usize) {
+        for index in start + 1..end {
+            let Some(label) = self
+                .label_map
+                .get_mut(&index)
+                .filter(|label| !label.inserted)
+            else {
+                continue;
+            };
+
+            label.inserted = true;
+            writeln!(self.file_buffer, "{} equ $-{}", label.name, end - index).unwrap();
         }
     }
 
     fn push_prefix_instruction(&mut self) {
-        self.instruction_buffer.push(WrittenInstruction {
-            start_instruction_index: self.current_instruction_byte_index,
-            start_file_index: self.file_buffer.len(),
-            length: 1,
-        });
+        let index = self.current_instruction_byte_index;
+
+        self.write_header_and_label(index);
+
+        // Comments belong to the line of the prefixed instruction
+        if let Some(comment) = self.comment_map.remove(&index) {
+            self.comment_map.insert(index + 1, comment);
+        }
+
+        self.line_start_byte_index.get_or_insert(index);
         self.current_instruction_byte_index += 1;
         self.next_instruction_byte_index += 1;
     }
@@ -121,18 +378,7 @@ impl Writer {
     }
 
     pub fn start_instruction(&mut self, instruction: &Instruction) -> &mut Self {
-        let mut label_str = None;
-
-        if let Some(label) = self.label_map.get_mut(&self.current_instruction_byte_index) {
-            if !label.inserted {
-                label_str = Some(format!("loc_{}:\n", label.number));
-                label.inserted = true;
-            }
-        }
-
-        if let Some(label_str) = label_str {
-            self.write_str(&label_str);
-        }
+        self.write_header_and_label(self.current_instruction_byte_index);
 
         if self.options.verbose {
             self.write_instruction_input(instruction);
@@ -150,12 +396,9 @@ impl Writer {
 
         let written_instruction = WrittenInstruction {
             start_instruction_index: self.current_instruction_byte_index,
-            start_file_index: self.file_buffer.len(),
             length: instruction.length,
         };
 
-        self.instruction_buffer.push(written_instruction);
-
         self.write_str(instruction.opcode.get_mnemonic())
             .write_byte(b' ');
 
@@ -243,7 +486,10 @@ impl Writer {
     fn effective_to_string(&self, instruction: &Instruction, effective: u8) -> String {
         let has_memory_mode = instruction.data_fields.mode == mode::MEMORY_MODE;
         if effective == effective::BP_OR_DIRECT_ADDRESS && has_memory_mode {
-            format!("[{}", instruction.disp)
+            match self.variable_map.get(&(instruction.disp as u16 as usize)) {
+                Some(name) => format!("[{}", name),
+                None => format!("[{}", instruction.disp),
+            }
         } else {
             EFFECTIVE_ADDRESS_STRINGS[effective as usize].to_string()
         }
@@ -293,68 +539,127 @@ impl Writer {
         self.write_str(&str)
     }
 
+    /// Writes the label of a jump target relative to the end of the
+    /// instruction, or the target relative to `$` if it is before the start
+    /// of the input. Labels for targets that were not added with
+    /// `add_jump_target` before the target was written are defined at the
+    /// end, see `define_unwritten_labels`.
     pub fn write_jump_displacement(&mut self, displacement: i16) -> &mut Self {
-        let next_inst_byte = self.next_instruction_byte_index;
-        let target_index = (next_inst_byte as isize) + (displacement as isize);
+        let target_index = (self.next_instruction_byte_index as isize) + (displacement as isize);
 
-        assert!(target_index >= 0);
+        // Targets before the start of the input have no label
+        let Ok(target_index) = usize::try_from(target_index) else {
+            let line_start = self
+                .line_start_byte_index
+                .unwrap_or(self.current_instruction_byte_index);
+            let relative = self.next_instruction_byte_index - line_start;
 
-        let target_index = target_index as usize;
-        let new_label_number = self.label_map.len();
+            return self.write_str(&format!("${:+}", relative as isize + displacement as isize));
+        };
 
-        let label = *self.label_map.entry(target_index).or_insert_with(|| Label {
-            number: new_label_number,
-            inserted: false,
-        });
+        let label = self.jump_label(target_index);
 
-        let label_str = format!("loc_{}", label.number);
+        self.write_str(&label.name)
+    }
 
-        self.write_str(&label_str);
+    fn start_data(&mut self, length: usize) {
+        self.write_header_and_label(self.current_instruction_byte_index);
 
-        if displacement >= 0 || label.inserted {
-            return self;
-        }
-
-        // Insert label to a previous instruction
+        let written_instruction = WrittenInstruction {
+            start_instruction_index: self.current_instruction_byte_index,
+            length: length as u8,
+        };
 
-        let mut current_instruction = self
-            .current_instruction
-            .expect("No instruction to go back from");
+        self.current_instruction = Some(written_instruction);
+        self.next_instruction_byte_index += length;
+        self.write(b"db ");
+    }
 
-        let abs_displacement = displacement.unsigned_abs() as usize;
-        let mut target_instruction = &mut current_instruction;
+    /// Writes bytes that are not decoded as `db` lines of up to 16 bytes.
+    pub fn write_data(&mut self, bytes: &[u8]) -> &mut Self {
+        for chunk in bytes.chunks(16) {
+            self.start_data(chunk.len());
 
-        for inst in self.instruction_buffer.iter_mut().rev() {
-            let byte_difference = next_inst_byte - inst.start_instruction_index;
-            if byte_difference == abs_displacement {
-                target_instruction = inst;
-                break;
+            for (i, byte) in chunk.iter().enumerate() {
+                if i > 0 {
+                    self.write_comma_separator();
+                }
+                self.write_str(&format!("{:#04x}", byte));
             }
+
+            self.end_line();
         }
 
-        let insert_index = target_instruction.start_file_index;
-        let str = format!("{}:\n", label_str);
-        let inserted_bytes = str.as_bytes();
+        self
+    }
 
-        self.file_buffer
-            .splice(insert_index..insert_index, inserted_bytes.iter().copied());
+    /// Writes a string as `db` lines of up to 64 bytes, quoting the printable
+    /// characters, e.g. `db 'Hello', 0x0d, 0x0a, '$'`.
+    pub fn write_string(&mut self, bytes: &[u8]) -> &mut Self {
+        for chunk in bytes.chunks(64) {
+            self.start_data(chunk.len());
 
-        target_instruction.start_file_index += inserted_bytes.len();
+            let mut quoted = false;
 
-        self.label_map.insert(
-            target_index,
-            Label {
-                inserted: true,
-                ..label
-            },
-        );
+            for (i, &byte) in chunk.iter().enumerate() {
+                let printable = (0x20..=0x7e).contains(&byte) && byte != b'\'';
+
+                if printable && quoted {
+                    self.write_byte(byte);
+                    continue;
+                }
+
+                if quoted {
+                    self.write_byte(b'\'');
+                    quoted = false;
+                }
+
+                if i > 0 {
+                    self.write_comma_separator();
+                }
+
+                if printable {
+                    self.write_byte(b'\'').write_byte(byte);
+                    quoted = true;
+                } else {
+                    self.write_str(&format!("{:#04x}", byte));
+                }
+            }
+
+            if quoted {
+                self.write_byte(b'\'');
+            }
+
+            self.end_line();
+        }
 
         self
     }
 
     pub fn end_line(&mut self) -> &mut Self {
         if let Some(current_instruction) = self.current_instruction.take() {
+            let index = current_instruction.start_instruction_index;
+
+            if let Some(comment) = self.comment_map.remove(&index) {
+                if self.file_buffer.last() != Some(&b' ') {
+                    self.file_buffer.push(b' ');
+                }
+                write!(self.file_buffer, "; {}", comment).unwrap();
+            }
+
             self.current_instruction_byte_index += current_instruction.length as usize;
+            self.file_buffer.push(b'\n');
+
+            let line_start = self.line_start_byte_index.take().unwrap_or(index);
+
+            self.write_inner_labels(line_start, self.current_instruction_byte_index);
+
+            if self.sink.is_some() && self.file_buffer.len() >= SINK_BUFFER_SIZE {
+                self.define_passed_labels(self.current_instruction_byte_index);
+                self.flush_sink();
+            }
+
+            return self;
         }
 
         self.file_buffer.push(b'\n');
@@ -362,6 +667,7 @@ impl Writer {
         self
     }
 
+    /// Returns the output that has not been written to a sink.
     pub fn as_slice(&self) -> &[u8] {
         self.file_buffer.as_slice()
     }
@@ -369,7 +675,11 @@ impl Writer {
 
 #[test]
 fn test_writer_labels_add_before() {
-    let mut writer = Writer::new(WriterOptions { verbose: false });
+    let mut writer = Writer::new(WriterOptions {
+        verbose: false,
+        faithful: false,
+        undocumented: false,
+    });
 
     let mov_instruction = Instruction::parse(&[0b1000_1001, 0b1101_1000]).unwrap();
     let add_instruction = Instruction::parse(&[0b0000_0001, 0b1101_1000]).unwrap();
@@ -378,6 +688,9 @@ fn test_writer_labels_add_before() {
     let rep_instruction = Instruction::parse(&[0b1111_0011]).unwrap();
     let cmps_instruction = Instruction::parse(&[0b1010_0110]).unwrap();
 
+    // The targets of the jumps back, which are known before writing
+    writer.add_jump_target(0).add_jump_target(10);
+
     add_instruction.write(&mut writer);
     sub_instruction.write(&mut writer);
 
@@ -440,7 +753,11 @@ je loc_1
 
 #[test]
 fn test_writer_labels_add_after() {
-    let mut writer = Writer::new(WriterOptions { verbose: false });
+    let mut writer = Writer::new(WriterOptions {
+        verbose: false,
+        faithful: false,
+        undocumented: false,
+    });
 
     let add_instruction = Instruction::parse(&[0b0000_0001, 0b1101_1000]).unwrap();
     let sub_instruction = Instruction::parse(&[0b0010_1001, 0b1101_1000]).unwrap();
@@ -0,0 +1,50 @@
+bits 16
+
+; Copies and compares a string, scans it, counts in a subroutine and sets
+; the flags with arithmetic before halting
+mov ax, cs
+mov ds, ax
+mov es, ax
+mov word [0x200], 0x6261
+mov word [0x202], 0x6463
+mov si, 0x200
+mov di, 0x204
+mov cx, 4
+cld
+rep movsb
+mov si, 0x200
+mov di, 0x204
+mov cx, 4
+repe cmpsb
+jne mismatch
+mov al, 0x61
+mov di, 0x200
+mov cx, 4
+repe scasb
+call count
+mov ax, 0x7fff
+add ax, 1
+jo overflow
+jmp done
+overflow:
+stc
+sbb bx, bx
+clc
+cmc
+adc bx, 0
+jc done
+mismatch:
+mov bx, 0xffff
+done:
+pushf
+pop bp
+std
+lodsb
+hlt
+count:
+xor dx, dx
+next:
+inc dx
+shl ax, 1
+loop next
+ret
@@ -0,0 +1,296 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn de8086(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_de8086"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stderr).to_string()
+}
+
+#[test]
+fn test_disasm_reads_stdin() {
+    // mov ax, 0x0001; jmp to itself
+    let output = de8086(
+        &["disasm", "-", "--org", "0x100"],
+        &[0xb8, 0x01, 0x00, 0xeb, 0xfe],
+    );
+
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("; stdin\n\nbits 16\norg 0x0100\n"));
+
+    let output = de8086(
+        &["-", "--format", "listing", "--start", "3", "--org", "0x100"],
+        &[0xb8, 0x01, 0x00, 0xeb, 0xfe],
+    );
+
+    assert_eq!(stdout(&output), "0103  eb fe             jmp 0x0103\n");
+
+    // Without --org the selection is where it is in a .com program, so
+    // that the string DS:DX points to is found
+    let output = de8086(
+        &["-", "--start", "3"],
+        b"\xb8\x01\x00\xb4\x09\xba\x0b\x01\xcd\x21\xc3Hi$",
+    );
+
+    assert!(stdout(&output).starts_with("; stdin\n\nbits 16\norg 0x0103\n"));
+    assert!(stdout(&output).ends_with("ret \ndb 'Hi$'\n"));
+}
+
+#[test]
+fn test_exit_codes() {
+    // mov ax, 0x0001 followed by a byte that is not an instruction
+    let output = de8086(&["-"], &[0xb8, 0x01, 0x00, 0xd6]);
+
+    assert_eq!(output.status.code(), Some(3));
+    assert!(stdout(&output).contains("mov ax, 0x0001"));
+    assert_eq!(
+        stderr(&output),
+        "Error: 1 byte from 0x0003 can not be decoded\n"
+    );
+
+    // Faithful mode writes the byte as data and goes on
+    let output = de8086(&["-", "--faithful"], &[0xb8, 0x01, 0x00, 0xd6, 0x90]);
+
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with("mov ax, 0x0001\ndb 0xd6\nnop \n"));
+
+    let output = de8086(&["disasm", "-", "--end", "4", "--length", "2"], &[]);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("--end and --length can not be used together"));
+
+    let output = de8086(&["info", "test/does_not_exist"], &[]);
+
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn test_disasm_graphs() {
+    // call 0x0004; ret; push bp; ret
+    let program = [0xe8, 0x01, 0x00, 0xc3, 0x55, 0xc3];
+    let directory = std::env::temp_dir().join(format!("de8086-cfg-{}", std::process::id()));
+    let symbols = directory.with_extension("sym");
+    let out = directory.with_extension("asm");
+
+    std::fs::write(&symbols, "0x0004 helper\n").unwrap();
+
+    let symbols = symbols.to_str().unwrap();
+    let output = de8086(&["-", "--call-graph", "--org", "0x100"], &program);
+
+    assert_eq!(
+        stdout(&output),
+        "start\n    calls: sub_0104\n    called by: -\nsub_0104\n    calls: -\n    called by: start\n"
+    );
+
+    let output = de8086(
+        &[
+            "-",
+            "--cfg",
+            directory.to_str().unwrap(),
+            "-o",
+            out.to_str().unwrap(),
+            "--symbols",
+            symbols,
+        ],
+        &program,
+    );
+    let dot = std::fs::read_to_string(directory.join("start.dot")).unwrap();
+    let helper = directory.join("helper.dot").exists();
+
+    std::fs::remove_dir_all(&directory).unwrap();
+    std::fs::remove_file(symbols).unwrap();
+
+    assert!(output.status.success());
+    assert!(dot.contains("call helper"));
+    assert!(helper);
+    assert!(!out.exists());
+}
+
+#[test]
+fn test_exec_and_info() {
+    // mov cx, 3; xor ax, ax; add ax, cx; loop; hlt
+    let program = [0xb9, 0x03, 0x00, 0x31, 0xc0, 0x01, 0xc8, 0xe2, 0xfc, 0xf4];
+    let output = de8086(&["exec", "-"], &program);
+
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("halted after 9 instructions\nax=0006 "));
+
+    let output = de8086(&["info", "-", "--format", "json"], &program);
+
+    assert!(stdout(&output).contains("\"instructions\": 5"));
+}
+
+#[test]
+fn test_exec_dos() {
+    // Print the command tail and the key read, then exit with code 7
+    let program = de8086::assembler::assemble(
+        "\
+bits 16
+org 0x100
+mov ah, 0x40
+mov bx, 1
+mov cl, [0x0080]
+mov ch, 0
+mov dx, 0x0081
+int 0x21
+mov ah, 0x08
+int 0x21
+mov dl, al
+mov ah, 0x02
+int 0x21
+mov ax, 0x4c07
+int 0x21
+",
+    )
+    .unwrap();
+    let path = std::env::temp_dir().join(format!("de8086-cli-{}.com", std::process::id()));
+
+    std::fs::write(&path, program).unwrap();
+
+    let output = de8086(
+        &["exec", "--dos", "--args", " hello", path.to_str().unwrap()],
+        b"x",
+    );
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(stdout(&output), " hellox");
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn test_exec_debug_console() {
+    // mov al, 'A'; out 0xe9, al; hlt
+    let output = de8086(&["exec", "-"], &[0xb0, 0x41, 0xe6, 0xe9, 0xf4]);
+
+    assert!(output.status.success());
+    assert!(stdout(&output).starts_with("Ahalted after 3 instructions\n"));
+}
+
+#[test]
+fn test_exec_dump() {
+    // mov word [0x0200], 0x1234; hlt
+    let path = std::env::temp_dir().join(format!("de8086-dump-{}.bin", std::process::id()));
+    let output = de8086(
+        &["exec", "-", "--dump", path.to_str().unwrap()],
+        &[0xc7, 0x06, 0x00, 0x02, 0x34, 0x12, 0xf4],
+    );
+    let memory = std::fs::read(&path).unwrap();
+
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(memory.len(), 1 << 20);
+    assert_eq!(&memory[0x10200..0x10202], &[0x34, 0x12]);
+}
+
+#[test]
+fn test_exec_screen() {
+    // Print "Hi" with the BIOS and an "!" to B800:0004, then halt
+    let program = de8086::assembler::assemble(
+        "\
+bits 16
+org 0
+mov ax, 0x0e48
+int 0x10
+mov al, 0x69
+int 0x10
+mov ax, 0xb800
+mov es, ax
+mov word [es:4], 0x0721
+hlt
+",
+    )
+    .unwrap();
+    let output = de8086(&["exec", "-", "--screen", "text"], &program);
+
+    assert!(output.status.success());
+    assert!(stdout(&output).ends_with("flags=f002\nHi!\n"));
+}
+
+#[test]
+fn test_exec_coverage() {
+    // mov cx, 3; xor ax, ax; add ax, cx; loop; hlt; nop
+    let program = [
+        0xb9, 0x03, 0x00, 0x31, 0xc0, 0x01, 0xc8, 0xe2, 0xfc, 0xf4, 0x90,
+    ];
+    let directory = std::env::temp_dir();
+    let asm = directory.join(format!("de8086-coverage-{}.asm", std::process::id()));
+    let lcov = directory.join(format!("de8086-coverage-{}.info", std::process::id()));
+    let output = de8086(
+        &[
+            "exec",
+            "-",
+            "--org",
+            "0x100",
+            "--coverage",
+            asm.to_str().unwrap(),
+            "--lcov",
+            lcov.to_str().unwrap(),
+        ],
+        &program,
+    );
+    let asm_text = std::fs::read_to_string(&asm).unwrap();
+    let lcov_text = std::fs::read_to_string(&lcov).unwrap();
+
+    std::fs::remove_file(&asm).unwrap();
+    std::fs::remove_file(&lcov).unwrap();
+
+    assert!(output.status.success());
+    assert!(asm_text.contains("add ax, cx ; hits: 3\nloop loc_0 ; hits: 3\n"));
+    assert!(asm_text.ends_with(
+        "nop ; hits: 0\n\
+         ; coverage: 5 of 6 instructions executed\n\
+         ; never executed: 0x010a (1 byte)\n"
+    ));
+    assert!(lcov_text.contains("DA:8,1\nDA:9,1\nDA:11,3\nDA:12,3\nDA:13,1\nDA:14,0\n"));
+    assert!(lcov_text.ends_with("LF:6\nLH:5\nend_of_record\n"));
+}
+
+#[test]
+fn test_symbols_with_origin() {
+    // call 0x010a; mov ax, [0x0110]; mov cl, [0x0080]; ret
+    let program = [
+        0xe8, 0x07, 0x00, 0xa1, 0x10, 0x01, 0x8a, 0x0e, 0x80, 0x00, 0xc3,
+    ];
+    let symbols = std::env::temp_dir().join(format!("de8086-org-{}.sym", std::process::id()));
+
+    std::fs::write(&symbols, "010a func\n0110 g_var\n0080 cmd_tail\n").unwrap();
+
+    let symbols = symbols.to_str().unwrap();
+    let output = de8086(&["-", "--org", "0x100", "--symbols", symbols], &program);
+    let verified = de8086(
+        &["verify", "-", "--org", "0x100", "--symbols", symbols],
+        &program,
+    );
+
+    std::fs::remove_file(symbols).unwrap();
+
+    let source = stdout(&output);
+
+    assert!(source.contains("call func\n"));
+    assert!(source.contains("\nfunc:\n"));
+    assert!(source.contains("mov ax, [g_var]\n"));
+    assert!(source.contains("g_var equ 0x0110\n"));
+    assert!(source.contains("cmd_tail equ 0x0080\n"));
+    assert!(source.contains("mov cl, [cmd_tail]\n"));
+    assert!(verified.status.success(), "{}", stderr(&verified));
+}
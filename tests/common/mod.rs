@@ -0,0 +1,32 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Test files that do not reassemble to the same bytes unless written in
+/// faithful mode.
+#[allow(dead_code)]
+pub const KNOWN_MISMATCHES: &[&str] = &["evil_file"];
+
+/// Returns the files in `test` with the extension, or the binaries without
+/// one for `None`, sorted by name.
+pub fn test_files(extension: Option<&str>) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = fs::read_dir("test")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().and_then(|extension| extension.to_str()) == extension)
+        .collect();
+
+    paths.sort();
+    paths
+}
+
+/// Returns the name of a test file without its extension.
+pub fn name(path: &Path) -> &str {
+    path.file_stem().unwrap().to_str().unwrap()
+}
+
+/// Returns the names of the test files, to check which ones a test covers.
+pub fn names(paths: &[PathBuf]) -> Vec<&str> {
+    paths.iter().map(|path| name(path)).collect()
+}
@@ -0,0 +1,34 @@
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{names, test_files};
+
+/// Replays the debugger scripts in `test/<file>.debug` against the file and
+/// compares the output with `test/<file>.session`. Regenerate one with
+/// `de8086 debug --script test/<file>.debug test/<file> > test/<file>.session`.
+#[test]
+fn test_sessions_match() {
+    let files = test_files(Some("debug"));
+
+    assert_eq!(names(&files), ["mov_file"]);
+
+    for path in &files {
+        let expected = fs::read_to_string(path.with_extension("session")).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_de8086"))
+            .arg("debug")
+            .arg("--script")
+            .arg(path)
+            .arg(path.with_extension(""))
+            .output()
+            .unwrap();
+
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            expected,
+            "{} differs",
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+#[test]
+fn test_shell_reads_commands_from_stdin() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_de8086"))
+        .args(["shell", "test/kitchen_sink"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"label 2 second\nu 0 2\nd second 4\nfoo\nquit\nu 0\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        format!(
+            "0000  89 d9             mov cx, bx\n\
+             second:\n\
+             0002  88 e5             mov ch, ah\n\
+             0002  88 e5 89 da{}....\n\
+             Error: unknown command foo\n",
+            " ".repeat(37)
+        )
+    );
+}
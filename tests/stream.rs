@@ -0,0 +1,55 @@
+mod common;
+
+use std::{cell::RefCell, fs, io, rc::Rc};
+
+use common::{name, names, test_files, KNOWN_MISMATCHES};
+use de8086::{assembler::assemble, run_streaming, writer::WriterOptions};
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for SharedBuffer {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_stream_test_files() {
+    let files = test_files(None);
+
+    assert_eq!(
+        names(&files),
+        ["evil_file", "flow_file", "kitchen_sink", "mov_file"]
+    );
+
+    for path in &files {
+        let name = name(path);
+        let bytes = fs::read(path).unwrap();
+        let output = SharedBuffer::default();
+
+        run_streaming(
+            name,
+            bytes.as_slice(),
+            output.clone(),
+            WriterOptions {
+                verbose: false,
+                faithful: false,
+                undocumented: false,
+            },
+        )
+        .unwrap();
+
+        // Labels written out of memory are still defined
+        let source = String::from_utf8(output.0.take()).unwrap();
+        let assembled = assemble(&source).unwrap_or_else(|error| panic!("{}: {}", name, error));
+
+        if !KNOWN_MISMATCHES.contains(&name) {
+            assert!(assembled == bytes, "{} does not reassemble", name);
+        }
+    }
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{names, test_files};
+
+/// Traces the test files that have a `.trace` file next to them and compares
+/// the output with it. Regenerate one with
+/// `de8086 exec --trace test/<file> > test/<file>.trace`.
+#[test]
+fn test_traces_match() {
+    let files = test_files(Some("trace"));
+
+    assert_eq!(names(&files), ["flow_file", "mov_file"]);
+
+    for path in &files {
+        let expected = fs::read_to_string(path).unwrap();
+        let output = Command::new(env!("CARGO_BIN_EXE_de8086"))
+            .arg("exec")
+            .arg("--trace")
+            .arg(path.with_extension(""))
+            .output()
+            .unwrap();
+        let actual = String::from_utf8(output.stdout).unwrap();
+
+        if let Some((line, (expected, actual))) = expected
+            .lines()
+            .zip(actual.lines())
+            .enumerate()
+            .find(|(_, (expected, actual))| expected != actual)
+        {
+            panic!(
+                "{} differs at line {}:\n  expected: {}\n  actual:   {}",
+                path.display(),
+                line + 1,
+                expected,
+                actual
+            );
+        }
+
+        assert_eq!(expected.lines().count(), actual.lines().count());
+    }
+}
@@ -0,0 +1,38 @@
+mod common;
+
+use std::fs;
+
+use common::{name, names, test_files, KNOWN_MISMATCHES};
+use de8086::{verify::verify, writer::WriterOptions};
+
+#[test]
+fn test_verify_test_files() {
+    let files = test_files(None);
+
+    assert_eq!(
+        names(&files),
+        ["evil_file", "flow_file", "kitchen_sink", "mov_file"]
+    );
+
+    for path in &files {
+        let name = name(path);
+        let bytes = fs::read(path).unwrap();
+        let options = |faithful| WriterOptions {
+            verbose: false,
+            faithful,
+            undocumented: false,
+        };
+        let result = verify(&bytes, options(false));
+
+        if KNOWN_MISMATCHES.contains(&name) {
+            assert!(result.is_err(), "{} reassembles now", name);
+        } else if let Err(error) = result {
+            panic!("{}: {}", name, error);
+        }
+
+        // Faithful mode keeps the bytes of every file
+        if let Err(error) = verify(&bytes, options(true)) {
+            panic!("{} in faithful mode: {}", name, error);
+        }
+    }
+}